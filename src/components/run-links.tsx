@@ -3,6 +3,7 @@ import { FaYoutube, FaTwitch, FaLink } from "react-icons/fa";
 import {
   GetGamePage_game_gameCategories_leaderboard_run,
   GetGamePage_game_gameCategories_progression_run,
+  RunVideoKind,
 } from "~/components/schema";
 
 const RunLinks = ({
@@ -14,13 +15,14 @@ const RunLinks = ({
 }) => (
   <>
     {run.videos.map((video, index) => (
-      <a href={video} key={index}>
-        {/^https?:\/\/(www\.)?(youtu\.be|youtube.com)\//i.test(video) ? (
-          <FaYoutube title={video} />
-        ) : /^https?:\/\/(www\.)?(twitch\.tv)\//i.test(video) ? (
-          <FaTwitch title={video} />
+      <a href={video.url} key={index}>
+        {video.kind === RunVideoKind.YOUTUBE ? (
+          <FaYoutube title={video.url} />
+        ) : video.kind === RunVideoKind.TWITCH_VOD ||
+          video.kind === RunVideoKind.TWITCH_CLIP ? (
+          <FaTwitch title={video.url} />
         ) : (
-          <FaLink title={video} />
+          <FaLink title={video.url} />
         )}
       </a>
     ))}
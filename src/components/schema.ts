@@ -40,6 +40,26 @@ export interface GetGamePagePreviewVariables {
 // GraphQL query operation: GetGamePage
 // ====================================================
 
+export interface GetGamePage_game_gameCategories_leaderboard_run_videos {
+  __typename: "RunVideo";
+  /**
+   * the site the video is on, if it's one we recognize
+   */
+  kind: RunVideoKind;
+  /**
+   * canonical URL of the video
+   */
+  url: string;
+  /**
+   * the video's ID on its site, or the slug of a Twitch clip
+   */
+  videoId: string | null;
+  /**
+   * offset into the video that the run starts at, in seconds
+   */
+  start: number | null;
+}
+
 export interface GetGamePage_game_gameCategories_leaderboard_run_category {
   __typename: "Category";
   /**
@@ -110,7 +130,7 @@ export interface GetGamePage_game_gameCategories_leaderboard_run {
    */
   srcId: string;
   timeMs: number;
-  videos: string[];
+  videos: GetGamePage_game_gameCategories_leaderboard_run_videos[];
   category: GetGamePage_game_gameCategories_leaderboard_run_category;
   level: GetGamePage_game_gameCategories_leaderboard_run_level | null;
   date: number | null;
@@ -125,6 +145,26 @@ export interface GetGamePage_game_gameCategories_leaderboard {
   run: GetGamePage_game_gameCategories_leaderboard_run;
 }
 
+export interface GetGamePage_game_gameCategories_progression_run_videos {
+  __typename: "RunVideo";
+  /**
+   * the site the video is on, if it's one we recognize
+   */
+  kind: RunVideoKind;
+  /**
+   * canonical URL of the video
+   */
+  url: string;
+  /**
+   * the video's ID on its site, or the slug of a Twitch clip
+   */
+  videoId: string | null;
+  /**
+   * offset into the video that the run starts at, in seconds
+   */
+  start: number | null;
+}
+
 export interface GetGamePage_game_gameCategories_progression_run_category {
   __typename: "Category";
   /**
@@ -195,13 +235,33 @@ export interface GetGamePage_game_gameCategories_progression_run {
    */
   srcId: string;
   timeMs: number;
-  videos: string[];
+  videos: GetGamePage_game_gameCategories_progression_run_videos[];
   category: GetGamePage_game_gameCategories_progression_run_category;
   level: GetGamePage_game_gameCategories_progression_run_level | null;
   date: number | null;
   players: GetGamePage_game_gameCategories_progression_run_players[];
 }
 
+export interface GetGamePage_game_gameCategories_progression_leaderboardRun_run_videos {
+  __typename: "RunVideo";
+  /**
+   * the site the video is on, if it's one we recognize
+   */
+  kind: RunVideoKind;
+  /**
+   * canonical URL of the video
+   */
+  url: string;
+  /**
+   * the video's ID on its site, or the slug of a Twitch clip
+   */
+  videoId: string | null;
+  /**
+   * offset into the video that the run starts at, in seconds
+   */
+  start: number | null;
+}
+
 export interface GetGamePage_game_gameCategories_progression_leaderboardRun_run_category {
   __typename: "Category";
   /**
@@ -272,7 +332,7 @@ export interface GetGamePage_game_gameCategories_progression_leaderboardRun_run
    */
   srcId: string;
   timeMs: number;
-  videos: string[];
+  videos: GetGamePage_game_gameCategories_progression_leaderboardRun_run_videos[];
   category: GetGamePage_game_gameCategories_progression_leaderboardRun_run_category;
   level: GetGamePage_game_gameCategories_progression_leaderboardRun_run_level | null;
   date: number | null;
@@ -322,6 +382,26 @@ export interface GetGamePage_game_gameCategories {
   progression: GetGamePage_game_gameCategories_progression[];
 }
 
+export interface GetGamePage_game_levelCategories_leaderboard_run_videos {
+  __typename: "RunVideo";
+  /**
+   * the site the video is on, if it's one we recognize
+   */
+  kind: RunVideoKind;
+  /**
+   * canonical URL of the video
+   */
+  url: string;
+  /**
+   * the video's ID on its site, or the slug of a Twitch clip
+   */
+  videoId: string | null;
+  /**
+   * offset into the video that the run starts at, in seconds
+   */
+  start: number | null;
+}
+
 export interface GetGamePage_game_levelCategories_leaderboard_run_category {
   __typename: "Category";
   /**
@@ -392,7 +472,7 @@ export interface GetGamePage_game_levelCategories_leaderboard_run {
    */
   srcId: string;
   timeMs: number;
-  videos: string[];
+  videos: GetGamePage_game_levelCategories_leaderboard_run_videos[];
   category: GetGamePage_game_levelCategories_leaderboard_run_category;
   level: GetGamePage_game_levelCategories_leaderboard_run_level | null;
   date: number | null;
@@ -407,6 +487,26 @@ export interface GetGamePage_game_levelCategories_leaderboard {
   run: GetGamePage_game_levelCategories_leaderboard_run;
 }
 
+export interface GetGamePage_game_levelCategories_progression_run_videos {
+  __typename: "RunVideo";
+  /**
+   * the site the video is on, if it's one we recognize
+   */
+  kind: RunVideoKind;
+  /**
+   * canonical URL of the video
+   */
+  url: string;
+  /**
+   * the video's ID on its site, or the slug of a Twitch clip
+   */
+  videoId: string | null;
+  /**
+   * offset into the video that the run starts at, in seconds
+   */
+  start: number | null;
+}
+
 export interface GetGamePage_game_levelCategories_progression_run_category {
   __typename: "Category";
   /**
@@ -477,13 +577,33 @@ export interface GetGamePage_game_levelCategories_progression_run {
    */
   srcId: string;
   timeMs: number;
-  videos: string[];
+  videos: GetGamePage_game_levelCategories_progression_run_videos[];
   category: GetGamePage_game_levelCategories_progression_run_category;
   level: GetGamePage_game_levelCategories_progression_run_level | null;
   date: number | null;
   players: GetGamePage_game_levelCategories_progression_run_players[];
 }
 
+export interface GetGamePage_game_levelCategories_progression_leaderboardRun_run_videos {
+  __typename: "RunVideo";
+  /**
+   * the site the video is on, if it's one we recognize
+   */
+  kind: RunVideoKind;
+  /**
+   * canonical URL of the video
+   */
+  url: string;
+  /**
+   * the video's ID on its site, or the slug of a Twitch clip
+   */
+  videoId: string | null;
+  /**
+   * offset into the video that the run starts at, in seconds
+   */
+  start: number | null;
+}
+
 export interface GetGamePage_game_levelCategories_progression_leaderboardRun_run_category {
   __typename: "Category";
   /**
@@ -554,7 +674,7 @@ export interface GetGamePage_game_levelCategories_progression_leaderboardRun_run
    */
   srcId: string;
   timeMs: number;
-  videos: string[];
+  videos: GetGamePage_game_levelCategories_progression_leaderboardRun_run_videos[];
   category: GetGamePage_game_levelCategories_progression_leaderboardRun_run_category;
   level: GetGamePage_game_levelCategories_progression_leaderboardRun_run_level | null;
   date: number | null;
@@ -596,6 +716,26 @@ export interface GetGamePage_game_levelCategories_levels_level {
   name: string;
 }
 
+export interface GetGamePage_game_levelCategories_levels_leaderboard_run_videos {
+  __typename: "RunVideo";
+  /**
+   * the site the video is on, if it's one we recognize
+   */
+  kind: RunVideoKind;
+  /**
+   * canonical URL of the video
+   */
+  url: string;
+  /**
+   * the video's ID on its site, or the slug of a Twitch clip
+   */
+  videoId: string | null;
+  /**
+   * offset into the video that the run starts at, in seconds
+   */
+  start: number | null;
+}
+
 export interface GetGamePage_game_levelCategories_levels_leaderboard_run_category {
   __typename: "Category";
   /**
@@ -666,7 +806,7 @@ export interface GetGamePage_game_levelCategories_levels_leaderboard_run {
    */
   srcId: string;
   timeMs: number;
-  videos: string[];
+  videos: GetGamePage_game_levelCategories_levels_leaderboard_run_videos[];
   category: GetGamePage_game_levelCategories_levels_leaderboard_run_category;
   level: GetGamePage_game_levelCategories_levels_leaderboard_run_level | null;
   date: number | null;
@@ -681,6 +821,26 @@ export interface GetGamePage_game_levelCategories_levels_leaderboard {
   run: GetGamePage_game_levelCategories_levels_leaderboard_run;
 }
 
+export interface GetGamePage_game_levelCategories_levels_progression_run_videos {
+  __typename: "RunVideo";
+  /**
+   * the site the video is on, if it's one we recognize
+   */
+  kind: RunVideoKind;
+  /**
+   * canonical URL of the video
+   */
+  url: string;
+  /**
+   * the video's ID on its site, or the slug of a Twitch clip
+   */
+  videoId: string | null;
+  /**
+   * offset into the video that the run starts at, in seconds
+   */
+  start: number | null;
+}
+
 export interface GetGamePage_game_levelCategories_levels_progression_run_category {
   __typename: "Category";
   /**
@@ -751,13 +911,33 @@ export interface GetGamePage_game_levelCategories_levels_progression_run {
    */
   srcId: string;
   timeMs: number;
-  videos: string[];
+  videos: GetGamePage_game_levelCategories_levels_progression_run_videos[];
   category: GetGamePage_game_levelCategories_levels_progression_run_category;
   level: GetGamePage_game_levelCategories_levels_progression_run_level | null;
   date: number | null;
   players: GetGamePage_game_levelCategories_levels_progression_run_players[];
 }
 
+export interface GetGamePage_game_levelCategories_levels_progression_leaderboardRun_run_videos {
+  __typename: "RunVideo";
+  /**
+   * the site the video is on, if it's one we recognize
+   */
+  kind: RunVideoKind;
+  /**
+   * canonical URL of the video
+   */
+  url: string;
+  /**
+   * the video's ID on its site, or the slug of a Twitch clip
+   */
+  videoId: string | null;
+  /**
+   * offset into the video that the run starts at, in seconds
+   */
+  start: number | null;
+}
+
 export interface GetGamePage_game_levelCategories_levels_progression_leaderboardRun_run_category {
   __typename: "Category";
   /**
@@ -828,7 +1008,7 @@ export interface GetGamePage_game_levelCategories_levels_progression_leaderboard
    */
   srcId: string;
   timeMs: number;
-  videos: string[];
+  videos: GetGamePage_game_levelCategories_levels_progression_leaderboardRun_run_videos[];
   category: GetGamePage_game_levelCategories_levels_progression_leaderboardRun_run_category;
   level: GetGamePage_game_levelCategories_levels_progression_leaderboardRun_run_level | null;
   date: number | null;
@@ -967,6 +1147,26 @@ export interface GetRunPage_game {
   name: string;
 }
 
+export interface GetRunPage_run_videos {
+  __typename: "RunVideo";
+  /**
+   * the site the video is on, if it's one we recognize
+   */
+  kind: RunVideoKind;
+  /**
+   * canonical URL of the video
+   */
+  url: string;
+  /**
+   * the video's ID on its site, or the slug of a Twitch clip
+   */
+  videoId: string | null;
+  /**
+   * offset into the video that the run starts at, in seconds
+   */
+  start: number | null;
+}
+
 export interface GetRunPage_run_category {
   __typename: "Category";
   /**
@@ -1037,7 +1237,7 @@ export interface GetRunPage_run {
    */
   srcId: string;
   timeMs: number;
-  videos: string[];
+  videos: GetRunPage_run_videos[];
   category: GetRunPage_run_category;
   level: GetRunPage_run_level | null;
   date: number | null;
@@ -1269,6 +1469,26 @@ export interface GetNodePageVariables {
 // GraphQL fragment: GameRun
 // ====================================================
 
+export interface GameRun_videos {
+  __typename: "RunVideo";
+  /**
+   * the site the video is on, if it's one we recognize
+   */
+  kind: RunVideoKind;
+  /**
+   * canonical URL of the video
+   */
+  url: string;
+  /**
+   * the video's ID on its site, or the slug of a Twitch clip
+   */
+  videoId: string | null;
+  /**
+   * offset into the video that the run starts at, in seconds
+   */
+  start: number | null;
+}
+
 export interface GameRun_category {
   __typename: "Category";
   /**
@@ -1339,7 +1559,7 @@ export interface GameRun {
    */
   srcId: string;
   timeMs: number;
-  videos: string[];
+  videos: GameRun_videos[];
   category: GameRun_category;
   level: GameRun_level | null;
   date: number | null;
@@ -1355,6 +1575,26 @@ export interface GameRun {
 // GraphQL fragment: GameLeaderboardRun
 // ====================================================
 
+export interface GameLeaderboardRun_run_videos {
+  __typename: "RunVideo";
+  /**
+   * the site the video is on, if it's one we recognize
+   */
+  kind: RunVideoKind;
+  /**
+   * canonical URL of the video
+   */
+  url: string;
+  /**
+   * the video's ID on its site, or the slug of a Twitch clip
+   */
+  videoId: string | null;
+  /**
+   * offset into the video that the run starts at, in seconds
+   */
+  start: number | null;
+}
+
 export interface GameLeaderboardRun_run_category {
   __typename: "Category";
   /**
@@ -1425,7 +1665,7 @@ export interface GameLeaderboardRun_run {
    */
   srcId: string;
   timeMs: number;
-  videos: string[];
+  videos: GameLeaderboardRun_run_videos[];
   category: GameLeaderboardRun_run_category;
   level: GameLeaderboardRun_run_level | null;
   date: number | null;
@@ -1449,6 +1689,18 @@ export interface GameLeaderboardRun {
 // START Enums and Input Objects
 //==============================================================
 
+/**
+ * The site a run's video is on.
+ */
+export enum RunVideoKind {
+  BILIBILI = "BILIBILI",
+  LINK = "LINK",
+  NICO_NICO = "NICO_NICO",
+  TWITCH_CLIP = "TWITCH_CLIP",
+  TWITCH_VOD = "TWITCH_VOD",
+  YOUTUBE = "YOUTUBE",
+}
+
 /**
  * A timing method that can be used to time a run.
  */
@@ -1,5 +1,6 @@
 //! https://speedrun.com API
 pub mod cli;
+pub mod drift;
 pub mod normalize;
 pub mod types;
 
@@ -0,0 +1,531 @@
+//! Compare two copies of our data record-by-record.
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    io::{prelude::*, BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use log::info;
+use serde::Serialize;
+use serde_json::{Deserializer as JsonDeserializer, Value as JsonValue};
+use tempfile::TempDir;
+
+use crate::cli::{
+    download::{
+        snapshots::Snapshots,
+        store::{self, STORE_MANIFEST},
+    },
+    sort,
+};
+
+#[derive(argh::FromArgs, PartialEq, Debug)]
+/// Compares two directories of downloaded (`data/api`) or imported (`data/imported`) data,
+/// or two snapshots of the downloaded data, listing the records that were added, removed,
+/// or modified between them, matched by table and ID.
+#[argh(subcommand, name = "diff")]
+pub struct Args {
+    /// the directory with the older data, or the ID of a snapshot of it (or any unique
+    /// prefix of that).
+    #[argh(positional)]
+    a: String,
+    /// the directory with the newer data, or the ID of a snapshot of it.
+    #[argh(positional)]
+    b: String,
+    /// output format: "text" (the default) or "jsonl", with one change per line.
+    #[argh(option, default = "Format::Text", from_str_fn(parse_format))]
+    format: Format,
+    /// only compare this table (such as "runs"). May be repeated.
+    #[argh(option)]
+    table: Vec<String>,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Format {
+    Text,
+    Jsonl,
+}
+
+fn parse_format(value: &str) -> Result<Format, String> {
+    match value {
+        "text" => Ok(Format::Text),
+        "jsonl" => Ok(Format::Jsonl),
+        _ => Err(format!(
+            "unknown format {:?}, expected text or jsonl",
+            value
+        )),
+    }
+}
+
+/// How a single record differs between two copies of a table.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(tag = "change", rename_all = "lowercase")]
+pub enum RecordDiff {
+    Added {
+        table: String,
+        id: String,
+        record: JsonValue,
+    },
+    Removed {
+        table: String,
+        id: String,
+        record: JsonValue,
+    },
+    Modified {
+        table: String,
+        id: String,
+        fields: Vec<FieldChange>,
+    },
+}
+
+/// A value that differs between two copies of a record.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FieldChange {
+    /// The dotted path of object keys leading to the value.
+    pub path: String,
+    /// The old value, or none if it was added.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<JsonValue>,
+    /// The new value, or none if it was removed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<JsonValue>,
+}
+
+impl std::fmt::Display for RecordDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RecordDiff::Added { table, id, .. } => write!(f, "+ {} {}", table, id),
+            RecordDiff::Removed { table, id, .. } => write!(f, "- {} {}", table, id),
+            RecordDiff::Modified { table, id, fields } => {
+                write!(f, "~ {} {}", table, id)?;
+                for field in fields {
+                    write!(f, "\n    {}: ", field.path)?;
+                    match &field.old {
+                        Some(old) => write!(f, "{}", old)?,
+                        None => write!(f, "(none)")?,
+                    }
+                    write!(f, " -> ")?;
+                    match &field.new {
+                        Some(new) => write!(f, "{}", new)?,
+                        None => write!(f, "(none)")?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The values that differ between two records. Objects are compared key by
+/// key, and anything else (including arrays) as a whole.
+pub fn diff_fields(a: &JsonValue, b: &JsonValue) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    diff_values("", Some(a), Some(b), &mut changes);
+    changes
+}
+
+fn diff_values(
+    path: &str,
+    a: Option<&JsonValue>,
+    b: Option<&JsonValue>,
+    changes: &mut Vec<FieldChange>,
+) {
+    match (a, b) {
+        (Some(JsonValue::Object(a)), Some(JsonValue::Object(b))) => {
+            let keys: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+            for key in keys {
+                let path = if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                diff_values(&path, a.get(key), b.get(key), changes);
+            }
+        }
+        (a, b) if a != b => changes.push(FieldChange {
+            path: path.to_string(),
+            old: a.cloned(),
+            new: b.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+/// A record's ID. Downloaded records have string IDs, and imported ones numbers.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RecordId {
+    Number(u64),
+    String(String),
+}
+
+impl std::fmt::Display for RecordId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RecordId::Number(id) => write!(f, "{}", id),
+            RecordId::String(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+pub fn record_id(record: &JsonValue) -> Result<RecordId, Box<dyn std::error::Error>> {
+    match record.get("id") {
+        Some(JsonValue::String(id)) => Ok(RecordId::String(id.clone())),
+        Some(JsonValue::Number(id)) => match id.as_u64() {
+            Some(id) => Ok(RecordId::Number(id)),
+            None => Err(format!("record with an invalid id: {}", id).into()),
+        },
+        _ => Err("record without an id".into()),
+    }
+}
+
+type Records = Box<dyn Iterator<Item = Result<JsonValue, Box<dyn std::error::Error>>>>;
+
+/// The differences between two copies of a table, which must each be sorted by
+/// ID. Only one record from each is held in memory at a time.
+pub fn diff_table(table: &str, a: Records, b: Records) -> TableDiff {
+    TableDiff {
+        table: table.to_string(),
+        a: Side::new(a),
+        b: Side::new(b),
+    }
+}
+
+/// The differences from [diff_table], in order of ID.
+pub struct TableDiff {
+    table: String,
+    a: Side,
+    b: Side,
+}
+
+impl TableDiff {
+    fn next_diff(&mut self) -> Result<Option<RecordDiff>, Box<dyn std::error::Error>> {
+        loop {
+            let a = self.a.peek(&self.table)?.cloned();
+            let b = self.b.peek(&self.table)?.cloned();
+            let order = match (a, b) {
+                (None, None) => return Ok(None),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (Some(a), Some(b)) => a.cmp(&b),
+            };
+            let table = self.table.clone();
+            match order {
+                std::cmp::Ordering::Less => {
+                    let (id, record) = self.a.take();
+                    return Ok(Some(RecordDiff::Removed {
+                        table,
+                        id: id.to_string(),
+                        record,
+                    }));
+                }
+                std::cmp::Ordering::Greater => {
+                    let (id, record) = self.b.take();
+                    return Ok(Some(RecordDiff::Added {
+                        table,
+                        id: id.to_string(),
+                        record,
+                    }));
+                }
+                std::cmp::Ordering::Equal => {
+                    let (id, old) = self.a.take();
+                    let (_, new) = self.b.take();
+                    if old != new {
+                        return Ok(Some(RecordDiff::Modified {
+                            table,
+                            id: id.to_string(),
+                            fields: diff_fields(&old, &new),
+                        }));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for TableDiff {
+    type Item = Result<RecordDiff, Box<dyn std::error::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_diff().transpose()
+    }
+}
+
+/// One copy of a table, read a record ahead.
+struct Side {
+    records: Records,
+    head: Option<(RecordId, JsonValue)>,
+    /// The ID of the last record we took, to check that they're in order.
+    last: Option<RecordId>,
+}
+
+impl Side {
+    fn new(records: Records) -> Self {
+        Side {
+            records,
+            head: None,
+            last: None,
+        }
+    }
+
+    fn peek(
+        &mut self,
+        table: &str,
+    ) -> Result<Option<&RecordId>, Box<dyn std::error::Error>> {
+        if self.head.is_none() {
+            if let Some(record) = self.records.next() {
+                let record = record?;
+                let id = record_id(&record)?;
+                if matches!(&self.last, Some(last) if id <= *last) {
+                    return Err(
+                        format!("{} records aren't sorted by id at {}", table, id).into()
+                    );
+                }
+                self.head = Some((id, record));
+            }
+        }
+        Ok(self.head.as_ref().map(|(id, _)| id))
+    }
+
+    fn take(&mut self) -> (RecordId, JsonValue) {
+        let (id, record) = self.head.take().expect("a record to have been peeked");
+        self.last = Some(id.clone());
+        (id, record)
+    }
+}
+
+/// The names of the tables in a directory of downloaded or imported data:
+/// downloaded resources' stores and archived `.jsonl.gz` files, and imported
+/// `.jsonl` files.
+pub fn tables(dir: &Path) -> Result<BTreeSet<String>, Box<dyn std::error::Error>> {
+    let mut tables = BTreeSet::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.path().join(STORE_MANIFEST).exists() {
+            tables.insert(name);
+        } else if let Some(table) = name
+            .strip_suffix(".jsonl.gz")
+            .or_else(|| name.strip_suffix(".jsonl"))
+        {
+            tables.insert(table.to_string());
+        }
+    }
+    Ok(tables)
+}
+
+/// Reads a table of downloaded or imported records from `dir`, in order of
+/// ID, or nothing if it doesn't have the table. Stores are already sorted, and
+/// anything else is sorted on disk, in `dir`.
+pub fn read_table(dir: &Path, table: &str) -> Result<Records, Box<dyn std::error::Error>> {
+    if dir.join(table).join(STORE_MANIFEST).exists() {
+        return Ok(Box::new(store::read(dir, table)?));
+    }
+
+    let imported = dir.join(format!("{}.jsonl", table));
+    let records: Records = if store::legacy_path(dir, table).exists() {
+        Box::new(store::read(dir, table)?)
+    } else if imported.exists() {
+        let file = BufReader::new(File::open(&imported)?);
+        Box::new(
+            JsonDeserializer::from_reader(file)
+                .into_iter::<JsonValue>()
+                .map(|record| record.map_err(Into::into)),
+        )
+    } else {
+        return Ok(Box::new(std::iter::empty()));
+    };
+
+    Ok(Box::new(sort::sort_by_key(
+        records,
+        dir,
+        sort::CHUNK_RECORDS,
+        |record: &JsonValue| record_id(record).ok(),
+    )?))
+}
+
+/// A directory of data to compare: either one that was given, or a snapshot
+/// checked out into a temporary directory, which is removed when this is
+/// dropped.
+struct Source {
+    path: PathBuf,
+    _checkout: Option<TempDir>,
+}
+
+impl Source {
+    /// Uses `name` as a directory if there is one, or as the ID of a snapshot
+    /// of `data/api` otherwise.
+    fn open(name: &str) -> Result<Source, Box<dyn std::error::Error>> {
+        let path = PathBuf::from(name);
+        if path.is_dir() {
+            return Ok(Source {
+                path,
+                _checkout: None,
+            });
+        }
+
+        let dir = TempDir::new_in("data")?;
+        let manifest = Snapshots::new("data/api").checkout(name, dir.path())?;
+        info!("Comparing snapshot {}.", manifest.id);
+        Ok(Source {
+            path: dir.path().to_path_buf(),
+            _checkout: Some(dir),
+        })
+    }
+}
+
+pub fn main(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let (a, b) = (Source::open(&args.a)?, Source::open(&args.b)?);
+    let (a, b) = (a.path.as_path(), b.path.as_path());
+    let (a_tables, b_tables) = (tables(a)?, tables(b)?);
+    let names: BTreeSet<&String> = a_tables
+        .iter()
+        .chain(b_tables.iter())
+        .filter(|name| args.table.is_empty() || args.table.contains(name))
+        .collect();
+
+    let stdout = std::io::stdout();
+    let mut output = BufWriter::new(stdout.lock());
+    for name in names {
+        let diffs = diff_table(name, read_table(a, name)?, read_table(b, name)?);
+
+        let (mut added, mut removed, mut modified) = (0, 0, 0);
+        for diff in diffs {
+            let diff = diff?;
+            match diff {
+                RecordDiff::Added { .. } => added += 1,
+                RecordDiff::Removed { .. } => removed += 1,
+                RecordDiff::Modified { .. } => modified += 1,
+            }
+            match args.format {
+                Format::Text => writeln!(output, "{}", diff)?,
+                Format::Jsonl => {
+                    serde_json::to_writer(&mut output, &diff)?;
+                    output.write_all(b"\n")?;
+                }
+            }
+        }
+        info!(
+            "{}: {} added, {} removed, {} modified.",
+            name, added, removed, modified
+        );
+    }
+    output.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_fields() {
+        let old = json!({
+            "id": "abc",
+            "times": {"primary_t": 12.5, "realtime_t": 12.5},
+            "players": [{"rel": "user", "id": "x"}],
+            "comment": "gg",
+        });
+        let new = json!({
+            "id": "abc",
+            "times": {"primary_t": 11.0, "realtime_t": 12.5},
+            "players": [{"rel": "user", "id": "y"}],
+            "video": "https://example.com",
+        });
+
+        assert_eq!(
+            diff_fields(&old, &new),
+            vec![
+                FieldChange {
+                    path: "comment".to_string(),
+                    old: Some(json!("gg")),
+                    new: None,
+                },
+                FieldChange {
+                    path: "players".to_string(),
+                    old: Some(json!([{"rel": "user", "id": "x"}])),
+                    new: Some(json!([{"rel": "user", "id": "y"}])),
+                },
+                FieldChange {
+                    path: "times.primary_t".to_string(),
+                    old: Some(json!(12.5)),
+                    new: Some(json!(11.0)),
+                },
+                FieldChange {
+                    path: "video".to_string(),
+                    old: None,
+                    new: Some(json!("https://example.com")),
+                },
+            ]
+        );
+        assert!(diff_fields(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_diff_table() {
+        let table =
+            |records: Vec<JsonValue>| -> Records { Box::new(records.into_iter().map(Ok)) };
+        let old = table(vec![
+            json!({"id": 1, "name": "a"}),
+            json!({"id": 2, "name": "b"}),
+            json!({"id": 3, "name": "c"}),
+        ]);
+        let new = table(vec![
+            json!({"id": 1, "name": "a"}),
+            json!({"id": 3, "name": "C"}),
+            json!({"id": 4, "name": "d"}),
+        ]);
+
+        let diffs: Vec<RecordDiff> =
+            diff_table("games", old, new).map(Result::unwrap).collect();
+        assert_eq!(diffs.len(), 3);
+        assert_eq!(diffs[0].to_string(), "- games 2");
+        assert_eq!(diffs[1].to_string(), "~ games 3\n    name: \"c\" -> \"C\"");
+        assert_eq!(diffs[2].to_string(), "+ games 4");
+        assert_eq!(
+            serde_json::to_value(&diffs[1]).unwrap(),
+            json!({
+                "change": "modified",
+                "table": "games",
+                "id": "3",
+                "fields": [{"path": "name", "old": "c", "new": "C"}],
+            })
+        );
+    }
+
+    #[test]
+    fn test_read_table_sorts() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("games.jsonl"),
+            "{\"id\": 10}\n{\"id\": 9}\n{\"id\": 100}\n",
+        )
+        .unwrap();
+
+        let ids: Vec<RecordId> = read_table(dir.path(), "games")
+            .unwrap()
+            .map(|record| record_id(&record.unwrap()).unwrap())
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                RecordId::Number(9),
+                RecordId::Number(10),
+                RecordId::Number(100)
+            ]
+        );
+        assert_eq!(read_table(dir.path(), "runs").unwrap().count(), 0);
+
+        let unsorted = diff_table(
+            "games",
+            Box::new(
+                vec![json!({"id": "b"}), json!({"id": "a"})]
+                    .into_iter()
+                    .map(Ok),
+            ),
+            Box::new(std::iter::empty()),
+        );
+        assert!(unsorted.collect::<Result<Vec<_>, _>>().is_err());
+    }
+}
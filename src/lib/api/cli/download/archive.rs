@@ -0,0 +1,190 @@
+//! A local archive of the API's raw responses, so that a crawl can be replayed
+//! exactly as it happened, without the network.
+//!
+//! Each response is kept in a gzipped JSON file named by the SHA-1 hash of its
+//! URL, relative to the API root, and its sequence number among the responses
+//! to that URL, under a directory for the first byte of that hash. The archive
+//! is append-only: a URL that's fetched again, like a retried request or a
+//! page we went back for, gets a new entry after its earlier ones, and
+//! replaying hands them out in the order they were recorded.
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder};
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+
+/// Whether and how a [super::Spider] uses a [ResponseArchive].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArchiveMode {
+    /// Fetch from the API without keeping the responses.
+    Off,
+    /// Fetch from the API, keeping every response in the archive at this path.
+    Record(PathBuf),
+    /// Answer every request from the archive at this path, without the API.
+    Replay(PathBuf),
+}
+
+/// A response as we received it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ArchivedResponse {
+    /// The full URL we requested.
+    pub url: String,
+    /// When we received the response.
+    pub fetched: DateTime<Utc>,
+    /// The HTTP status of the response.
+    pub status: u16,
+    /// The response body, unparsed.
+    pub body: String,
+}
+
+/// The responses archived in a directory.
+#[derive(Debug)]
+pub struct ResponseArchive {
+    dir: PathBuf,
+    base_url: String,
+    /// The sequence number of the next response we'll record, or replay, for
+    /// each URL's hash.
+    sequences: Mutex<HashMap<String, u64>>,
+}
+
+impl ResponseArchive {
+    /// The archive in `dir` of responses from the API at `base_url`. URLs are
+    /// archived relative to `base_url`, so an archive can be replayed from a
+    /// different one.
+    pub fn new(dir: impl Into<PathBuf>, base_url: &str) -> Self {
+        ResponseArchive {
+            dir: dir.into(),
+            base_url: base_url.to_string(),
+            sequences: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn hash(&self, url: &str) -> String {
+        let key = url.strip_prefix(&self.base_url).unwrap_or(url);
+        sha1::Sha1::from(key).digest().to_string()
+    }
+
+    fn path(&self, hash: &str, sequence: u64) -> PathBuf {
+        self.dir
+            .join(&hash[..2])
+            .join(format!("{}-{:06}.json.gz", hash, sequence))
+    }
+
+    /// How many responses we have archived for the URL with this hash.
+    fn len(&self, hash: &str) -> u64 {
+        let mut sequence = 0;
+        while self.path(hash, sequence).exists() {
+            sequence += 1;
+        }
+        sequence
+    }
+
+    /// Keeps `response`, after any earlier responses to the same URL.
+    pub fn insert(
+        &self,
+        response: &ArchivedResponse,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let hash = self.hash(&response.url);
+        let sequence = {
+            let mut sequences = self.sequences.lock().unwrap();
+            let sequence = match sequences.get(&hash) {
+                Some(sequence) => *sequence,
+                None => self.len(&hash),
+            };
+            sequences.insert(hash.clone(), sequence + 1);
+            sequence
+        };
+        let path = self.path(&hash, sequence);
+        let dir = path.parent().unwrap_or(&self.dir);
+        fs::create_dir_all(dir)?;
+
+        let mut file = NamedTempFile::new_in(dir)?;
+        {
+            let buffer = BufWriter::new(&mut file);
+            let mut compressor = GzEncoder::new(buffer, flate2::Compression::default());
+            serde_json::to_writer(&mut compressor, response)?;
+            compressor.finish()?;
+        }
+        file.persist_noclobber(path)?;
+
+        Ok(())
+    }
+
+    /// The next response to `url` that we haven't replayed, in the order they
+    /// were archived. Once we've replayed all of them, we keep replaying the
+    /// last one.
+    pub fn replay(
+        &self,
+        url: &str,
+    ) -> Result<Option<ArchivedResponse>, Box<dyn std::error::Error>> {
+        let hash = self.hash(url);
+        let sequence = {
+            let mut sequences = self.sequences.lock().unwrap();
+            let next = sequences.entry(hash.clone()).or_insert(0);
+            *next += 1;
+            *next - 1
+        };
+        let mut path = self.path(&hash, sequence);
+        if !path.exists() {
+            match self.len(&hash) {
+                0 => return Ok(None),
+                len => path = self.path(&hash, len - 1),
+            }
+        }
+        let file = File::open(path)?;
+        let response = serde_json::from_reader(GzDecoder::new(BufReader::new(file)))?;
+        Ok(Some(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_response_archive() {
+        let dir = TempDir::new().unwrap();
+        let recorded = ResponseArchive::new(dir.path(), "https://example.com/api/v1/");
+        let response = |status: u16, body: &str| ArchivedResponse {
+            url: "https://example.com/api/v1/runs?offset=0".to_string(),
+            fetched: Utc::now(),
+            status,
+            body: body.to_string(),
+        };
+
+        let unavailable = response(503, "");
+        let latest = response(200, r#"{"data": []}"#);
+        recorded.insert(&unavailable).unwrap();
+        recorded.insert(&latest).unwrap();
+
+        // the same requests to another server find the same responses, in order
+        let replayed = ResponseArchive::new(dir.path(), "http://127.0.0.1:1234/");
+        let url = "http://127.0.0.1:1234/runs?offset=0";
+        assert_eq!(replayed.replay(url).unwrap(), Some(unavailable));
+        assert_eq!(replayed.replay(url).unwrap(), Some(latest.clone()));
+        assert_eq!(replayed.replay(url).unwrap(), Some(latest.clone()));
+        assert_eq!(replayed.replay("runs?offset=200").unwrap(), None);
+
+        // recording again appends to what's there
+        let recorded = ResponseArchive::new(dir.path(), "https://example.com/api/v1/");
+        let later = response(200, r#"{"data": [{"id": "a"}]}"#);
+        recorded.insert(&later).unwrap();
+        let replayed = ResponseArchive::new(dir.path(), "https://example.com/api/v1/");
+        let responses: Vec<_> = (0..3)
+            .map(|_| replayed.replay(&later.url).unwrap().unwrap().body)
+            .collect();
+        assert_eq!(
+            responses,
+            vec!["", r#"{"data": []}"#, r#"{"data": [{"id": "a"}]}"#]
+        );
+    }
+}
@@ -0,0 +1,452 @@
+//! How `download` talks to the API: many requests at once, within a shared
+//! budget of requests per second.
+//!
+//! `reqwest`'s blocking client is what we have, so each request attempt runs
+//! on a thread of its own, and we await its result. That keeps the executor
+//! free to drive the other requests, and to sleep out rate limits and retry
+//! delays without holding a thread.
+//!
+//! A fetcher can also keep every response in a [ResponseArchive], or answer
+//! requests from one instead of the API.
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use futures::{
+    channel::oneshot,
+    stream::{self, StreamExt},
+};
+use log::{debug, error, info};
+use serde_json::Value as JsonValue;
+
+use super::{
+    archive::{ArchiveMode, ArchivedResponse, ResponseArchive},
+    retry::{parse_retry_after, Failure, RetryError, RetryPolicy, RetryStats},
+    state::SyncDirection,
+    Config, Resource, PAGE_SIZE,
+};
+
+/// A page of items from a resource listing.
+pub struct Page {
+    pub items: Vec<JsonValue>,
+    /// Whether the listing continues past this page.
+    pub has_next: bool,
+}
+
+/// Spaces out requests so that, between every task sharing it, we don't
+/// make more than a given number per second.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// A limiter allowing `requests_per_second`, or any number if that's not
+    /// a positive number.
+    pub fn new(requests_per_second: f64) -> Self {
+        let interval = if requests_per_second > 0.0 && requests_per_second.is_finite() {
+            Duration::from_secs_f64(1.0 / requests_per_second)
+        } else {
+            Duration::from_secs(0)
+        };
+        RateLimiter {
+            interval,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits for our turn to make a request.
+    pub async fn acquire(&self) {
+        let now = Instant::now();
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = (*next).max(now);
+            *next = slot + self.interval;
+            slot
+        };
+        let wait = slot.saturating_duration_since(now);
+        if wait > Duration::from_secs(0) {
+            async_std::task::sleep(wait).await;
+        }
+    }
+
+    /// Holds back everyone's requests for at least `delay`, for when the
+    /// server tells us we're going too fast.
+    pub fn defer(&self, delay: Duration) {
+        let mut next = self.next.lock().unwrap();
+        *next = (*next).max(Instant::now() + delay);
+    }
+}
+
+/// Makes requests for a [super::Spider], retrying and rate-limiting them.
+pub struct Fetcher {
+    client: reqwest::Client,
+    base_url: String,
+    retry: RetryPolicy,
+    concurrency: usize,
+    budget: RateLimiter,
+    stats: Arc<Mutex<BTreeMap<String, RetryStats>>>,
+    archive: Option<Arc<ResponseArchive>>,
+    /// Whether we answer requests from `archive` instead of the API.
+    replay: bool,
+}
+
+impl Fetcher {
+    /// A fetcher as configured by `config`, which records request counts
+    /// into `stats`.
+    pub fn new(
+        config: &Config,
+        stats: Arc<Mutex<BTreeMap<String, RetryStats>>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let archive = |dir| Some(Arc::new(ResponseArchive::new(dir, &config.base_url)));
+        let (archive, replay) = match &config.archive {
+            ArchiveMode::Off => (None, false),
+            ArchiveMode::Record(dir) => {
+                info!("Archiving responses in {}.", dir.display());
+                (archive(dir), false)
+            }
+            ArchiveMode::Replay(dir) => {
+                info!("Replaying responses from {}.", dir.display());
+                (archive(dir), true)
+            }
+        };
+
+        // There's no need to go easy on the archive.
+        let requests_per_second = if replay {
+            0.0
+        } else {
+            config.requests_per_second
+        };
+
+        Ok(Fetcher {
+            client: client()?,
+            base_url: config.base_url.clone(),
+            retry: config.retry.clone(),
+            concurrency: config.concurrency.max(1),
+            budget: RateLimiter::new(requests_per_second),
+            stats,
+            archive,
+            replay,
+        })
+    }
+
+    /// Fetches a single page of `resource`.
+    pub fn page(
+        &self,
+        resource: &Resource,
+        filters: &[(&str, &str)],
+        direction: SyncDirection,
+        offset: usize,
+    ) -> Result<Page, RetryError> {
+        async_std::task::block_on(self.fetch_page(resource, filters, direction, offset))
+    }
+
+    /// Fetches the consecutive pages of `resource` starting at `offset`, as
+    /// many at once as our concurrency allows.
+    ///
+    /// Every page is requested at a multiple of [PAGE_SIZE] from `offset`, so
+    /// if the API gives us a short page, the pages after it didn't start where
+    /// it ended. We drop them, along with anything after the end of the
+    /// listing or the first failure, so every result is the continuation of
+    /// the one before it.
+    pub fn window(
+        &self,
+        resource: &Resource,
+        filters: &[(&str, &str)],
+        direction: SyncDirection,
+        offset: usize,
+    ) -> Vec<Result<Page, RetryError>> {
+        let offsets = (0..self.concurrency).map(|i| offset + i * PAGE_SIZE);
+        let pages: Vec<_> = async_std::task::block_on(
+            stream::iter(offsets)
+                .map(|offset| self.fetch_page(resource, filters, direction, offset))
+                .buffered(self.concurrency)
+                .collect(),
+        );
+
+        let mut window = Vec::new();
+        for page in pages {
+            let last = match &page {
+                Ok(page) => page.items.len() < PAGE_SIZE || !page.has_next,
+                Err(_) => true,
+            };
+            window.push(page);
+            if last {
+                break;
+            }
+        }
+        window
+    }
+
+    /// Fetches records of `resource` by ID, as many at once as our
+    /// concurrency allows, with `None` for any that don't exist.
+    pub fn records(
+        &self,
+        resource: &Resource,
+        ids: &[String],
+    ) -> Vec<Result<Option<JsonValue>, RetryError>> {
+        async_std::task::block_on(
+            stream::iter(ids)
+                .map(|id| self.fetch_record(resource, id))
+                .buffered(self.concurrency)
+                .collect(),
+        )
+    }
+
+    async fn fetch_page(
+        &self,
+        resource: &Resource,
+        filters: &[(&str, &str)],
+        direction: SyncDirection,
+        offset: usize,
+    ) -> Result<Page, RetryError> {
+        let mut url = format!(
+            "{}{}?direction={}&max={}&orderby={}&embed={}&offset={}",
+            self.base_url,
+            resource.id,
+            direction.as_str(),
+            PAGE_SIZE,
+            resource.order,
+            resource.embed,
+            offset
+        );
+        for (key, value) in filters {
+            url.push_str(&format!("&{}={}", key, value));
+        }
+
+        self.with_retries(resource, url, |response| parse_page(response?))
+            .await
+    }
+
+    async fn fetch_record(
+        &self,
+        resource: &Resource,
+        id: &str,
+    ) -> Result<Option<JsonValue>, RetryError> {
+        let url = format!(
+            "{}{}/{}?embed={}",
+            self.base_url, resource.id, id, resource.embed
+        );
+
+        self.with_retries(resource, url, |response| match response {
+            Ok(response) => match response.get("data") {
+                Some(item) if item.is_object() => Ok(Some(item.clone())),
+                _ => Err(Failure::InvalidResponse("missing data object".to_string())),
+            },
+            Err(Failure::Permanent(404)) => Ok(None),
+            Err(failure) => Err(failure),
+        })
+        .await
+    }
+
+    /// Requests `url` and interprets the response with `parse` until that
+    /// succeeds, or until our [RetryPolicy] says to give up. When replaying,
+    /// we retry without waiting, getting the archived responses in the order
+    /// they were recorded, so we give up exactly where the recording did.
+    async fn with_retries<T: Send + 'static>(
+        &self,
+        resource: &Resource,
+        url: String,
+        parse: fn(Result<JsonValue, Failure>) -> Result<T, Failure>,
+    ) -> Result<T, RetryError> {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            self.budget.acquire().await;
+
+            let result = match &self.archive {
+                Some(archive) if self.replay => replay_json(archive, &url),
+                _ => {
+                    let client = self.client.clone();
+                    let archive = self.archive.clone();
+                    let request_url = url.clone();
+                    unblock(move || {
+                        try_fetch_json(&client, &request_url, archive.as_deref())
+                    })
+                    .await
+                }
+            };
+            let result = parse(result);
+
+            let delay = {
+                let mut stats = self.stats.lock().unwrap();
+                let stats = stats.entry(resource.id.to_string()).or_default();
+                stats.requests += 1;
+
+                let failure = match result {
+                    Ok(result) => return Ok(result),
+                    Err(failure) => failure,
+                };
+
+                error!(
+                    "{} for {} (attempt {} of {})",
+                    failure, url, attempts, self.retry.max_attempts
+                );
+                stats.record_failure(&failure);
+
+                if failure.is_permanent() || attempts >= self.retry.max_attempts {
+                    stats.abandoned += 1;
+                    return Err(RetryError {
+                        url,
+                        attempts,
+                        failure,
+                    });
+                }
+
+                let delay = match failure {
+                    _ if self.replay => Duration::from_secs(0),
+                    Failure::RateLimited(_, Some(retry_after)) => retry_after,
+                    _ => self.retry.delay(attempts),
+                };
+                if let Failure::RateLimited(..) = failure {
+                    self.budget.defer(delay);
+                }
+                stats.retries += 1;
+                stats.waited += delay;
+                delay
+            };
+
+            async_std::task::sleep(delay).await;
+        }
+    }
+}
+
+/// Runs a blocking function on a thread of its own, so that waiting for it
+/// doesn't hold up anything else.
+async fn unblock<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (sender, receiver) = oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(f());
+    });
+    receiver.await.expect("request thread should not panic")
+}
+
+fn client() -> Result<reqwest::Client, Box<dyn std::error::Error>> {
+    let mut headers = reqwest::header::HeaderMap::new();
+
+    let user_agent = format!(
+        "{}/{}",
+        option_env!("CARGO_PKG_NAME").unwrap_or("unknown"),
+        option_env!("CARGO_PKG_VERSION").unwrap_or("unknown")
+    );
+
+    debug!("user agent: {}", user_agent);
+
+    headers.insert(
+        reqwest::header::USER_AGENT,
+        reqwest::header::HeaderValue::from_str(&user_agent)?,
+    );
+
+    Ok(reqwest::Client::builder()
+        .default_headers(headers)
+        .build()?)
+}
+
+/// Fetches a JSON response, without retrying, keeping it in `archive` if
+/// we're given one.
+fn try_fetch_json(
+    client: &reqwest::Client,
+    url: &str,
+    archive: Option<&ResponseArchive>,
+) -> Result<JsonValue, Failure> {
+    let mut response = client
+        .get(url)
+        .send()
+        .map_err(|error| Failure::Network(error.to_string()))?;
+
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok().and_then(parse_retry_after));
+    let body = response
+        .text()
+        .map_err(|error| Failure::Network(error.to_string()))?;
+
+    if let Some(archive) = archive {
+        let archived = ArchivedResponse {
+            url: url.to_string(),
+            fetched: Utc::now(),
+            status: status.as_u16(),
+            body,
+        };
+        if let Err(error) = archive.insert(&archived) {
+            error!("Failed to archive response for {}: {}", url, error);
+        }
+        return parse_json(archived.status, retry_after, &archived.body);
+    }
+
+    parse_json(status.as_u16(), retry_after, &body)
+}
+
+/// Answers a request with the response archived for it.
+fn replay_json(archive: &ResponseArchive, url: &str) -> Result<JsonValue, Failure> {
+    match archive.replay(url) {
+        Ok(Some(response)) => parse_json(response.status, None, &response.body),
+        Ok(None) => Err(Failure::Unarchived),
+        Err(error) => Err(Failure::InvalidResponse(error.to_string())),
+    }
+}
+
+/// Interprets a response with the given status and body as JSON.
+fn parse_json(
+    status: u16,
+    retry_after: Option<Duration>,
+    body: &str,
+) -> Result<JsonValue, Failure> {
+    if !(200..300).contains(&status) {
+        return Err(Failure::from_status(status, retry_after));
+    }
+
+    serde_json::from_str(body).map_err(|error| Failure::InvalidResponse(error.to_string()))
+}
+
+/// Interprets a response as a single page of a resource listing.
+fn parse_page(response: JsonValue) -> Result<Page, Failure> {
+    let items = response
+        .get("data")
+        .and_then(JsonValue::as_array)
+        .ok_or_else(|| Failure::InvalidResponse("missing data array".to_string()))?;
+
+    // The API sometimes returns short pages before the end of a listing, so
+    // we trust its pagination links over the page size if it gives us any.
+    let has_next = match response.get("pagination") {
+        Some(pagination) => pagination["links"]
+            .as_array()
+            .map(|links| links.iter().any(|link| link["rel"] == "next"))
+            .unwrap_or(false),
+        None => items.len() >= PAGE_SIZE,
+    };
+
+    Ok(Page {
+        items: items.clone(),
+        has_next,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let budget = RateLimiter::new(20.0);
+        let start = Instant::now();
+        async_std::task::block_on(async {
+            for _ in 0..5 {
+                budget.acquire().await;
+            }
+        });
+        // the first request goes immediately, and each after waits 50ms
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        budget.defer(Duration::from_millis(100));
+        let start = Instant::now();
+        async_std::task::block_on(budget.acquire());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,1083 @@
+#![allow(clippy::useless_attribute, clippy::useless_vec)]
+
+use flate2::read::GzDecoder;
+
+use chrono::Utc;
+use log::{error, info, warn};
+use serde_json::{Deserializer as JsonDeserializer, Value as JsonValue};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs::{self, File},
+    io::{prelude::*, BufReader, BufWriter, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+pub mod archive;
+mod fetch;
+pub mod retry;
+pub mod snapshots;
+pub mod state;
+pub mod store;
+pub mod tombstones;
+use archive::ArchiveMode;
+use fetch::Fetcher;
+use retry::{RetryPolicy, RetryStats};
+use snapshots::{Manifest, Snapshots};
+use state::{ResourceSyncState, SyncCursor, SyncDirection, SyncState, SYNC_STATE_FILE};
+use store::{Change, Store};
+use tombstones::{Tombstones, TOMBSTONES_FILE};
+
+use crate::cli::sort;
+
+/// The speedrun.com API we mirror unless told otherwise.
+pub const DEFAULT_BASE_URL: &str = "https://www.speedrun.com/api/v1/";
+
+/// The maximum page size the API allows, which we always request.
+const PAGE_SIZE: usize = 200;
+
+/// How many pages we fetch between checkpoints of our progress.
+const CHECKPOINT_INTERVAL: usize = 32;
+
+/// How many times we'll go back for records that were added while we were
+/// paging through a resource, before leaving them for the next sync.
+const MAX_ROUNDS: usize = 4;
+
+#[derive(argh::FromArgs, PartialEq, Debug)]
+/// Fetches/updates a local mirror of speedrun.com API content. This just stores the JSON
+/// representation of each item as-is, it doesn't make any assumptions about their structure
+/// beyond the existence of  a string "id" value. Records are kept in sorted segments on disk,
+/// so only a bounded number of them are held in memory at a time.
+#[argh(subcommand, name = "download")]
+pub struct Args {
+    /// the base URL of the speedrun.com API to download from.
+    #[argh(option, default = "DEFAULT_BASE_URL.to_string()")]
+    base_url: String,
+    /// sweep the full listing of every resource to find records that have been changed or
+    /// deleted upstream, instead of only fetching new ones. Deleted records are kept, but
+    /// marked with tombstones so that `import` can exclude them.
+    #[argh(switch)]
+    reconcile: bool,
+    /// only download the game with this abbreviation, its runs, and any users they
+    /// reference that we don't have yet, merging them into the existing data. May be
+    /// repeated.
+    #[argh(option)]
+    game: Vec<String>,
+    /// how many requests to make at once.
+    #[argh(option, default = "Config::default().concurrency")]
+    concurrency: usize,
+    /// the most requests to make per second, across all concurrent requests. speedrun.com
+    /// asks for no more than 100 per minute.
+    #[argh(option, default = "Config::default().requests_per_second")]
+    requests_per_second: f64,
+    /// keep every response from the API in an archive in this directory, so that the
+    /// download can be replayed later with `--replay-responses`.
+    #[argh(option)]
+    record_responses: Option<PathBuf>,
+    /// answer every request from the archive of responses in this directory, instead of
+    /// the API. Replaying into a copy of the data from before the recorded download (such
+    /// as a restored snapshot) reproduces it exactly.
+    #[argh(option)]
+    replay_responses: Option<PathBuf>,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct Resource {
+    id: &'static str,
+    order: &'static str,
+    embed: &'static str,
+    sync: SyncMethod,
+}
+
+/// How we keep a resource up to date.
+#[derive(PartialEq, Eq, Hash)]
+enum SyncMethod {
+    /// Paging through the listing newest-first until we reach records we
+    /// already have, then past the oldest ones we have.
+    Incremental,
+    /// Fetching the full listing every time, for small resources that the API
+    /// can't order by creation.
+    Full,
+    /// Collecting the records embedded in each record of another resource,
+    /// for resources the API doesn't list on their own. These are embedded
+    /// under a field with the same name as the resource.
+    Embedded(&'static str),
+}
+
+const RESOURCES: [Resource; 7] = [
+    Resource {
+        id: "games",
+        order: "created",
+        embed: "levels,categories,variables,gametypes,platforms,regions,genres,engines,developers,publishers",
+        sync: SyncMethod::Incremental,
+    },
+    Resource {
+        id: "users",
+        order: "signup",
+        embed: "",
+        sync: SyncMethod::Incremental,
+    },
+    Resource {
+        id: "runs",
+        order: "submitted",
+        embed: "",
+        sync: SyncMethod::Incremental,
+    },
+    Resource {
+        id: "platforms",
+        order: "name",
+        embed: "",
+        sync: SyncMethod::Full,
+    },
+    Resource {
+        id: "regions",
+        order: "name",
+        embed: "",
+        sync: SyncMethod::Full,
+    },
+    Resource {
+        id: "series",
+        order: "created",
+        embed: "",
+        sync: SyncMethod::Incremental,
+    },
+    Resource {
+        id: "variables",
+        order: "",
+        embed: "",
+        sync: SyncMethod::Embedded("games"),
+    },
+];
+
+/// The resource with the given ID.
+fn resource(id: &str) -> &'static Resource {
+    RESOURCES
+        .iter()
+        .find(|resource| resource.id == id)
+        .expect("resource should exist")
+}
+
+/// The names of the files in `data_dir` that make up our mirror, relative to
+/// it, including any from before we had [Store]s.
+pub fn mirror_files(data_dir: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+    for resource in RESOURCES.iter() {
+        let dir = data_dir.join(resource.id);
+        if dir.is_dir() {
+            for entry in fs::read_dir(dir)? {
+                let name = entry?.file_name().to_string_lossy().to_string();
+                files.push(format!("{}/{}", resource.id, name));
+            }
+        }
+        files.push(format!("{}.jsonl.gz", resource.id));
+    }
+    files.push(SYNC_STATE_FILE.to_string());
+    files.push(TOMBSTONES_FILE.to_string());
+
+    Ok(files
+        .into_iter()
+        .filter(|name| data_dir.join(name).is_file())
+        .collect())
+}
+
+/// Where and how a [Spider] downloads.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The API root, ending with a slash, which resource names are appended to.
+    pub base_url: String,
+    /// The directory containing a [Store] for each resource, and our sync state.
+    pub data_dir: PathBuf,
+    /// How many requests we make at once.
+    pub concurrency: usize,
+    /// The most requests we make per second, in total. Zero for no limit.
+    pub requests_per_second: f64,
+    /// How we retry failed requests.
+    pub retry: RetryPolicy,
+    /// How many changed records of each resource we hold in memory before
+    /// writing them out.
+    pub flush_records: usize,
+    /// Whether we archive the API's responses, or replay archived ones.
+    pub archive: ArchiveMode,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            data_dir: PathBuf::from("data/api"),
+            concurrency: 4,
+            requests_per_second: 1.5,
+            retry: RetryPolicy::default(),
+            flush_records: 10_000,
+            archive: ArchiveMode::Off,
+        }
+    }
+}
+
+/// What a reconciliation sweep found for a single resource.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ReconcileStats {
+    /// Records we found in the upstream listing.
+    pub seen: usize,
+    /// Records we didn't have before.
+    pub added: usize,
+    /// Records that were different from the copy we had.
+    pub changed: usize,
+    /// Records that we newly found to have been deleted upstream.
+    pub deleted: usize,
+    /// Previously deleted records that have reappeared upstream.
+    pub restored: usize,
+}
+
+impl std::fmt::Display for ReconcileStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} seen, {} added, {} changed, {} deleted, {} restored",
+            self.seen, self.added, self.changed, self.deleted, self.restored
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Spider {
+    config: Config,
+    stores: BTreeMap<&'static str, Store>,
+    state: SyncState,
+    tombstones: Tombstones,
+    retry_stats: Arc<Mutex<BTreeMap<String, RetryStats>>>,
+}
+
+impl Spider {
+    /// The records we have of `resource`, opening its store if we haven't.
+    fn store(
+        &mut self,
+        resource: &Resource,
+    ) -> Result<&mut Store, Box<dyn std::error::Error>> {
+        if !self.stores.contains_key(resource.id) {
+            let store = Store::open(
+                self.config.data_dir.join(resource.id),
+                self.config.flush_records,
+            )?;
+            self.stores.insert(resource.id, store);
+        }
+        Ok(self.stores.get_mut(resource.id).expect("store to be open"))
+    }
+
+    fn sync_state_path(&self) -> PathBuf {
+        self.config.data_dir.join(SYNC_STATE_FILE)
+    }
+
+    fn tombstones_path(&self) -> PathBuf {
+        self.config.data_dir.join(TOMBSTONES_FILE)
+    }
+
+    pub fn load_or_create(config: Config) -> Self {
+        let mut spider = Spider {
+            config,
+            ..Spider::default()
+        };
+
+        for resource in RESOURCES.iter() {
+            if let Err(error) = spider.load(resource) {
+                info!("Error: {:?}", error);
+            }
+        }
+
+        match SyncState::load(spider.sync_state_path()) {
+            Ok(state) => spider.state = state,
+            Err(error) => warn!("Ignoring unreadable sync state: {:?}", error),
+        }
+
+        match Tombstones::load(spider.tombstones_path()) {
+            Ok(tombstones) => spider.tombstones = tombstones,
+            Err(error) => warn!("Ignoring unreadable tombstones: {:?}", error),
+        }
+
+        spider
+    }
+
+    /// Opens the store for `resource`, moving the records from a
+    /// `{resource}.jsonl.gz` file into it if it's new.
+    fn load(&mut self, resource: &Resource) -> Result<(), Box<dyn std::error::Error>> {
+        info!("Loading {}...", resource.id);
+        let legacy = store::legacy_path(&self.config.data_dir, resource.id);
+        let store = self.store(resource)?;
+
+        if legacy.exists() {
+            if store.is_empty() {
+                info!("Moving {} into a store...", legacy.display());
+                let file = File::open(&legacy)?;
+                let buffer = BufReader::new(&file);
+                let decompressor = GzDecoder::new(buffer);
+                let deserializer = JsonDeserializer::from_reader(decompressor);
+                for item in deserializer.into_iter::<JsonValue>() {
+                    store.insert(item?)?;
+                }
+                store.compact()?;
+                fs::remove_file(&legacy)?;
+            } else {
+                warn!(
+                    "Ignoring {}, since we already have a store of {}.",
+                    legacy.display(),
+                    resource.id
+                );
+            }
+        }
+
+        info!("Loaded {} {}.", store.len(), resource.id);
+        Ok(())
+    }
+
+    /// Writes out any changes to `resource` that we're holding in memory.
+    fn save(&mut self, resource: &Resource) -> Result<(), Box<dyn std::error::Error>> {
+        let store = self.store(resource)?;
+        info!("Saving {} {}...", store.len(), resource.id);
+        store.flush()?;
+        info!("Saved.");
+
+        Ok(())
+    }
+
+    /// Writes out any changes to `resource`, and merges its segments, for when
+    /// we're finished with it.
+    fn compact(&mut self, resource: &Resource) -> Result<(), Box<dyn std::error::Error>> {
+        self.store(resource)?.compact()
+    }
+
+    /// Records our progress through `resource`: saves everything we have for it,
+    /// then the sync state, so that the state never claims more than we've saved.
+    fn checkpoint(
+        &mut self,
+        resource: &Resource,
+        progress: &mut ResourceSyncState,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.save(resource)?;
+        self.tombstones.save(self.tombstones_path())?;
+        progress.updated = Some(Utc::now());
+        self.state
+            .resources
+            .insert(resource.id.to_string(), progress.clone());
+        self.state.save(self.sync_state_path())?;
+        Ok(())
+    }
+
+    /// A [Fetcher] for our configuration, which records into our retry stats.
+    fn fetcher(&self) -> Result<Fetcher, Box<dyn std::error::Error>> {
+        Fetcher::new(&self.config, self.retry_stats.clone())
+    }
+
+    /// Adds items to the resource table, returning the number that were new.
+    fn insert_items(
+        &mut self,
+        resource: &Resource,
+        items: &[JsonValue],
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut new = 0;
+        for item in items.iter().cloned() {
+            let id = item_id(&item).to_string();
+            if self.tombstones.remove(resource.id, &id) {
+                info!("{} {} has reappeared upstream.", resource.id, id);
+            }
+            if self.store(resource)?.insert(item)? == Change::Added {
+                new += 1;
+            }
+        }
+        Ok(new)
+    }
+
+    /// Adds or replaces a record found while reconciling, counting what changed.
+    fn reconcile_item(
+        &mut self,
+        resource: &Resource,
+        item: JsonValue,
+        stats: &mut ReconcileStats,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let id = item_id(&item).to_string();
+        if self.tombstones.remove(resource.id, &id) {
+            stats.restored += 1;
+        }
+        match self.store(resource)?.insert(item)? {
+            Change::Added => stats.added += 1,
+            Change::Changed => stats.changed += 1,
+            Change::Unchanged => {}
+        }
+        Ok(())
+    }
+
+    /// Finds where an interrupted `Newest` pass should continue from.
+    ///
+    /// If records have been added or removed upstream since we checkpointed,
+    /// the saved offset no longer points just past the last page we stored.
+    /// We look for the last of that page's IDs, first by stepping back (which
+    /// covers deletions and small numbers of insertions) and then forward (for
+    /// large numbers of insertions), and resume immediately after it.
+    fn reanchor(
+        &mut self,
+        fetcher: &Fetcher,
+        resource: &Resource,
+        progress: &mut ResourceSyncState,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if progress.last_seen_ids.is_empty() {
+            return Ok(());
+        }
+
+        let last_seen: HashSet<&str> =
+            progress.last_seen_ids.iter().map(String::as_str).collect();
+        let anchor_in = |items: &[JsonValue]| {
+            items
+                .iter()
+                .rposition(|item| last_seen.contains(item_id(item)))
+        };
+
+        let mut offset = progress.offset.saturating_sub(PAGE_SIZE);
+        loop {
+            let page = fetcher.page(resource, &[], progress.direction, offset)?;
+            if let Some(index) = anchor_in(&page.items) {
+                info!(
+                    "Resuming {} from offset {}.",
+                    resource.id,
+                    offset + index + 1
+                );
+                progress.offset = offset + index + 1;
+                return Ok(());
+            }
+            if offset == 0 {
+                break;
+            }
+            offset = offset.saturating_sub(PAGE_SIZE);
+        }
+
+        let mut offset = progress.offset;
+        'forward: loop {
+            for page in fetcher.window(resource, &[], progress.direction, offset) {
+                let page = page?;
+                if let Some(index) = anchor_in(&page.items) {
+                    info!(
+                        "Resuming {} from offset {}.",
+                        resource.id,
+                        offset + index + 1
+                    );
+                    progress.offset = offset + index + 1;
+                    return Ok(());
+                }
+                if !page.has_next {
+                    break 'forward;
+                }
+                offset += page.items.len();
+            }
+        }
+
+        warn!(
+            "Couldn't find where we left off in {}, resuming from offset {} anyway.",
+            resource.id, progress.offset
+        );
+        Ok(())
+    }
+
+    /// Pages from the newest records of `resource` until we reach ones we
+    /// already have. The records we hadn't seen are added to how much of the
+    /// listing we've covered, since they've pushed the rest of it along.
+    fn sync_newest(
+        &mut self,
+        fetcher: &Fetcher,
+        resource: &Resource,
+        progress: &mut ResourceSyncState,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if progress.offset > 0 {
+            self.reanchor(fetcher, resource, progress)?;
+        }
+
+        let mut pages = 0;
+        'pages: loop {
+            let len = self.store(resource)?.len();
+            info!(
+                "We have {} {}, looking for more new {}...",
+                len, resource.id, resource.id
+            );
+
+            for page in fetcher.window(resource, &[], progress.direction, progress.offset) {
+                let page = page?;
+                let more = self.insert_items(resource, &page.items)?;
+                info!("Got {} more {}.", more, resource.id);
+
+                progress.offset += page.items.len();
+                progress.covered += more;
+                progress.last_seen_ids =
+                    page.items.iter().map(item_id).map(String::from).collect();
+
+                if more == 0 || !page.has_next {
+                    // no new items at beginning of list
+                    break 'pages;
+                }
+
+                pages += 1;
+                if pages % CHECKPOINT_INTERVAL == 0 {
+                    self.checkpoint(resource, progress)?;
+                }
+            }
+        }
+
+        // Our store can have more records than we've covered, from `--game` or
+        // deleted upstream, so we can't use its size without skipping some.
+        progress.cursor = SyncCursor::Oldest;
+        progress.offset = progress.covered;
+        progress.last_seen_ids.clear();
+        self.checkpoint(resource, progress)
+    }
+
+    /// Pages past the oldest records of `resource` that we have until the end
+    /// of the listing. Returns whether we saw any records we already had, which
+    /// means that more were added at the beginning while we were paging.
+    fn sync_oldest(
+        &mut self,
+        fetcher: &Fetcher,
+        resource: &Resource,
+        progress: &mut ResourceSyncState,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let mut saw_duplicates = false;
+        let mut rechecking = false;
+
+        let mut pages = 0;
+        'pages: loop {
+            let len = self.store(resource)?.len();
+            info!(
+                "We have {} {}, looking for more old {}...",
+                len, resource.id, resource.id
+            );
+
+            for page in fetcher.window(resource, &[], progress.direction, progress.offset) {
+                let page = page?;
+                let more = self.insert_items(resource, &page.items)?;
+                info!("Got {} more {}.", more, resource.id);
+
+                saw_duplicates |= more < page.items.len();
+
+                if more == 0 && page.has_next && !rechecking {
+                    // this could be a stale copy of a page we've already seen, so
+                    // check again before we skip past whatever is really here.
+                    rechecking = true;
+                    continue 'pages;
+                }
+                rechecking = false;
+
+                progress.offset += page.items.len();
+                progress.last_seen_ids =
+                    page.items.iter().map(item_id).map(String::from).collect();
+
+                if !page.has_next {
+                    // end of entire run list
+                    break 'pages;
+                }
+
+                pages += 1;
+                if pages % CHECKPOINT_INTERVAL == 0 {
+                    self.checkpoint(resource, progress)?;
+                }
+            }
+        }
+
+        progress.cursor = SyncCursor::Complete;
+        progress.covered = progress.offset;
+        progress.offset = 0;
+        progress.last_seen_ids.clear();
+        self.checkpoint(resource, progress)?;
+
+        Ok(saw_duplicates)
+    }
+
+    /// Syncs `resource` from wherever `progress` left off, going back for
+    /// anything added while we were paging through it.
+    fn sync(
+        &mut self,
+        fetcher: &Fetcher,
+        resource: &Resource,
+        progress: &mut ResourceSyncState,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for round in 1..=MAX_ROUNDS {
+            if progress.cursor == SyncCursor::Complete {
+                *progress = progress.restart();
+            }
+
+            if progress.cursor == SyncCursor::Newest {
+                self.sync_newest(fetcher, resource, progress)?;
+            }
+
+            let saw_duplicates = self.sync_oldest(fetcher, resource, progress)?;
+
+            if !saw_duplicates {
+                break;
+            } else if round < MAX_ROUNDS {
+                info!(
+                    "More {} were added while we were downloading, going back for them.",
+                    resource.id
+                );
+            } else {
+                info!(
+                    "More {} were added while we were downloading, leaving them for next time.",
+                    resource.id
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pages through the full listing of `resource`, updating every record we
+    /// have and marking the ones that are gone with tombstones.
+    ///
+    /// Records deleted while we're paging shift later records to lower
+    /// offsets, so some records may be skipped by the sweep. Rather than
+    /// trusting the listing, we look up each record we didn't see by ID, and
+    /// only mark it as deleted if that lookup is a 404.
+    ///
+    /// The IDs we see are spilled to disk and sorted, then merged with the
+    /// store, which is already sorted by ID, one shard at a time.
+    fn reconcile_resource(
+        &mut self,
+        fetcher: &Fetcher,
+        resource: &Resource,
+    ) -> Result<ReconcileStats, Box<dyn std::error::Error>> {
+        info!("Reconciling {}...", resource.id);
+        let mut stats = ReconcileStats::default();
+        let mut seen = BufWriter::new(tempfile::tempfile_in(&self.config.data_dir)?);
+
+        // Going oldest-first means that records added during the sweep are
+        // appended after our offset instead of shifting everything past it.
+        let mut offset = 0;
+        let mut pages = 0;
+        'pages: loop {
+            for page in fetcher.window(resource, &[], SyncDirection::Asc, offset) {
+                let page = page?;
+                offset += page.items.len();
+                pages += 1;
+
+                for item in page.items.iter().cloned() {
+                    serde_json::to_writer(&mut seen, item_id(&item))?;
+                    seen.write_all(b"\n")?;
+                    self.reconcile_item(resource, item, &mut stats)?;
+                }
+
+                if page.items.is_empty() || !page.has_next {
+                    break 'pages;
+                }
+
+                if pages % CHECKPOINT_INTERVAL == 0 {
+                    info!("Reconciled {} {} so far.", offset, resource.id);
+                    self.save(resource)?;
+                }
+            }
+        }
+
+        let mut seen = seen.into_inner().map_err(|error| error.into_error())?;
+        seen.seek(SeekFrom::Start(0))?;
+        let seen = JsonDeserializer::from_reader(BufReader::new(seen))
+            .into_iter::<String>()
+            .map(|id| id.map_err(Into::into));
+        // the listing can repeat records that shifted while we paged through it
+        let mut last = None;
+        let mut seen = sort::sort_by_key(
+            seen,
+            &self.config.data_dir,
+            sort::CHUNK_RECORDS,
+            |id: &String| id.clone(),
+        )?
+        .filter(move |id| match id {
+            Ok(id) if last.as_ref() == Some(id) => false,
+            Ok(id) => {
+                last = Some(id.clone());
+                true
+            }
+            Err(_) => true,
+        });
+
+        let mut next_seen = seen.next().transpose()?;
+        let mut unseen = Vec::new();
+        for item in self.store(resource)?.records()? {
+            let id = item_id(&item?).to_string();
+            while let Some(seen_id) = &next_seen {
+                if *seen_id >= id {
+                    break;
+                }
+                stats.seen += 1;
+                next_seen = seen.next().transpose()?;
+            }
+            if next_seen.as_ref() != Some(&id)
+                && !self.tombstones.contains(resource.id, &id)
+            {
+                unseen.push(id);
+            }
+        }
+        if next_seen.is_some() {
+            stats.seen += 1;
+        }
+        for id in seen {
+            id?;
+            stats.seen += 1;
+        }
+        let detected = Utc::now();
+        for (id, record) in unseen.iter().zip(fetcher.records(resource, &unseen)) {
+            match record? {
+                Some(item) => self.reconcile_item(resource, item, &mut stats)?,
+                None => {
+                    info!("{} {} has been deleted upstream.", resource.id, id);
+                    self.tombstones.insert(resource.id, id, detected);
+                    stats.deleted += 1;
+                }
+            }
+        }
+
+        self.compact(resource)?;
+        self.tombstones.save(self.tombstones_path())?;
+        info!("Reconciled {}: {}.", resource.id, stats);
+
+        Ok(stats)
+    }
+
+    /// Checks every record we have against upstream, instead of only fetching
+    /// new ones like [Spider::run]. Returns what we found, by resource ID.
+    pub fn reconcile(
+        &mut self,
+    ) -> Result<BTreeMap<String, ReconcileStats>, Box<dyn std::error::Error>> {
+        let fetcher = self.fetcher()?;
+        let mut results = BTreeMap::new();
+
+        for resource in RESOURCES.iter() {
+            if let SyncMethod::Embedded(parent_id) = resource.sync {
+                self.sync_embedded(resource, parent_id)?;
+                continue;
+            }
+
+            match self.reconcile_resource(&fetcher, resource) {
+                Ok(stats) => {
+                    results.insert(resource.id.to_string(), stats);
+                }
+                Err(error) => {
+                    error!("Stopping reconciliation of {}: {}", resource.id, error);
+                    self.save(resource)?;
+                    self.tombstones.save(self.tombstones_path())?;
+                    self.report_retry_stats();
+                    return Err(error);
+                }
+            }
+        }
+
+        self.report_retry_stats();
+
+        Ok(results)
+    }
+
+    /// Downloads a single game by its abbreviation, along with all of its runs
+    /// and the users they reference that we don't already have.
+    fn sync_game(
+        &mut self,
+        fetcher: &Fetcher,
+        abbreviation: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (games, runs, users) = (resource("games"), resource("runs"), resource("users"));
+
+        info!("Downloading game {}...", abbreviation);
+        let game = fetcher
+            .records(games, &[abbreviation.to_string()])
+            .remove(0)?
+            .ok_or_else(|| format!("no game found with abbreviation {:?}", abbreviation))?;
+        let game_id = item_id(&game).to_string();
+        self.insert_items(games, &[game])?;
+
+        let mut offset = 0;
+        let mut user_ids = BTreeSet::new();
+        'pages: loop {
+            let filters = [("game", game_id.as_str())];
+            for page in fetcher.window(runs, &filters, SyncDirection::Desc, offset) {
+                let page = page?;
+                offset += page.items.len();
+
+                for run in page.items.iter() {
+                    for player in run["players"].as_array().into_iter().flatten() {
+                        if player["rel"] == "user" {
+                            if let Some(id) = player["id"].as_str() {
+                                user_ids.insert(id.to_string());
+                            }
+                        }
+                    }
+                }
+                self.insert_items(runs, &page.items)?;
+
+                if page.items.is_empty() || !page.has_next {
+                    break 'pages;
+                }
+            }
+        }
+
+        let mut new_user_ids = Vec::new();
+        for id in user_ids {
+            if !self.store(users)?.contains(&id)? {
+                new_user_ids.push(id);
+            }
+        }
+        let mut new_users = 0;
+        for (user_id, user) in new_user_ids
+            .iter()
+            .zip(fetcher.records(users, &new_user_ids))
+        {
+            match user? {
+                Some(user) => {
+                    self.insert_items(users, &[user])?;
+                    new_users += 1;
+                }
+                None => warn!("User {} from {} runs wasn't found.", user_id, abbreviation),
+            }
+        }
+
+        info!(
+            "Downloaded {} with {} runs and {} new users.",
+            abbreviation, offset, new_users
+        );
+
+        Ok(())
+    }
+
+    /// Downloads only the games with the given abbreviations, their runs, and
+    /// the users they reference, merging them with what we already have.
+    pub fn run_games(
+        &mut self,
+        abbreviations: &[String],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let fetcher = self.fetcher()?;
+
+        let mut result = Ok(());
+        for abbreviation in abbreviations {
+            result = self.sync_game(&fetcher, abbreviation);
+            if let Err(ref error) = result {
+                error!("Stopping download of {}: {}", abbreviation, error);
+                break;
+            }
+        }
+
+        // We save whatever we got, even if we stopped early, since it's all
+        // merged with our existing records rather than replacing them.
+        for resource in RESOURCES.iter() {
+            match resource.sync {
+                SyncMethod::Embedded(parent_id) => {
+                    self.sync_embedded(resource, parent_id)?
+                }
+                _ => self.compact(resource)?,
+            }
+        }
+        self.tombstones.save(self.tombstones_path())?;
+        self.report_retry_stats();
+
+        result
+    }
+
+    /// Records we've found to have been deleted upstream.
+    pub fn tombstones(&self) -> &Tombstones {
+        &self.tombstones
+    }
+
+    /// Takes a snapshot of everything we've saved, unless nothing has changed
+    /// since the last one.
+    pub fn snapshot(&mut self) -> Result<Manifest, Box<dyn std::error::Error>> {
+        let mut files = Vec::new();
+        for resource in RESOURCES.iter() {
+            let store = self.store(resource)?;
+            store.flush()?;
+            for (name, records) in store.files() {
+                files.push((format!("{}/{}", resource.id, name), records));
+            }
+        }
+        files.push((SYNC_STATE_FILE.to_string(), None));
+        files.push((TOMBSTONES_FILE.to_string(), None));
+
+        Snapshots::new(&self.config.data_dir).create(&files)
+    }
+
+    /// Request counts for each resource we've downloaded, by resource ID.
+    pub fn retry_stats(&self) -> BTreeMap<String, RetryStats> {
+        self.retry_stats.lock().unwrap().clone()
+    }
+
+    fn report_retry_stats(&self) {
+        for (resource_id, stats) in self.retry_stats().iter() {
+            info!("{}: {}", resource_id, stats);
+        }
+    }
+
+    /// Brings an incrementally-synced resource up to date, resuming any
+    /// interrupted sync of it.
+    fn sync_incremental(
+        &mut self,
+        fetcher: &Fetcher,
+        resource: &Resource,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // the logic:
+        // try to grab from offset of len
+        // if you see any duplicates, that means you're missing some at
+        // the beginning, so you need to switch back into that mode.
+        // if you don't see any duplicates, keep going forward until
+        // you get to the last page, indicating that you're at the end.
+        //
+        // filling from the beginning can leave a gap between the new
+        // records and the ones we already had until it's finished, so
+        // the sync state records how far we got, and an interrupted
+        // pass resumes from there instead of stopping at the first page
+        // of records it already has.
+        //
+        // Hmm, actually, I guess you can find gaps, eh?
+        // If your end count is wrong but there are no new items at the
+        // beginning, you can do a binary search to find the
+        // place that missing records throw off your indices.
+        //
+        // deletions still mess this up, though. `--reconcile` sweeps the
+        // full listings to identify them, but that's too slow to do on
+        // every sync.
+
+        let direction = SyncDirection::Desc;
+        let mut progress = match self.state.resources.get(resource.id) {
+            Some(progress) if progress.direction == direction => {
+                if progress.cursor != SyncCursor::Complete {
+                    info!(
+                        "Resuming interrupted {:?} pass over {}.",
+                        progress.cursor, resource.id
+                    );
+                }
+                progress.clone()
+            }
+            _ => ResourceSyncState::new(direction),
+        };
+
+        if let Err(error) = self.sync(fetcher, resource, &mut progress) {
+            self.checkpoint(resource, &mut progress)?;
+            return Err(error);
+        }
+
+        Ok(())
+    }
+
+    /// Fetches the full listing of a resource, merging it with what we have.
+    fn sync_full(
+        &mut self,
+        fetcher: &Fetcher,
+        resource: &Resource,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut offset = 0;
+        let mut new = 0;
+        'pages: loop {
+            for page in fetcher.window(resource, &[], SyncDirection::Asc, offset) {
+                let page = match page {
+                    Ok(page) => page,
+                    Err(error) => {
+                        self.save(resource)?;
+                        return Err(error.into());
+                    }
+                };
+                offset += page.items.len();
+                new += self.insert_items(resource, &page.items)?;
+
+                if page.items.is_empty() || !page.has_next {
+                    break 'pages;
+                }
+            }
+        }
+
+        info!("Got {} {}, {} of them new.", offset, resource.id, new);
+        self.compact(resource)
+    }
+
+    /// Rebuilds a resource from the copies embedded in another's records.
+    fn sync_embedded(
+        &mut self,
+        resource: &Resource,
+        parent_id: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let parents = self.store(self::resource(parent_id))?.records()?;
+
+        let store = self.store(resource)?;
+        store.clear()?;
+        for parent in parents {
+            for item in parent?[resource.id]["data"]
+                .as_array()
+                .into_iter()
+                .flatten()
+            {
+                store.insert(item.clone())?;
+            }
+        }
+        store.compact()?;
+
+        info!(
+            "Collected {} {} from {}.",
+            store.len(),
+            resource.id,
+            parent_id
+        );
+        Ok(())
+    }
+
+    pub fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let fetcher = self.fetcher()?;
+
+        for resource in RESOURCES.iter() {
+            let result = match resource.sync {
+                SyncMethod::Incremental => self
+                    .sync_incremental(&fetcher, resource)
+                    .and_then(|()| self.compact(resource)),
+                SyncMethod::Full => self.sync_full(&fetcher, resource),
+                SyncMethod::Embedded(parent_id) => self.sync_embedded(resource, parent_id),
+            };
+
+            if let Err(error) = result {
+                error!("Stopping download of {}: {}", resource.id, error);
+                self.report_retry_stats();
+                return Err(error);
+            }
+        }
+
+        self.report_retry_stats();
+
+        Ok(())
+    }
+}
+
+/// The string "id" value of an API item.
+fn item_id(item: &JsonValue) -> &str {
+    item.get("id")
+        .expect("json response to have expected structure")
+        .as_str()
+        .expect("json response to have expected structure")
+}
+
+pub fn main(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let archive = match (args.record_responses, args.replay_responses) {
+        (None, None) => ArchiveMode::Off,
+        (Some(dir), None) => ArchiveMode::Record(dir),
+        (None, Some(dir)) => ArchiveMode::Replay(dir),
+        (Some(_), Some(_)) => {
+            return Err(
+                "--record-responses can't be combined with --replay-responses".into(),
+            )
+        }
+    };
+
+    let mut spider = Spider::load_or_create(Config {
+        base_url: args.base_url,
+        concurrency: args.concurrency,
+        requests_per_second: args.requests_per_second,
+        archive,
+        ..Config::default()
+    });
+
+    if !args.game.is_empty() {
+        if args.reconcile {
+            return Err("--reconcile can't be combined with --game".into());
+        }
+        spider.run_games(&args.game)?;
+    } else if args.reconcile {
+        spider.reconcile()?;
+    } else {
+        spider.run()?;
+    }
+
+    spider.snapshot()?;
+    Ok(())
+}
@@ -0,0 +1,207 @@
+//! How `download` retries failed requests, and what it tells you about them.
+use std::{fmt, time::Duration};
+
+use err_derive::Error;
+use rand::Rng;
+
+/// Exponential backoff with jitter, up to a maximum number of attempts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// How long to wait after the first failure.
+    pub initial_delay: Duration,
+    /// The longest we'll wait between attempts, unless the server asks for longer.
+    pub max_delay: Duration,
+    /// How many times we'll try a request before giving up.
+    pub max_attempts: u32,
+    /// The fraction of each delay that's randomized, so that several clients
+    /// backing off at once don't all retry in lockstep.
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            initial_delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(300),
+            max_attempts: 12,
+            jitter: 0.5,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// How long to wait after the given (1-based) failed attempt.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = self
+            .initial_delay
+            .checked_mul(1 << exponent)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        delay.mul_f64(1.0 - jitter * rand::thread_rng().gen::<f64>())
+    }
+}
+
+/// Why a request attempt failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Failure {
+    /// We couldn't connect or didn't get a complete response.
+    Network(String),
+    /// The server had a problem, which might be gone if we try again.
+    Server(u16),
+    /// The server asked us to slow down, possibly saying how long to wait.
+    RateLimited(u16, Option<Duration>),
+    /// The response wasn't the JSON listing we were expecting.
+    InvalidResponse(String),
+    /// The server rejected the request, and would again if we repeated it.
+    Permanent(u16),
+    /// We're replaying archived responses, and there isn't one for the request.
+    Unarchived,
+}
+
+impl Failure {
+    /// Classifies an unsuccessful response status.
+    pub fn from_status(status: u16, retry_after: Option<Duration>) -> Failure {
+        match status {
+            // speedrun.com uses 420 for rate limiting, as well as the standard 429.
+            420 | 429 => Failure::RateLimited(status, retry_after),
+            408 => Failure::Server(status),
+            400..=499 => Failure::Permanent(status),
+            _ => Failure::Server(status),
+        }
+    }
+
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, Failure::Permanent(_) | Failure::Unarchived)
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Network(error) => write!(f, "request error: {}", error),
+            Failure::Server(status) => write!(f, "server error status {}", status),
+            Failure::RateLimited(status, Some(wait)) => write!(
+                f,
+                "rate limited with status {}, retry after {}s",
+                status,
+                wait.as_secs()
+            ),
+            Failure::RateLimited(status, None) => {
+                write!(f, "rate limited with status {}", status)
+            }
+            Failure::InvalidResponse(error) => write!(f, "invalid response: {}", error),
+            Failure::Permanent(status) => write!(f, "permanent error status {}", status),
+            Failure::Unarchived => write!(f, "no archived response"),
+        }
+    }
+}
+
+/// Parses a Retry-After header value. We only support the delay-seconds form,
+/// which is the only one we've seen speedrun.com use.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// A request that we've given up on.
+#[derive(Debug, Error)]
+#[error(
+    display = "giving up on {} after {} attempt(s), last {}",
+    url,
+    attempts,
+    failure
+)]
+pub struct RetryError {
+    pub url: String,
+    pub attempts: u32,
+    pub failure: Failure,
+}
+
+/// Counts of the requests made for a single resource.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RetryStats {
+    /// Every attempt, successful or not.
+    pub requests: u64,
+    /// Attempts that were followed by another attempt at the same request.
+    pub retries: u64,
+    pub network_errors: u64,
+    pub server_errors: u64,
+    pub rate_limited: u64,
+    pub invalid_responses: u64,
+    pub permanent_errors: u64,
+    /// Requests we gave up on.
+    pub abandoned: u64,
+    /// The total time we spent waiting to retry.
+    pub waited: Duration,
+}
+
+impl RetryStats {
+    pub fn record_failure(&mut self, failure: &Failure) {
+        match failure {
+            Failure::Network(_) => self.network_errors += 1,
+            Failure::Server(_) => self.server_errors += 1,
+            Failure::RateLimited(..) => self.rate_limited += 1,
+            Failure::InvalidResponse(_) => self.invalid_responses += 1,
+            Failure::Permanent(_) | Failure::Unarchived => self.permanent_errors += 1,
+        }
+    }
+}
+
+impl fmt::Display for RetryStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} requests, {} retries ({} network errors, {} server errors, {} rate limited, \
+             {} invalid responses, {} permanent errors), {} abandoned, waited {}s",
+            self.requests,
+            self.retries,
+            self.network_errors,
+            self.server_errors,
+            self.rate_limited,
+            self.invalid_responses,
+            self.permanent_errors,
+            self.abandoned,
+            self.waited.as_secs()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RetryPolicy {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            max_attempts: 100,
+            jitter: 0.5,
+        };
+        for (attempt, expected_secs) in
+            vec![(1, 1), (2, 2), (3, 4), (6, 32), (7, 60), (99, 60)]
+        {
+            let expected = Duration::from_secs(expected_secs);
+            let actual = policy.delay(attempt);
+            assert!(actual <= expected, "{:?} > {:?}", actual, expected);
+            assert!(actual >= expected / 2, "{:?} < {:?}", actual, expected / 2);
+        }
+    }
+
+    #[test]
+    fn test_failure_from_status() {
+        let wait = Some(Duration::from_secs(5));
+        assert_eq!(
+            Failure::from_status(420, wait),
+            Failure::RateLimited(420, wait)
+        );
+        assert_eq!(
+            Failure::from_status(429, None),
+            Failure::RateLimited(429, None)
+        );
+        assert_eq!(Failure::from_status(404, None), Failure::Permanent(404));
+        assert_eq!(Failure::from_status(408, None), Failure::Server(408));
+        assert_eq!(Failure::from_status(503, wait), Failure::Server(503));
+    }
+}
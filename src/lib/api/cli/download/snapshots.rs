@@ -0,0 +1,326 @@
+//! Dated copies of the mirror, so that we can go back to what it held at the
+//! end of any earlier sync.
+//!
+//! Each snapshot is a manifest naming the files that made up the mirror, with
+//! the hash of each file's contents. The files themselves are stored once per
+//! hash, so a snapshot only takes space for the files that changed since the
+//! last one.
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+
+/// The directory in the download directory where we keep our snapshots.
+pub const SNAPSHOTS_DIR: &str = "snapshots";
+
+/// The directory in [SNAPSHOTS_DIR] where we keep the contents of the files
+/// that snapshots refer to, named by their hashes.
+const OBJECTS_DIR: &str = "objects";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// When the snapshot was taken, followed by a hash of its contents.
+    pub id: String,
+    pub created: DateTime<Utc>,
+    /// The files in the snapshot, by their names in the download directory.
+    pub files: BTreeMap<String, SnapshotFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SnapshotFile {
+    /// The SHA-1 hash of the file's contents.
+    pub hash: String,
+    /// The size of the file.
+    pub bytes: u64,
+    /// The number of records in the file, if it holds a resource.
+    pub records: Option<usize>,
+}
+
+impl Manifest {
+    /// The hash of the snapshot's contents, which is the same for any two
+    /// snapshots of the same files.
+    fn hash(&self) -> String {
+        let mut hasher = sha1::Sha1::new();
+        for (name, file) in self.files.iter() {
+            hasher.update(name.as_bytes());
+            hasher.update(b"\0");
+            hasher.update(file.hash.as_bytes());
+            hasher.update(b"\n");
+        }
+        hasher.digest().to_string()
+    }
+
+    /// The total number of records across every resource in the snapshot.
+    pub fn records(&self) -> usize {
+        self.files.values().filter_map(|file| file.records).sum()
+    }
+
+    /// The number of records of each resource in the snapshot, whether it
+    /// was stored in a directory of segments or a single `.jsonl.gz` file.
+    pub fn resources(&self) -> BTreeMap<String, usize> {
+        let mut resources = BTreeMap::new();
+        for (name, file) in self.files.iter() {
+            if let Some(records) = file.records {
+                let resource = match name.split_once('/') {
+                    Some((dir, _)) => dir,
+                    None => name.trim_end_matches(".jsonl.gz"),
+                };
+                *resources.entry(resource.to_string()).or_insert(0) += records;
+            }
+        }
+        resources
+    }
+}
+
+/// The snapshots of the mirror in a download directory.
+#[derive(Debug, Clone)]
+pub struct Snapshots {
+    data_dir: PathBuf,
+}
+
+impl Snapshots {
+    pub fn new(data_dir: impl Into<PathBuf>) -> Self {
+        Snapshots {
+            data_dir: data_dir.into(),
+        }
+    }
+
+    fn dir(&self) -> PathBuf {
+        self.data_dir.join(SNAPSHOTS_DIR)
+    }
+
+    fn objects_dir(&self) -> PathBuf {
+        self.dir().join(OBJECTS_DIR)
+    }
+
+    fn manifest_path(&self, id: &str) -> PathBuf {
+        self.dir().join(format!("{}.json", id))
+    }
+
+    /// Where the contents of a file in a snapshot are stored.
+    pub fn object_path(&self, file: &SnapshotFile) -> PathBuf {
+        self.objects_dir().join(&file.hash)
+    }
+
+    /// Takes a snapshot of the given files in the download directory, with
+    /// their record counts. Files that don't exist are left out.
+    ///
+    /// If nothing has changed since the latest snapshot, that snapshot is
+    /// returned instead of taking a new one.
+    pub fn create(
+        &self,
+        files: &[(String, Option<usize>)],
+    ) -> Result<Manifest, Box<dyn std::error::Error>> {
+        fs::create_dir_all(self.objects_dir())?;
+
+        let created = Utc::now();
+        let mut manifest = Manifest {
+            id: String::new(),
+            created,
+            files: BTreeMap::new(),
+        };
+
+        for (name, records) in files {
+            let path = self.data_dir.join(name);
+            if !path.exists() {
+                continue;
+            }
+            let (hash, bytes) = hash_file(&path)?;
+            let file = SnapshotFile {
+                hash,
+                bytes,
+                records: *records,
+            };
+
+            let object_path = self.object_path(&file);
+            if !object_path.exists() {
+                let mut object = NamedTempFile::new_in(self.objects_dir())?;
+                io::copy(&mut File::open(&path)?, &mut object)?;
+                object.persist(object_path)?;
+            }
+
+            manifest.files.insert(name.clone(), file);
+        }
+
+        let hash = manifest.hash();
+        if let Some(latest) = self.list()?.pop() {
+            if latest.hash() == hash {
+                info!("Nothing has changed since snapshot {}.", latest.id);
+                return Ok(latest);
+            }
+        }
+
+        manifest.id = format!("{}-{}", created.format("%Y%m%dT%H%M%SZ"), &hash[..8]);
+        write_json(&self.manifest_path(&manifest.id), &manifest)?;
+        info!(
+            "Saved snapshot {} of {} records.",
+            manifest.id,
+            manifest.records()
+        );
+
+        Ok(manifest)
+    }
+
+    /// Every snapshot, oldest first.
+    pub fn list(&self) -> Result<Vec<Manifest>, Box<dyn std::error::Error>> {
+        let dir = self.dir();
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut manifests = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().map(|ext| ext == "json").unwrap_or(false) {
+                let file = File::open(&path)?;
+                let manifest: Manifest = serde_json::from_reader(BufReader::new(file))?;
+                manifests.push(manifest);
+            }
+        }
+        manifests.sort_by(|a, b| (a.created, &a.id).cmp(&(b.created, &b.id)));
+
+        Ok(manifests)
+    }
+
+    /// The snapshot with the given ID, or the only one starting with it.
+    pub fn get(&self, id: &str) -> Result<Manifest, Box<dyn std::error::Error>> {
+        let mut matches: Vec<Manifest> = self
+            .list()?
+            .into_iter()
+            .filter(|manifest| manifest.id.starts_with(id))
+            .collect();
+
+        if let Some(index) = matches.iter().position(|manifest| manifest.id == id) {
+            return Ok(matches.remove(index));
+        }
+        match matches.len() {
+            1 => Ok(matches.remove(0)),
+            0 => Err(format!("no snapshot found matching {:?}", id).into()),
+            n => Err(format!("{} snapshots match {:?}", n, id).into()),
+        }
+    }
+
+    /// Writes the files in the snapshot `id` into `dest`, replacing any that
+    /// are already there. Files are hard-linked to the stored copies where
+    /// possible, which is safe because we never modify our files in place.
+    pub fn checkout(
+        &self,
+        id: &str,
+        dest: &Path,
+    ) -> Result<Manifest, Box<dyn std::error::Error>> {
+        let manifest = self.get(id)?;
+
+        for (name, file) in manifest.files.iter() {
+            let path = dest.join(name);
+            let dir = path.parent().unwrap_or(dest);
+            fs::create_dir_all(dir)?;
+
+            let linked = NamedTempFile::new_in(dir)?.into_temp_path();
+            fs::remove_file(&linked)?;
+            if fs::hard_link(self.object_path(file), &linked).is_ok() {
+                linked.persist(path)?;
+            } else {
+                let mut restored = NamedTempFile::new_in(dir)?;
+                io::copy(&mut File::open(self.object_path(file))?, &mut restored)?;
+                restored.persist(path)?;
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    /// Replaces the files in the download directory with the ones in the
+    /// snapshot `id`. Any of the `managed` files that aren't in the snapshot
+    /// are removed, so that they don't get mixed up with what it restored.
+    pub fn restore(
+        &self,
+        id: &str,
+        managed: &[String],
+    ) -> Result<Manifest, Box<dyn std::error::Error>> {
+        let manifest = self.checkout(id, &self.data_dir)?;
+
+        for name in managed {
+            let path = self.data_dir.join(name);
+            if !manifest.files.contains_key(name) && path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+
+        info!("Restored snapshot {}.", manifest.id);
+        Ok(manifest)
+    }
+
+    /// Deletes all but the newest `keep` snapshots, and any stored files that
+    /// the remaining ones don't refer to. Returns the deleted snapshots.
+    pub fn prune(&self, keep: usize) -> Result<Vec<Manifest>, Box<dyn std::error::Error>> {
+        let mut manifests = self.list()?;
+        let kept = manifests.split_off(manifests.len().saturating_sub(keep));
+
+        for manifest in manifests.iter() {
+            fs::remove_file(self.manifest_path(&manifest.id))?;
+        }
+
+        let referenced: HashSet<&str> = kept
+            .iter()
+            .flat_map(|manifest| manifest.files.values())
+            .map(|file| file.hash.as_str())
+            .collect();
+        let objects_dir = self.objects_dir();
+        if objects_dir.exists() {
+            for entry in fs::read_dir(objects_dir)? {
+                let path = entry?.path();
+                let name = path.file_name().and_then(|name| name.to_str());
+                if !name.map(|name| referenced.contains(name)).unwrap_or(false) {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+
+        info!(
+            "Deleted {} snapshots, kept {}.",
+            manifests.len(),
+            kept.len()
+        );
+        Ok(manifests)
+    }
+}
+
+/// The SHA-1 hash and size of a file.
+fn hash_file(path: &Path) -> Result<(String, u64), Box<dyn std::error::Error>> {
+    let mut file = File::open(path)?;
+    let mut hasher = sha1::Sha1::new();
+    let mut bytes = 0;
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        bytes += read as u64;
+    }
+    Ok((hasher.digest().to_string(), bytes))
+}
+
+/// Atomically writes `value` as JSON to `path`.
+fn write_json(
+    path: &Path,
+    value: &impl Serialize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = NamedTempFile::new_in(path.parent().unwrap_or_else(|| Path::new(".")))?;
+    {
+        let buffer = BufWriter::new(&mut file);
+        serde_json::to_writer_pretty(buffer, value)?;
+    }
+    file.persist(path)?;
+    Ok(())
+}
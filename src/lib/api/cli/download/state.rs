@@ -0,0 +1,123 @@
+//! Persistent progress for `download`, so an interrupted sync can pick up where
+//! it left off instead of starting over.
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+
+/// The file in the download directory where we keep our [SyncState], alongside
+/// the mirrored resources.
+pub const SYNC_STATE_FILE: &str = "sync.json";
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SyncState {
+    pub resources: BTreeMap<String, ResourceSyncState>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ResourceSyncState {
+    /// Which pass over the resource listing we're in the middle of.
+    pub cursor: SyncCursor,
+    /// The offset of the next page to request.
+    pub offset: usize,
+    /// The sort direction the offset refers to.
+    pub direction: SyncDirection,
+    /// IDs from the last page we stored. If records have been added or removed
+    /// upstream since then, offsets will have shifted, so we use these to find
+    /// our place again when resuming.
+    pub last_seen_ids: Vec<String>,
+    /// When this state was last checkpointed.
+    pub updated: Option<DateTime<Utc>>,
+    /// How many records at the start of the listing our passes over it have
+    /// covered, which is where the `Oldest` pass resumes. This is kept between
+    /// syncs, and unlike the size of our store, it doesn't count records that we
+    /// got some other way, such as with `--game`, or that are only kept as
+    /// tombstones.
+    #[serde(default)]
+    pub covered: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub enum SyncCursor {
+    /// Paging from the newest records until we reach ones we already have.
+    Newest,
+    /// Paging past the oldest records we have until the end of the listing.
+    Oldest,
+    /// Both passes have finished. The next sync will start a new `Newest` pass.
+    Complete,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "lowercase")]
+pub enum SyncDirection {
+    Asc,
+    Desc,
+}
+
+impl SyncDirection {
+    /// This direction as it's specified in API query strings.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SyncDirection::Asc => "asc",
+            SyncDirection::Desc => "desc",
+        }
+    }
+}
+
+impl ResourceSyncState {
+    /// The state of a resource that we're about to start a fresh sync of.
+    pub fn new(direction: SyncDirection) -> Self {
+        ResourceSyncState {
+            cursor: SyncCursor::Newest,
+            offset: 0,
+            direction,
+            last_seen_ids: Vec::new(),
+            updated: None,
+            covered: 0,
+        }
+    }
+
+    /// The state for the next sync after this one finished, which still knows
+    /// how much of the listing we've covered.
+    pub fn restart(&self) -> Self {
+        ResourceSyncState {
+            covered: self.covered,
+            ..ResourceSyncState::new(self.direction)
+        }
+    }
+}
+
+impl SyncState {
+    /// Loads the sync state from `path`, or returns an empty state if there
+    /// isn't one yet.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(SyncState::default());
+        }
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    /// Atomically replaces the sync state at `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let mut file =
+            NamedTempFile::new_in(path.parent().unwrap_or_else(|| Path::new(".")))?;
+        {
+            let buffer = BufWriter::new(&mut file);
+            serde_json::to_writer_pretty(buffer, self)?;
+        }
+        file.persist(path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,645 @@
+//! How we keep each mirrored resource on disk without holding it in memory.
+//!
+//! A resource's records are split into shards by the first byte of their IDs.
+//! Each shard is a list of segment files, each sorted by ID, with the newest
+//! copy of a record taking precedence over older ones. Changed records are
+//! buffered in memory and appended to their shards as new segments, and
+//! segments are merged back together when a shard has too many of them, or
+//! once we're finished syncing a resource. Only the shards that changed are
+//! ever rewritten, and only one record per segment is held in memory while
+//! merging.
+//!
+//! Each segment has an index alongside it, of fixed-width entries sorted by
+//! ID, so we can find out whether we have a record (and whether it's changed)
+//! by binary-searching it on disk instead of decompressing the segment.
+//!
+//! The files that make up a resource are listed in its [STORE_MANIFEST],
+//! which is replaced atomically after they've been written, so an
+//! interrupted write leaves the store as it was.
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryInto,
+    fs::{self, File},
+    io::{prelude::*, BufReader, BufWriter, SeekFrom},
+    iter::Peekable,
+    path::{Path, PathBuf},
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use serde_json::{Deserializer as JsonDeserializer, Value as JsonValue};
+use tempfile::NamedTempFile;
+
+/// The file in a store's directory listing the segments of each shard.
+pub const STORE_MANIFEST: &str = "store.json";
+
+/// The longest record ID we can index.
+const ID_WIDTH: usize = 16;
+
+/// The size of an index entry: a record ID, padded with zeros, followed by a
+/// hash of the record.
+const ENTRY_WIDTH: usize = ID_WIDTH + 8;
+
+/// How many segments a shard can have before we merge them.
+const MAX_SEGMENTS: usize = 8;
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct StoreManifest {
+    /// The number of the next segment we'll write.
+    next_segment: u64,
+    /// Shards by the hex value of the first byte of their records' IDs.
+    shards: BTreeMap<String, Shard>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct Shard {
+    /// The number of distinct records in the shard.
+    records: usize,
+    /// The numbers of the shard's segments, oldest first.
+    segments: Vec<u64>,
+}
+
+/// What inserting a record into a [Store] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Changed,
+    Unchanged,
+}
+
+/// The records of a single resource.
+#[derive(Debug)]
+pub struct Store {
+    dir: PathBuf,
+    manifest: StoreManifest,
+    /// Records that have been inserted but not yet written, with their hashes.
+    pending: BTreeMap<String, (u64, JsonValue)>,
+    /// How many pending records we'll hold before writing them out.
+    flush_records: usize,
+    /// Open segment indices, by segment number.
+    indices: HashMap<u64, File>,
+}
+
+impl Store {
+    /// Opens the store in `dir`, creating it if it doesn't exist. Pending
+    /// records are written out once there are `flush_records` of them.
+    pub fn open(
+        dir: impl Into<PathBuf>,
+        flush_records: usize,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let manifest = read_manifest(&dir)?.unwrap_or_default();
+
+        let store = Store {
+            dir,
+            manifest,
+            pending: BTreeMap::new(),
+            flush_records: flush_records.max(1),
+            indices: HashMap::new(),
+        };
+        store.remove_unlisted_files()?;
+
+        Ok(store)
+    }
+
+    /// The number of distinct records in the store, including pending ones.
+    pub fn len(&self) -> usize {
+        self.manifest
+            .shards
+            .values()
+            .map(|shard| shard.records)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&mut self, id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self.lookup(id)?.is_some())
+    }
+
+    /// Adds or replaces a record, by its string "id" value.
+    pub fn insert(
+        &mut self,
+        item: JsonValue,
+    ) -> Result<Change, Box<dyn std::error::Error>> {
+        let id = record_id(&item)?.to_string();
+        let hash = record_hash(&item)?;
+
+        let change = match self.lookup(&id)? {
+            None => Change::Added,
+            Some(previous) if previous != hash => Change::Changed,
+            Some(_) => return Ok(Change::Unchanged),
+        };
+        if change == Change::Added {
+            self.manifest
+                .shards
+                .entry(shard_of(&id))
+                .or_default()
+                .records += 1;
+        }
+        self.pending.insert(id, (hash, item));
+
+        if self.pending.len() >= self.flush_records {
+            self.flush()?;
+        }
+
+        Ok(change)
+    }
+
+    /// Writes out any pending records, as a new segment of each shard they
+    /// belong to, merging the segments of any shards that have too many.
+    pub fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        debug!(
+            "Writing {} records to {}...",
+            self.pending.len(),
+            self.dir.display()
+        );
+
+        let pending = std::mem::take(&mut self.pending);
+        let mut by_shard: BTreeMap<String, Vec<JsonValue>> = BTreeMap::new();
+        for (id, (_hash, item)) in pending {
+            by_shard.entry(shard_of(&id)).or_default().push(item);
+        }
+
+        for (shard, items) in by_shard {
+            let segment = self.manifest.next_segment;
+            self.manifest.next_segment += 1;
+            self.write_segment(&shard, segment, items.into_iter().map(Ok))?;
+            self.manifest
+                .shards
+                .entry(shard)
+                .or_default()
+                .segments
+                .push(segment);
+        }
+        self.write_manifest()?;
+
+        let crowded: Vec<String> = self
+            .manifest
+            .shards
+            .iter()
+            .filter(|(_, shard)| shard.segments.len() > MAX_SEGMENTS)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for shard in crowded {
+            self.compact_shard(&shard)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes out any pending records, then merges every shard into a single
+    /// segment.
+    pub fn compact(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.flush()?;
+
+        let shards: Vec<String> = self
+            .manifest
+            .shards
+            .iter()
+            .filter(|(_, shard)| shard.segments.len() > 1)
+            .map(|(name, _)| name.clone())
+            .collect();
+        if !shards.is_empty() {
+            info!(
+                "Compacting {} shards of {}...",
+                shards.len(),
+                self.dir.display()
+            );
+        }
+        for shard in shards {
+            self.compact_shard(&shard)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes every record.
+    pub fn clear(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.pending.clear();
+        self.indices.clear();
+        let next_segment = self.manifest.next_segment;
+        self.manifest = StoreManifest {
+            next_segment,
+            ..StoreManifest::default()
+        };
+        self.write_manifest()?;
+        self.remove_unlisted_files()
+    }
+
+    /// Every record, in order of ID, after writing out any pending ones.
+    pub fn records(&mut self) -> Result<Records, Box<dyn std::error::Error>> {
+        self.flush()?;
+        Ok(Records::new(self.shard_paths()))
+    }
+
+    /// The names of the files that make up the store, relative to its
+    /// directory, with the number of records in each segment that has every
+    /// record of its shard.
+    pub fn files(&self) -> Vec<(String, Option<usize>)> {
+        let mut files = vec![(STORE_MANIFEST.to_string(), None)];
+        for (name, shard) in self.manifest.shards.iter() {
+            for segment in shard.segments.iter() {
+                let records = if shard.segments.len() == 1 {
+                    Some(shard.records)
+                } else {
+                    None
+                };
+                files.push((segment_file(name, *segment), records));
+                files.push((index_file(name, *segment), None));
+            }
+        }
+        files
+    }
+
+    /// The hash of the record with the given ID, if we have it.
+    fn lookup(&mut self, id: &str) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+        if let Some((hash, _)) = self.pending.get(id) {
+            return Ok(Some(*hash));
+        }
+
+        let key = index_key(id)?;
+        let shard = shard_of(id);
+        let segments = match self.manifest.shards.get(&shard) {
+            Some(shard) => shard.segments.clone(),
+            None => return Ok(None),
+        };
+        for segment in segments.iter().rev() {
+            if !self.indices.contains_key(segment) {
+                let index = File::open(self.dir.join(index_file(&shard, *segment)))?;
+                self.indices.insert(*segment, index);
+            }
+            let index = self.indices.get_mut(segment).expect("index to be open");
+            if let Some(hash) = search_index(index, &key)? {
+                return Ok(Some(hash));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Merges the segments of a shard into one.
+    fn compact_shard(&mut self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let old_segments = self.manifest.shards[name].segments.clone();
+        let paths: Vec<PathBuf> = old_segments
+            .iter()
+            .map(|segment| self.dir.join(segment_file(name, *segment)))
+            .collect();
+
+        let segment = self.manifest.next_segment;
+        self.manifest.next_segment += 1;
+        let records = self.write_segment(name, segment, ShardReader::open(&paths)?)?;
+
+        let shard = self.manifest.shards.get_mut(name).expect("shard to exist");
+        shard.segments = vec![segment];
+        shard.records = records;
+        self.write_manifest()?;
+
+        for old in old_segments {
+            self.indices.remove(&old);
+            fs::remove_file(self.dir.join(segment_file(name, old)))?;
+            fs::remove_file(self.dir.join(index_file(name, old)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes records, which must be sorted by ID, as a segment of a shard,
+    /// with its index. Returns the number of records written.
+    fn write_segment(
+        &self,
+        shard: &str,
+        segment: u64,
+        items: impl Iterator<Item = Result<JsonValue, Box<dyn std::error::Error>>>,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut data = NamedTempFile::new_in(&self.dir)?;
+        let mut index = NamedTempFile::new_in(&self.dir)?;
+        let mut records = 0;
+        {
+            let mut compressor =
+                GzEncoder::new(BufWriter::new(&mut data), flate2::Compression::best());
+            let mut index = BufWriter::new(&mut index);
+            for item in items {
+                let item = item?;
+                index.write_all(&index_key(record_id(&item)?)?)?;
+                index.write_all(&record_hash(&item)?.to_be_bytes())?;
+                serde_json::to_writer(&mut compressor, &item)?;
+                compressor.write_all(b"\n")?;
+                records += 1;
+            }
+            compressor.finish()?;
+            index.flush()?;
+        }
+        data.persist(self.dir.join(segment_file(shard, segment)))?;
+        index.persist(self.dir.join(index_file(shard, segment)))?;
+        Ok(records)
+    }
+
+    fn write_manifest(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = NamedTempFile::new_in(&self.dir)?;
+        {
+            let buffer = BufWriter::new(&mut file);
+            serde_json::to_writer_pretty(buffer, &self.manifest)?;
+        }
+        file.persist(self.dir.join(STORE_MANIFEST))?;
+        Ok(())
+    }
+
+    /// The paths of each shard's segments, oldest first, in order of shard.
+    fn shard_paths(&self) -> Vec<Vec<PathBuf>> {
+        self.manifest
+            .shards
+            .iter()
+            .map(|(name, shard)| {
+                shard
+                    .segments
+                    .iter()
+                    .map(|segment| self.dir.join(segment_file(name, *segment)))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Removes anything in the store's directory that its manifest doesn't
+    /// list, like segments from an interrupted write.
+    fn remove_unlisted_files(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let listed: Vec<String> = self.files().into_iter().map(|(name, _)| name).collect();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.file_type()?.is_file() && !listed.contains(&name) {
+                debug!("Removing unlisted file {}.", name);
+                fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the records of a resource from `data_dir`, without opening its store
+/// for writing. If there's no store, but there's a single
+/// `{resource}.jsonl.gz` file from before we had them, its records are read
+/// instead.
+pub fn read(
+    data_dir: &Path,
+    resource: &str,
+) -> Result<Records, Box<dyn std::error::Error>> {
+    let dir = data_dir.join(resource);
+    if let Some(manifest) = read_manifest(&dir)? {
+        let store = Store {
+            dir,
+            manifest,
+            pending: BTreeMap::new(),
+            flush_records: 1,
+            indices: HashMap::new(),
+        };
+        return Ok(Records::new(store.shard_paths()));
+    }
+
+    let legacy = legacy_path(data_dir, resource);
+    if legacy.exists() {
+        return Ok(Records::new(vec![vec![legacy]]));
+    }
+
+    Err(format!("no {} found in {}", resource, data_dir.display()).into())
+}
+
+/// Where we kept a resource before we had stores: a single file of records.
+pub fn legacy_path(data_dir: &Path, resource: &str) -> PathBuf {
+    data_dir.join(format!("{}.jsonl.gz", resource))
+}
+
+fn read_manifest(dir: &Path) -> Result<Option<StoreManifest>, Box<dyn std::error::Error>> {
+    let path = dir.join(STORE_MANIFEST);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let file = File::open(path)?;
+    Ok(Some(serde_json::from_reader(BufReader::new(file))?))
+}
+
+/// The records of a store, read from disk one shard at a time.
+pub struct Records {
+    shards: std::vec::IntoIter<Vec<PathBuf>>,
+    current: Option<ShardReader>,
+}
+
+impl Records {
+    fn new(shards: Vec<Vec<PathBuf>>) -> Self {
+        Records {
+            shards: shards.into_iter(),
+            current: None,
+        }
+    }
+}
+
+impl Iterator for Records {
+    type Item = Result<JsonValue, Box<dyn std::error::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(current) = &mut self.current {
+                if let Some(item) = current.next() {
+                    return Some(item);
+                }
+            }
+            let paths = self.shards.next()?;
+            match ShardReader::open(&paths) {
+                Ok(reader) => self.current = Some(reader),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+type Segment =
+    Peekable<Box<dyn Iterator<Item = Result<JsonValue, Box<dyn std::error::Error>>>>>;
+
+/// Merges the segments of a shard, yielding each record once, in order of ID,
+/// from the newest segment that has it.
+struct ShardReader {
+    /// Oldest first.
+    segments: Vec<Segment>,
+}
+
+impl ShardReader {
+    fn open(paths: &[PathBuf]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut segments = Vec::new();
+        for path in paths {
+            let file = BufReader::new(File::open(path)?);
+            let items = JsonDeserializer::from_reader(GzDecoder::new(file))
+                .into_iter::<JsonValue>()
+                .map(|item| item.map_err(Into::into));
+            let items: Box<dyn Iterator<Item = _>> = Box::new(items);
+            segments.push(items.peekable());
+        }
+        Ok(ShardReader { segments })
+    }
+}
+
+impl Iterator for ShardReader {
+    type Item = Result<JsonValue, Box<dyn std::error::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut lowest: Option<(String, usize)> = None;
+        for (i, segment) in self.segments.iter_mut().enumerate() {
+            let id = match segment.peek() {
+                None => continue,
+                Some(Err(_)) => return segment.next(),
+                Some(Ok(item)) => match record_id(item) {
+                    Ok(id) => id.to_string(),
+                    Err(error) => return Some(Err(error)),
+                },
+            };
+            // newer segments come later, so they win ties
+            if lowest
+                .as_ref()
+                .map(|(lowest, _)| id <= *lowest)
+                .unwrap_or(true)
+            {
+                lowest = Some((id, i));
+            }
+        }
+        let (id, newest) = lowest?;
+
+        for (i, segment) in self.segments.iter_mut().enumerate() {
+            if i != newest {
+                if let Some(Ok(item)) = segment.peek() {
+                    if record_id(item).ok() == Some(id.as_str()) {
+                        segment.next();
+                    }
+                }
+            }
+        }
+        self.segments[newest].next()
+    }
+}
+
+/// Finds a record's hash in a segment index.
+fn search_index(
+    index: &mut File,
+    key: &[u8; ID_WIDTH],
+) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+    let entries = index.metadata()?.len() / ENTRY_WIDTH as u64;
+    let mut entry = [0; ENTRY_WIDTH];
+    let (mut low, mut high) = (0, entries);
+    while low < high {
+        let middle = (low + high) / 2;
+        index.seek(SeekFrom::Start(middle * ENTRY_WIDTH as u64))?;
+        index.read_exact(&mut entry)?;
+        match entry[..ID_WIDTH].cmp(&key[..]) {
+            std::cmp::Ordering::Less => low = middle + 1,
+            std::cmp::Ordering::Greater => high = middle,
+            std::cmp::Ordering::Equal => {
+                let hash = entry[ID_WIDTH..].try_into().expect("hash to be 8 bytes");
+                return Ok(Some(u64::from_be_bytes(hash)));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn record_id(item: &JsonValue) -> Result<&str, Box<dyn std::error::Error>> {
+    item.get("id")
+        .and_then(JsonValue::as_str)
+        .ok_or_else(|| "record should have a string id".into())
+}
+
+/// A hash of a record's contents, to tell whether it's changed.
+fn record_hash(item: &JsonValue) -> Result<u64, Box<dyn std::error::Error>> {
+    let digest = sha1::Sha1::from(serde_json::to_vec(item)?).digest().bytes();
+    Ok(u64::from_be_bytes(
+        digest[..8]
+            .try_into()
+            .expect("digest to be longer than 8 bytes"),
+    ))
+}
+
+/// An ID padded with zeros to a fixed width, which sorts the same way as the ID.
+fn index_key(id: &str) -> Result<[u8; ID_WIDTH], Box<dyn std::error::Error>> {
+    if id.len() > ID_WIDTH {
+        return Err(format!("record id {:?} is too long to index", id).into());
+    }
+    let mut key = [0; ID_WIDTH];
+    key[..id.len()].copy_from_slice(id.as_bytes());
+    Ok(key)
+}
+
+/// The shard a record belongs in, named so that shards sort in the same order
+/// as the IDs in them.
+fn shard_of(id: &str) -> String {
+    format!("{:02x}", id.as_bytes().first().copied().unwrap_or(0))
+}
+
+fn segment_file(shard: &str, segment: u64) -> String {
+    format!("{}-{:06}.jsonl.gz", shard, segment)
+}
+
+fn index_file(shard: &str, segment: u64) -> String {
+    format!("{}-{:06}.idx", shard, segment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn ids(store: &mut Store) -> Vec<String> {
+        store
+            .records()
+            .unwrap()
+            .map(|item| item.unwrap()["id"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_store() {
+        let data_dir = TempDir::new().unwrap();
+        let dir = data_dir.path().join("runs");
+        let mut store = Store::open(&dir, 3).unwrap();
+
+        for id in &["b2", "a1", "b1", "a2", "c1"] {
+            let change = store.insert(json!({"id": id, "n": 1})).unwrap();
+            assert_eq!(change, Change::Added);
+        }
+        assert_eq!(
+            store.insert(json!({"id": "a1", "n": 1})).unwrap(),
+            Change::Unchanged
+        );
+        assert_eq!(
+            store.insert(json!({"id": "a1", "n": 2})).unwrap(),
+            Change::Changed
+        );
+        assert_eq!(store.len(), 5);
+        assert!(store.contains("b2").unwrap());
+        assert!(!store.contains("b3").unwrap());
+
+        // the newest copy of a1 wins, and everything comes out in order
+        assert_eq!(ids(&mut store), vec!["a1", "a2", "b1", "b2", "c1"]);
+        let a1 = store.records().unwrap().next().unwrap().unwrap();
+        assert_eq!(a1["n"], 2);
+
+        // reopening finds everything that was written, and compacting leaves
+        // one segment per shard
+        let mut store = Store::open(&dir, 3).unwrap();
+        assert_eq!(store.len(), 5);
+        store.compact().unwrap();
+        assert_eq!(store.files().iter().filter(|(_, n)| n.is_some()).count(), 3);
+        assert_eq!(ids(&mut store), vec!["a1", "a2", "b1", "b2", "c1"]);
+        assert_eq!(read(data_dir.path(), "runs").unwrap().count(), 5);
+
+        store.clear().unwrap();
+        assert!(store.is_empty());
+        assert_eq!(ids(&mut store), Vec::<String>::new());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+}
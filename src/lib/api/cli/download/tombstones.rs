@@ -0,0 +1,81 @@
+//! Records that we've mirrored but which have since been deleted from
+//! speedrun.com. We keep the records themselves, but mark them here so that
+//! they can be excluded from imports.
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+
+/// The file in the download directory where we keep our [Tombstones],
+/// alongside the mirrored resources.
+pub const TOMBSTONES_FILE: &str = "tombstones.json";
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Tombstones {
+    /// Tombstones by resource ID, then by record ID.
+    pub resources: BTreeMap<String, BTreeMap<String, Tombstone>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Tombstone {
+    /// When we found that the record had been deleted upstream.
+    pub detected: DateTime<Utc>,
+}
+
+impl Tombstones {
+    /// Loads the tombstones from `path`, or returns none if there isn't a file yet.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Tombstones::default());
+        }
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    /// Atomically replaces the tombstones at `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let mut file =
+            NamedTempFile::new_in(path.parent().unwrap_or_else(|| Path::new(".")))?;
+        {
+            let buffer = BufWriter::new(&mut file);
+            serde_json::to_writer_pretty(buffer, self)?;
+        }
+        file.persist(path)?;
+        Ok(())
+    }
+
+    pub fn contains(&self, resource: &str, id: &str) -> bool {
+        self.resources
+            .get(resource)
+            .map(|tombstones| tombstones.contains_key(id))
+            .unwrap_or(false)
+    }
+
+    /// Marks a record as deleted, returning whether it wasn't already.
+    pub fn insert(&mut self, resource: &str, id: &str, detected: DateTime<Utc>) -> bool {
+        let tombstones = self.resources.entry(resource.to_string()).or_default();
+        if tombstones.contains_key(id) {
+            return false;
+        }
+        tombstones.insert(id.to_string(), Tombstone { detected });
+        true
+    }
+
+    /// Unmarks a record that has turned up again, returning whether it was marked.
+    pub fn remove(&mut self, resource: &str, id: &str) -> bool {
+        self.resources
+            .get_mut(resource)
+            .map(|tombstones| tombstones.remove(id).is_some())
+            .unwrap_or(false)
+    }
+}
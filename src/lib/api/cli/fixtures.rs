@@ -0,0 +1,296 @@
+//! Choosing the subset of the API data that `import --fixtures` includes.
+//!
+//! Fixtures are built from a few games and users. Each game brings its categories,
+//! levels and runs, and each user brings their runs and the games they're in. Every
+//! player of an included run is included too, so that the subset is a valid database
+//! by itself.
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use log::info;
+use serde_json::Value as JsonValue;
+
+use crate::{
+    cli::download::store,
+    types::{RunPlayer, RunStatus},
+};
+
+/// The games our checked-in fixtures are built from.
+pub const DEFAULT_GAMES: &[&str] =
+    &["wc1", "wc2", "wc2btdp", "bpr", "forza_horizon", "zoombinis"];
+
+/// The games, runs and users to include in the fixtures, by API ID.
+#[derive(Debug, Default, Clone)]
+pub struct Fixtures {
+    /// The games whose runs we include.
+    game_ids: HashSet<String>,
+    /// The users whose runs we include.
+    user_ids: HashSet<String>,
+    /// Games that runs by those users are in, which we include without their other
+    /// runs.
+    referenced_game_ids: HashSet<String>,
+    /// Everyone who played a run we've included so far.
+    player_ids: HashSet<String>,
+    /// How many verified runs of each fixture game's leaderboards to include, if not all.
+    runs_per_leaderboard: Option<usize>,
+    /// How many verified runs we've included from each leaderboard, keyed by game,
+    /// category and level.
+    leaderboard_runs: HashMap<(String, String, Option<String>), usize>,
+}
+
+impl Fixtures {
+    /// Finds the `games` (by abbreviation or ID) and `users` (by name or ID) in the
+    /// downloaded data, and the games that those users' runs are in.
+    pub fn find(
+        api_dir: &Path,
+        games: &[String],
+        users: &[String],
+        runs_per_leaderboard: Option<usize>,
+    ) -> Result<Fixtures, Box<dyn std::error::Error>> {
+        let game_ids = find_ids(api_dir, "games", games, &["abbreviation"])?;
+        let user_ids = find_ids(api_dir, "users", users, &["names", "international"])?;
+
+        let mut referenced_game_ids = HashSet::new();
+        if !user_ids.is_empty() {
+            for run in store::read(api_dir, "runs")? {
+                let run = run?;
+                if player_ids(&run).any(|id| user_ids.contains(id)) {
+                    if let Some(game) = run["game"].as_str() {
+                        referenced_game_ids.insert(game.to_string());
+                    }
+                }
+            }
+        }
+
+        info!(
+            "Including {} games and {} users, whose runs are in {} games.",
+            game_ids.len(),
+            user_ids.len(),
+            referenced_game_ids.len()
+        );
+
+        Ok(Fixtures {
+            game_ids,
+            user_ids,
+            referenced_game_ids,
+            runs_per_leaderboard,
+            ..Fixtures::default()
+        })
+    }
+
+    /// Whether to include a game, with its categories and levels.
+    pub fn includes_game(&self, id: &str) -> bool {
+        self.game_ids.contains(id) || self.referenced_game_ids.contains(id)
+    }
+
+    /// Whether a run is by a fixture user or in a fixture game, so that it could be
+    /// included. This lets us skip normalizing the runs that can't be.
+    pub fn may_include_run(&self, run: &crate::types::Run) -> bool {
+        self.game_ids.contains(run.game())
+            || user_ids(run).any(|id| self.user_ids.contains(id))
+    }
+
+    /// Whether to include a run that we've normalized. Runs are offered in order, and
+    /// including one includes its players, so this must be called for every run before
+    /// [Fixtures::includes_user]. Only verified runs count towards the runs per
+    /// leaderboard, so that runs that won't be ranked don't take their places.
+    pub fn includes_run(&mut self, run: &crate::types::Run) -> bool {
+        let included = if user_ids(run).any(|id| self.user_ids.contains(id)) {
+            true
+        } else if self.game_ids.contains(run.game()) {
+            match self.runs_per_leaderboard {
+                Some(_) if !matches!(run.status(), RunStatus::Verified { .. }) => true,
+                Some(limit) => {
+                    let count = self
+                        .leaderboard_runs
+                        .entry((
+                            run.game().clone(),
+                            run.category().clone(),
+                            run.level().clone(),
+                        ))
+                        .or_default();
+                    *count += 1;
+                    *count <= limit
+                }
+                None => true,
+            }
+        } else {
+            false
+        };
+
+        if included {
+            self.player_ids.extend(user_ids(run).cloned());
+        }
+        included
+    }
+
+    /// Whether to include a user.
+    pub fn includes_user(&self, id: &str) -> bool {
+        self.user_ids.contains(id) || self.player_ids.contains(id)
+    }
+}
+
+/// The IDs of the `resource` records whose ID or the string at `field` matches one of
+/// `names`, ignoring case. Fails if any of `names` doesn't match anything.
+fn find_ids(
+    api_dir: &Path,
+    resource: &str,
+    names: &[String],
+    field: &[&str],
+) -> Result<HashSet<String>, Box<dyn std::error::Error>> {
+    let mut ids = HashSet::new();
+    if names.is_empty() {
+        return Ok(ids);
+    }
+
+    let names: Vec<String> = names.iter().map(|name| name.to_lowercase()).collect();
+    let mut found = vec![false; names.len()];
+    for record in store::read(api_dir, resource)? {
+        let record = record?;
+        let id = match record["id"].as_str() {
+            Some(id) => id,
+            None => continue,
+        };
+        let name = field
+            .iter()
+            .fold(&record, |value, key| &value[*key])
+            .as_str()
+            .map(str::to_lowercase);
+
+        for (i, wanted) in names.iter().enumerate() {
+            if id.to_lowercase() == *wanted || name.as_ref() == Some(wanted) {
+                ids.insert(id.to_string());
+                found[i] = true;
+            }
+        }
+    }
+
+    let missing: Vec<&str> = names
+        .iter()
+        .zip(found)
+        .filter(|(_, found)| !found)
+        .map(|(name, _)| name.as_str())
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("no {} found for {}", resource, missing.join(", ")).into());
+    }
+
+    Ok(ids)
+}
+
+/// The IDs of the users who played a run.
+fn user_ids(run: &crate::types::Run) -> impl Iterator<Item = &String> + Clone {
+    run.players().iter().filter_map(|player| match player {
+        RunPlayer::User { id, .. } => Some(id),
+        RunPlayer::Guest { .. } => None,
+    })
+}
+
+/// The IDs of the users who played a run, from its JSON.
+fn player_ids(run: &JsonValue) -> impl Iterator<Item = &str> {
+    run["players"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|player| player["rel"] == "user")
+        .filter_map(|player| player["id"].as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn run(id: &str, game: &str, category: &str, players: &[&str]) -> crate::types::Run {
+        let verified = json!({"status": "verified", "examiner": "e1", "verify-date": null});
+        run_with_status(id, game, category, players, verified)
+    }
+
+    fn run_with_status(
+        id: &str,
+        game: &str,
+        category: &str,
+        players: &[&str],
+        status: JsonValue,
+    ) -> crate::types::Run {
+        serde_json::from_value(json!({
+            "id": id,
+            "weblink": null,
+            "game": game,
+            "level": null,
+            "category": category,
+            "videos": null,
+            "comment": null,
+            "status": status,
+            "players": players
+                .iter()
+                .map(|id| json!({"rel": "user", "id": id, "uri": "https://example.com"}))
+                .collect::<Vec<_>>(),
+            "date": null,
+            "submitted": null,
+            "times": {
+                "primary": "PT1M",
+                "primary_t": 60.0,
+                "realtime": "PT1M",
+                "realtime_t": 60.0,
+                "realtime_noloads": null,
+                "realtime_noloads_t": 0.0,
+                "ingame": null,
+                "ingame_t": 0.0,
+            },
+            "system": {"platform": null, "emulated": false, "region": null},
+            "splits": null,
+            "values": {},
+            "links": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_fixtures() {
+        let mut fixtures = Fixtures {
+            game_ids: vec!["g1".to_string()].into_iter().collect(),
+            user_ids: vec!["u1".to_string()].into_iter().collect(),
+            referenced_game_ids: vec!["g2".to_string()].into_iter().collect(),
+            runs_per_leaderboard: Some(1),
+            ..Fixtures::default()
+        };
+
+        assert!(fixtures.includes_game("g1"));
+        assert!(fixtures.includes_game("g2"));
+        assert!(!fixtures.includes_game("g3"));
+
+        assert!(fixtures.may_include_run(&run("r0", "g1", "c1", &["u7"])));
+        assert!(fixtures.may_include_run(&run("r0", "g3", "c4", &["u1"])));
+        assert!(!fixtures.may_include_run(&run("r0", "g2", "c3", &["u7"])));
+
+        // runs that aren't verified are included, without taking a verified run's place
+        let pending = json!({"status": "new"});
+        assert!(fixtures.includes_run(&run_with_status(
+            "r7",
+            "g1",
+            "c1",
+            &["u8"],
+            pending
+        )));
+
+        // one verified run from each of the fixture game's leaderboards
+        assert!(fixtures.includes_run(&run("r1", "g1", "c1", &["u2"])));
+        assert!(!fixtures.includes_run(&run("r2", "g1", "c1", &["u3"])));
+        assert!(fixtures.includes_run(&run("r3", "g1", "c2", &["u4"])));
+
+        // and every run by the fixture user, with whoever they played with
+        assert!(fixtures.includes_run(&run("r4", "g1", "c1", &["u1"])));
+        assert!(fixtures.includes_run(&run("r5", "g2", "c3", &["u5", "u1"])));
+        assert!(!fixtures.includes_run(&run("r6", "g2", "c3", &["u6"])));
+
+        for user in &["u1", "u2", "u4", "u5", "u8"] {
+            assert!(fixtures.includes_user(user), "{}", user);
+        }
+        for user in &["u3", "u6"] {
+            assert!(!fixtures.includes_user(user), "{}", user);
+        }
+    }
+}
@@ -1,4 +1,8 @@
 //! Convert our API data into our simplified and normalized format.
+//!
+//! Records are normalized as they're decoded, and runs, the largest table by far, are
+//! never all held in memory: they're spilled to disk, sorted by ID on disk, then checked
+//! against the IDs of the rows they refer to as they're written out.
 #![allow(
     clippy::useless_attribute,
     clippy::cognitive_complexity,
@@ -6,145 +10,698 @@
 )]
 
 use std::{
-    collections::HashSet,
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
     fs::File,
     io::{prelude::*, BufReader, BufWriter},
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
-use flate2::read::GzDecoder;
 use itertools::Itertools;
 
-use log::info;
+use log::{error, info, warn};
 use serde::{de::DeserializeOwned, Serialize};
-use serde_json::{Deserializer as JsonDeserializer, Value as JsonValue};
-use tempfile::NamedTempFile;
+use serde_json::Deserializer as JsonDeserializer;
+use tempfile::{NamedTempFile, TempDir};
 
-use crate::normalize::Normalize;
-use speedruns_database::{Database, Tables};
+use crate::{
+    cli::download::{
+        snapshots::Snapshots,
+        store,
+        tombstones::{Tombstones, TOMBSTONES_FILE},
+    },
+    cli::fixtures::{Fixtures, DEFAULT_GAMES},
+    cli::sort,
+    drift::DriftReport,
+    normalize::Normalize,
+};
+use speedruns_database::{
+    has_wrong_player_count, user_precedence, validate_game_moderator, validate_run,
+    Database, IntegrityErrors, RunReferences, Tables,
+};
+use speedruns_models::{Category, Game, Run, User, Variable};
+use speedruns_utils::base36;
 
 #[derive(argh::FromArgs, PartialEq, Debug)]
 /// Imports downloaded data (converting it to our internal representation, discarding weird
-/// records). existing data is removed/replaced. This is even less memory-efficient than
-/// `download` because it also stores everything in memory, and but also memory leaks on top
-/// of that!
+/// records). existing data is removed/replaced. Runs are streamed through a temporary file
+/// in data/, so this needs disk space for them, but not memory.
 #[argh(subcommand, name = "import")]
 pub struct Args {
     /// import a subset of the API data into our fixtures, instead of importing the full
-    /// data set into our database.
+    /// data set into our database. Without --fixture-game or --fixture-user, this is the
+    /// games our checked-in fixtures are built from.
     #[argh(switch)]
     fixtures: bool,
+    /// a game to include in the fixtures, by abbreviation or ID, with its categories,
+    /// levels and runs. Implies --fixtures, and may be repeated.
+    #[argh(option)]
+    fixture_game: Vec<String>,
+    /// a user to include in the fixtures, by name or ID, with their runs and the games
+    /// they're in. Implies --fixtures, and may be repeated.
+    #[argh(option)]
+    fixture_user: Vec<String>,
+    /// only include this many verified runs from each leaderboard of the fixture games (the
+    /// first ones downloaded that we can import), instead of all of them. A fixture user's
+    /// runs are all included, as are runs that aren't verified.
+    #[argh(option)]
+    fixture_runs_per_leaderboard: Option<usize>,
+    /// include records that `download --reconcile` found to have been deleted from
+    /// speedrun.com, which are excluded by default.
+    #[argh(switch)]
+    keep_tombstoned: bool,
+    /// import records that don't match the API types we expect, dropping unknown fields
+    /// and nulling out values we can't parse, instead of stopping at the first one. Fails
+    /// at the end only if that affected fields we import.
+    #[argh(switch)]
+    lenient: bool,
+    /// where to write the report of mismatches found with --lenient.
+    #[argh(option, default = "String::from(\"data/drift.json\")")]
+    drift_report: String,
+    /// where to write the records that we couldn't convert, with the reasons why.
+    #[argh(option, default = "String::from(\"data/quarantine.jsonl\")")]
+    quarantine: String,
+    /// the largest fraction of any resource's records that we can fail to convert before
+    /// giving up on the import, instead of quarantining them and continuing.
+    #[argh(option, default = "0.01")]
+    max_error_rate: f64,
+    /// import from the `download` snapshot with this ID (or unique prefix of it), instead of
+    /// the latest downloaded data.
+    #[argh(option)]
+    snapshot: Option<String>,
 }
 
 pub fn main(args: Args) -> Result<(), Box<dyn std::error::Error>> {
-    let mut runs = Vec::new();
-    let mut users = Vec::new();
     let mut games = Vec::new();
     let mut categories = Vec::new();
     let mut levels = Vec::new();
+    let mut variables = Vec::new();
+    // Games embed the records they're tagged with, so we dedupe them by ID.
+    let mut developers = BTreeMap::new();
+    let mut publishers = BTreeMap::new();
+    let mut genres = BTreeMap::new();
+    let mut engines = BTreeMap::new();
+    let mut gametypes = BTreeMap::new();
+    let mut platforms = BTreeMap::new();
+    let mut game_moderators = Vec::new();
+    let mut regions = Vec::new();
 
-    if args.fixtures {
+    let fixtures =
+        args.fixtures || !args.fixture_game.is_empty() || !args.fixture_user.is_empty();
+    if fixtures {
         info!("Generating fixture data, not importing into database.");
     }
 
-    let fixture_game_slugs = ["wc1", "wc2", "wc2btdp", "bpr", "forza_horizon", "zoombinis"];
-    let mut fixture_game_ids = HashSet::new();
-    let mut fixture_run_ids = HashSet::new();
-    let mut fixture_user_ids = HashSet::new();
+    // A snapshot is checked out into a temporary directory that mirrors the download
+    // directory, and removed once we're done with it.
+    let checkout = match &args.snapshot {
+        Some(id) => {
+            let dir = TempDir::new_in("data")?;
+            let manifest = Snapshots::new("data/api").checkout(id, dir.path())?;
+            info!("Importing from snapshot {}.", manifest.id);
+            Some(dir)
+        }
+        None => None,
+    };
+    let api_dir: PathBuf = match &checkout {
+        Some(dir) => dir.path().to_path_buf(),
+        None => PathBuf::from("data/api"),
+    };
+
+    let tombstones = if args.keep_tombstoned {
+        Tombstones::default()
+    } else {
+        Tombstones::load(api_dir.join(TOMBSTONES_FILE))?
+    };
+    let mut tombstoned = 0;
+
+    let mut drift = if args.lenient {
+        Some(DriftReport::default())
+    } else {
+        None
+    };
+    let mut quarantine = Quarantine::new("data")?;
+
+    let mut fixtures = if fixtures {
+        let games = if args.fixture_game.is_empty() && args.fixture_user.is_empty() {
+            DEFAULT_GAMES.iter().map(|slug| slug.to_string()).collect()
+        } else {
+            args.fixture_game.clone()
+        };
+        Some(Fixtures::find(
+            &api_dir,
+            &games,
+            &args.fixture_user,
+            args.fixture_runs_per_leaderboard,
+        )?)
+    } else {
+        None
+    };
 
     info!("Loading API games, with categories and levels...");
-    for api_game in load_api_type::<crate::types::Game>("data/api/games.jsonl.gz")? {
-        if args.fixtures && !fixture_game_slugs.contains(&api_game.abbreviation().as_ref())
-        {
+    for api_game in load_api_type::<crate::types::Game>(&api_dir, "games", &mut drift)? {
+        let api_game = api_game?;
+        if tombstones.contains("games", api_game.id()) {
+            tombstoned += 1;
             continue;
-        } else {
-            fixture_game_ids.insert(api_game.id().clone());
         }
 
-        let (game, mut game_categories, mut game_levels) = api_game
-            .normalize()
-            .expect("we should be able to handle all run game variations");
+        if let Some(fixtures) = &fixtures {
+            if !fixtures.includes_game(api_game.id()) {
+                continue;
+            }
+        }
+
+        let mut normalized =
+            match quarantine.normalize("games", api_game.id(), &api_game)? {
+                Some(normalized) => normalized,
+                None => continue,
+            };
 
-        games.push(game);
-        categories.append(&mut game_categories);
-        levels.append(&mut game_levels);
+        games.push(normalized.game);
+        categories.append(&mut normalized.categories);
+        levels.append(&mut normalized.levels);
+        variables.append(&mut normalized.variables);
+        game_moderators.append(&mut normalized.moderators);
+        for (table, tags) in [
+            (&mut developers, normalized.developers),
+            (&mut publishers, normalized.publishers),
+            (&mut genres, normalized.genres),
+            (&mut engines, normalized.engines),
+            (&mut gametypes, normalized.gametypes),
+        ] {
+            for tag in tags {
+                table.entry(tag.id).or_insert(tag);
+            }
+        }
+        for platform in normalized.platforms {
+            platforms.entry(platform.id).or_insert(platform);
+        }
     }
 
+    // Platforms and regions are few, and always imported in full, fixtures or not. The
+    // platforms resource takes precedence over the copies embedded in games, but those
+    // are kept in case a game refers to a platform the resource doesn't list.
+    info!("Loading API platforms and regions...");
+    for api_platform in
+        load_api_type::<crate::types::Platform>(&api_dir, "platforms", &mut drift)?
+    {
+        let api_platform = api_platform?;
+        if tombstones.contains("platforms", api_platform.id()) {
+            tombstoned += 1;
+            continue;
+        }
+
+        if let Some(platform) =
+            quarantine.normalize("platforms", api_platform.id(), &api_platform)?
+        {
+            platforms.insert(platform.id, platform);
+        }
+    }
+    for api_region in
+        load_api_type::<crate::types::Region>(&api_dir, "regions", &mut drift)?
+    {
+        let api_region = api_region?;
+        if tombstones.contains("regions", api_region.id()) {
+            tombstoned += 1;
+            continue;
+        }
+
+        if let Some(region) =
+            quarantine.normalize("regions", api_region.id(), &api_region)?
+        {
+            regions.push(region);
+        }
+    }
+
+    // Runs are normalized as they're decoded and spilled to disk, because we can't
+    // check their players until we've loaded the users, and we don't know which
+    // users we need for the fixtures until we've seen the runs.
     info!("Loading API runs...");
-    for api_run in load_api_type::<crate::types::Run>("data/api/runs.jsonl.gz")? {
-        if args.fixtures && !fixture_game_ids.contains(api_run.game()) {
+    let spill_dir = TempDir::new_in("data")?;
+    let spilled_runs_path = spill_dir.path().join("runs.jsonl");
+    let mut spilled_runs = BufWriter::new(File::create(&spilled_runs_path)?);
+    for api_run in load_api_type::<crate::types::Run>(&api_dir, "runs", &mut drift)? {
+        let api_run = api_run?;
+        if tombstones.contains("runs", api_run.id()) {
+            tombstoned += 1;
             continue;
-        } else {
-            fixture_run_ids.insert(api_run.id().clone());
-            for player in api_run.players() {
-                if let crate::types::RunPlayer::User { id, .. } = player {
-                    fixture_user_ids.insert(id.clone());
-                }
+        }
+
+        if let Some(fixtures) = &fixtures {
+            if !fixtures.may_include_run(&api_run) {
+                continue;
             }
         }
 
-        if let Some(run) = api_run
-            .normalize()
-            .expect("we should be able to handle all run data variations")
-        {
-            runs.push(run);
+        if let Some(run) = quarantine.normalize("runs", api_run.id(), &api_run)? {
+            if let Some(fixtures) = &mut fixtures {
+                if !fixtures.includes_run(&api_run) {
+                    continue;
+                }
+            }
+            serde_json::to_writer(&mut spilled_runs, &run)?;
+            spilled_runs.write_all(b"\n")?;
         }
     }
+    spilled_runs.flush()?;
+    drop(spilled_runs);
 
+    // Users are small, so we only keep the one that takes precedence for each slug.
     info!("Loading API users...");
-    for api_user in load_api_type::<crate::types::User>("data/api/users.jsonl.gz")? {
-        if args.fixtures && !fixture_user_ids.contains(api_user.id()) {
+    let mut users_by_slug = HashMap::<String, User>::new();
+    for api_user in load_api_type::<crate::types::User>(&api_dir, "users", &mut drift)? {
+        let api_user = api_user?;
+        if tombstones.contains("users", api_user.id()) {
+            tombstoned += 1;
             continue;
         }
 
-        let user = api_user
-            .normalize()
-            .expect("we should be able to handle all user data variations");
+        if let Some(fixtures) = &fixtures {
+            if !fixtures.includes_user(api_user.id()) {
+                continue;
+            }
+        }
+
+        if let Some(user) = quarantine.normalize("users", api_user.id(), &api_user)? {
+            match users_by_slug.entry(user.slug().clone()) {
+                Entry::Vacant(entry) => {
+                    entry.insert(user);
+                }
+                Entry::Occupied(mut entry) => {
+                    if user_precedence(&user) < user_precedence(entry.get()) {
+                        entry.insert(user);
+                    }
+                }
+            }
+        }
+    }
+
+    if tombstoned > 0 {
+        info!("Skipped {} records deleted from speedrun.com.", tombstoned);
+    }
 
-        users.push(user);
+    if let Some(drift) = &drift {
+        write_drift_report(&args.drift_report, drift)?;
     }
 
-    info!("Validating and cleaning API data...");
+    info!("Validating and cleaning API games and what they contain...");
 
-    let database = Database::new(Arc::new(Tables::new(
-        games, categories, levels, runs, users,
-    )));
+    let database = Database::new(Arc::new(
+        Tables::new(games, categories, levels, Vec::new(), Vec::new())
+            .with_variables(variables)
+            .with_platforms(platforms.into_values())
+            .with_regions(regions)
+            .with_developers(developers.into_values())
+            .with_publishers(publishers.into_values())
+            .with_genres(genres.into_values())
+            .with_engines(engines.into_values())
+            .with_gametypes(gametypes.into_values()),
+    ));
+    let references = References {
+        database: &database,
+        users: users_by_slug.values().map(|user| user.id).collect(),
+    };
 
     let games: Vec<_> = database.games().values().collect();
     let categories: Vec<_> = database.categories().values().collect();
     let levels: Vec<_> = database.levels().values().collect();
-    let runs: Vec<_> = database.runs().values().collect();
-    let users: Vec<_> = database.users().values().collect();
+    let variables: Vec<_> = database.variables().values().collect();
+    let platforms: Vec<_> = database.platforms().values().collect();
+    let regions: Vec<_> = database.regions().values().collect();
+    let developers: Vec<_> = database.developers().values().collect();
+    let publishers: Vec<_> = database.publishers().values().collect();
+    let genres: Vec<_> = database.genres().values().collect();
+    let engines: Vec<_> = database.engines().values().collect();
+    let gametypes: Vec<_> = database.gametypes().values().collect();
+    let users: Vec<_> = users_by_slug.values().collect();
 
-    let dir = if args.fixtures { "fixture" } else { "imported" };
+    // Runs are sorted by ID on disk, and the ones that refer to rows we don't have are
+    // quarantined, before we write anything, so that too many of those stops the import.
+    info!("Sorting and validating runs...");
+    let mut runs_file = NamedTempFile::new_in("data")?;
+    let spilled = BufReader::new(File::open(&spilled_runs_path)?);
+    let valid = validate_runs(
+        JsonDeserializer::from_reader(spilled).into_iter(),
+        spill_dir.path(),
+        &references,
+        &mut quarantine,
+        &mut runs_file,
+    )?;
+
+    quarantine.finish(&args.quarantine, args.max_error_rate)?;
+
+    let dir = if fixtures.is_some() {
+        "fixture"
+    } else {
+        "imported"
+    };
 
     info!("Dumping {} games...", games.len());
     dump_table(&format!("data/{}/games", dir), games)?;
     info!("Dumping {} users...", users.len());
     dump_table(&format!("data/{}/users", dir), users)?;
-    info!("Dumping {} runs...", runs.len());
-    dump_table(&format!("data/{}/runs", dir), runs)?;
+    runs_file.persist(format!("data/{}/runs.jsonl", dir))?;
+    info!("Dumped {} runs.", valid);
+
     info!("Dumping {} categories...", categories.len());
     dump_table(&format!("data/{}/categories", dir), categories)?;
     info!("Dumping {} levels...", levels.len());
     dump_table(&format!("data/{}/levels", dir), levels)?;
+    info!("Dumping {} variables...", variables.len());
+    dump_table(&format!("data/{}/variables", dir), variables)?;
+    info!("Dumping {} platforms...", platforms.len());
+    dump_table(&format!("data/{}/platforms", dir), platforms)?;
+    info!("Dumping {} regions...", regions.len());
+    dump_table(&format!("data/{}/regions", dir), regions)?;
+    info!("Dumping {} developers...", developers.len());
+    dump_table(&format!("data/{}/developers", dir), developers)?;
+    info!("Dumping {} publishers...", publishers.len());
+    dump_table(&format!("data/{}/publishers", dir), publishers)?;
+    info!("Dumping {} genres...", genres.len());
+    dump_table(&format!("data/{}/genres", dir), genres)?;
+    info!("Dumping {} engines...", engines.len());
+    dump_table(&format!("data/{}/engines", dir), engines)?;
+    info!("Dumping {} gametypes...", gametypes.len());
+    dump_table(&format!("data/{}/gametypes", dir), gametypes)?;
+
+    // Moderators are dropped along with their games or users, such as the users that
+    // fixtures don't include.
+    let (game_moderators, invalid_game_moderators): (Vec<_>, Vec<_>) = game_moderators
+        .into_iter()
+        .partition(|moderator| validate_game_moderator(&references, moderator).is_ok());
+    if !invalid_game_moderators.is_empty() {
+        warn!(
+            "{:6} ({:3}%) invalid game moderators",
+            invalid_game_moderators.len(),
+            (invalid_game_moderators.len() * 100)
+                / (game_moderators.len() + invalid_game_moderators.len())
+        );
+    }
+    info!("Dumping {} game moderators...", game_moderators.len());
+    dump_table(&format!("data/{}/game_moderators", dir), game_moderators)?;
+
+    if let Some(drift) = &drift {
+        let normalized = drift.normalized();
+        if !normalized.is_empty() {
+            for drift in normalized.iter() {
+                error!(
+                    "{} {} in {} {}: {}",
+                    drift.count, drift.kind, drift.resource, drift.path, drift.message
+                );
+            }
+            return Err(format!(
+                "the API's schema has drifted in {} fields that we import, see {}",
+                normalized.len(),
+                args.drift_report
+            )
+            .into());
+        }
+    }
 
     Ok(())
 }
 
-fn load_api_type<ApiType: DeserializeOwned>(
+/// Sorts `runs` by ID, in chunks spilled to `dir`, and writes the ones that are valid
+/// against `references` to `output`, quarantining the rest. Runs with a number of players
+/// their category doesn't allow are still written, but flagged so they're kept off
+/// leaderboards. Returns how many were valid.
+fn validate_runs(
+    runs: impl Iterator<Item = Result<Run, serde_json::Error>>,
+    dir: &Path,
+    references: &impl RunReferences,
+    quarantine: &mut Quarantine,
+    output: impl Write,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let runs = runs.map(|run| run.map_err(Into::into));
+    let sorted = sort::sort_by_key(runs, dir, sort::CHUNK_RECORDS, |run: &Run| run.id)?;
+
+    let mut valid = 0;
+    let mut buffer = BufWriter::new(output);
+    let mut wrong_player_counts = 0;
+    for run in sorted {
+        let mut run = run?;
+        if let Err(errors) = validate_run(references, &run) {
+            quarantine.reject("runs", &base36(run.id), &errors, &run)?;
+            continue;
+        }
+        run.wrong_player_count =
+            has_wrong_player_count(references.category(run.category_id), &run);
+        if run.wrong_player_count {
+            wrong_player_counts += 1;
+        }
+        valid += 1;
+        serde_json::to_writer(&mut buffer, &run)?;
+        buffer.write_all(b"\n")?;
+    }
+    buffer.flush()?;
+
+    if wrong_player_counts > 0 {
+        warn!(
+            "{} runs have a number of players their category doesn't allow.",
+            wrong_player_counts
+        );
+    }
+
+    Ok(valid)
+}
+
+/// What we check runs and game moderators against: the cleaned games, categories,
+/// levels, variables, platforms and regions, and the IDs of the users we're importing.
+struct References<'a> {
+    database: &'a Database,
+    users: HashSet<u64>,
+}
+
+impl RunReferences for References<'_> {
+    fn game(&self, id: u64) -> Option<&Game> {
+        self.database.game(id)
+    }
+
+    fn category(&self, id: u64) -> Option<&Category> {
+        self.database.category(id)
+    }
+
+    fn has_level(&self, id: u64) -> bool {
+        self.database.has_level(id)
+    }
+
+    fn has_user(&self, id: u64) -> bool {
+        self.users.contains(&id)
+    }
+
+    fn variable(&self, id: u64) -> Option<&Variable> {
+        self.database.variable(id)
+    }
+
+    fn has_platform(&self, id: u64) -> bool {
+        self.database.has_platform(id)
+    }
+
+    fn has_region(&self, id: u64) -> bool {
+        self.database.has_region(id)
+    }
+}
+
+/// The records that we couldn't normalize or validate, which are written to a JSONL file
+/// instead of being imported, with counts of what went wrong.
+struct Quarantine {
+    file: BufWriter<NamedTempFile>,
+    /// How many records of each resource we've tried to normalize.
+    attempts: BTreeMap<&'static str, usize>,
+    /// How many records of each resource failed, by the kind of error.
+    errors: BTreeMap<&'static str, BTreeMap<&'static str, usize>>,
+    /// How many records of each resource were converted but refer to something we don't
+    /// have, by the kind of error. These don't count towards the maximum error rate, since
+    /// they're expected when records are deleted or excluded upstream.
+    rejected: BTreeMap<&'static str, BTreeMap<&'static str, usize>>,
+}
+
+#[derive(Serialize)]
+struct QuarantinedRecord<'a, T> {
+    resource: &'a str,
+    id: &'a str,
+    kind: &'a str,
+    reason: String,
+    record: &'a T,
+}
+
+impl Quarantine {
+    /// A quarantine that's written to a temporary file in `dir` until it's finished.
+    fn new(dir: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Quarantine {
+            file: BufWriter::new(NamedTempFile::new_in(dir)?),
+            attempts: BTreeMap::new(),
+            errors: BTreeMap::new(),
+            rejected: BTreeMap::new(),
+        })
+    }
+
+    /// Normalizes a `resource` record, or quarantines it if we can't.
+    fn normalize<ApiType: Normalize + Serialize>(
+        &mut self,
+        resource: &'static str,
+        id: &str,
+        record: &ApiType,
+    ) -> Result<Option<ApiType::Normalized>, Box<dyn std::error::Error>> {
+        *self.attempts.entry(resource).or_default() += 1;
+
+        let error = match record.normalize() {
+            Ok(normalized) => return Ok(Some(normalized)),
+            Err(error) => error,
+        };
+        *self
+            .errors
+            .entry(resource)
+            .or_default()
+            .entry(error.kind())
+            .or_default() += 1;
+        self.quarantine(resource, id, error.kind(), error.to_string(), record)?;
+
+        Ok(None)
+    }
+
+    /// Quarantines a normalized `resource` record that failed validation, such as a run
+    /// of a category we don't have. It must already have been counted by
+    /// [Quarantine::normalize], but isn't counted as a failure to convert it.
+    fn reject<T: Serialize>(
+        &mut self,
+        resource: &'static str,
+        id: &str,
+        errors: &IntegrityErrors,
+        record: &T,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let kind = errors
+            .errors
+            .first()
+            .map_or("integrity", |error| error.kind());
+        let reason = errors.errors.iter().map(ToString::to_string).join("; ");
+        *self
+            .rejected
+            .entry(resource)
+            .or_default()
+            .entry(kind)
+            .or_default() += 1;
+        self.quarantine(resource, id, kind, reason, record)
+    }
+
+    fn quarantine<T: Serialize>(
+        &mut self,
+        resource: &'static str,
+        id: &str,
+        kind: &'static str,
+        reason: String,
+        record: &T,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        serde_json::to_writer(
+            &mut self.file,
+            &QuarantinedRecord {
+                resource,
+                id,
+                kind,
+                reason,
+                record,
+            },
+        )?;
+        self.file.write_all(b"\n")?;
+
+        Ok(())
+    }
+
+    /// Saves the quarantined records to `path`, and logs how many there were. Fails if
+    /// more than `max_error_rate` of any resource's records couldn't be converted.
+    fn finish(
+        self,
+        path: &str,
+        max_error_rate: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.file.into_inner()?.persist(path)?;
+
+        let counts = |errors: &BTreeMap<&str, usize>| -> (usize, String) {
+            let counts: Vec<String> = errors
+                .iter()
+                .map(|(kind, count)| format!("{} {}", count, kind))
+                .collect();
+            (errors.values().sum(), counts.join(", "))
+        };
+
+        for (resource, rejected) in self.rejected.iter() {
+            let (invalid, counts) = counts(rejected);
+            warn!(
+                "Quarantined {} of {} {} that refer to records we don't have: {}.",
+                invalid, self.attempts[resource], resource, counts
+            );
+        }
+
+        let mut too_many = Vec::new();
+        for (resource, errors) in self.errors.iter() {
+            let attempts = self.attempts[resource];
+            let (failed, counts) = counts(errors);
+            warn!(
+                "Quarantined {} of {} {} that we couldn't convert: {}.",
+                failed, attempts, resource, counts
+            );
+
+            if failed as f64 > max_error_rate * attempts as f64 {
+                too_many.push(format!("{} of {} {}", failed, attempts, resource));
+            }
+        }
+
+        if !too_many.is_empty() {
+            return Err(format!(
+                "too many records couldn't be imported ({}, more than the maximum error \
+                 rate of {}), see {}",
+                too_many.join(", "),
+                max_error_rate,
+                path
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Streams the downloaded records of `resource`. If we're given a [DriftReport], records
+/// that don't match `ApiType` are patched or skipped, and recorded in it.
+fn load_api_type<'a, ApiType: DeserializeOwned + 'a>(
+    api_dir: &Path,
+    resource: &'a str,
+    drift: &'a mut Option<DriftReport>,
+) -> Result<
+    impl Iterator<Item = Result<ApiType, Box<dyn std::error::Error>>> + 'a,
+    Box<dyn std::error::Error>,
+> {
+    let json_results = store::read(api_dir, resource)?;
+    Ok(json_results.filter_map(move |json| {
+        let json = match json {
+            Ok(json) => json,
+            Err(error) => return Some(Err(error)),
+        };
+        match drift {
+            Some(drift) => drift.parse(resource, json).map(Ok),
+            None => Some(ApiType::deserialize(json).map_err(Into::into)),
+        }
+    }))
+}
+
+fn write_drift_report(
     path: &str,
-) -> Result<Vec<ApiType>, Box<dyn std::error::Error>> {
-    let file = File::open(path)?;
-    let buffer = BufReader::new(&file);
-    let decompressor = GzDecoder::new(buffer);
-    let deserializer = JsonDeserializer::from_reader(decompressor);
-    let json_results = deserializer.into_iter::<JsonValue>();
-    Ok(json_results
-        .map(Result::unwrap)
-        .map(ApiType::deserialize)
-        .map(Result::unwrap)
-        .collect())
+    drift: &DriftReport,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for (resource, counts) in drift.records.iter() {
+        info!(
+            "{}: {} parsed, {} patched, {} skipped.",
+            resource, counts.parsed, counts.patched, counts.skipped
+        );
+    }
+
+    let mut file = NamedTempFile::new_in("data")?;
+    serde_json::to_writer_pretty(BufWriter::new(&mut file), drift)?;
+    file.persist(path)?;
+    info!("Wrote {} kinds of drift to {}.", drift.drift.len(), path);
+
+    Ok(())
 }
 
 fn dump_table<T: Serialize + Ord>(
@@ -163,3 +720,169 @@ fn dump_table<T: Serialize + Ord>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn api_run(id: &str) -> crate::types::Run {
+        serde_json::from_value(json!({
+            "id": id,
+            "weblink": null,
+            "game": "00000001",
+            "level": null,
+            "category": "00000002",
+            "videos": null,
+            "comment": null,
+            "status": {"status": "verified", "examiner": null, "verify-date": null},
+            "players": [],
+            "date": null,
+            "submitted": null,
+            "times": {
+                "primary": "PT1M",
+                "primary_t": 60.0,
+                "realtime": "PT1M",
+                "realtime_t": 60.0,
+                "realtime_noloads": null,
+                "realtime_noloads_t": 0.0,
+                "ingame": null,
+                "ingame_t": 0.0,
+            },
+            "system": {"platform": null, "emulated": false, "region": null},
+            "splits": null,
+            "values": {},
+            "links": [],
+        }))
+        .unwrap()
+    }
+
+    fn quarantined(path: &Path) -> Vec<serde_json::Value> {
+        JsonDeserializer::from_reader(File::open(path).unwrap())
+            .into_iter()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn test_quarantine_max_error_rate() {
+        let dir = TempDir::new().unwrap();
+        let quarantine_runs = |max_error_rate| {
+            let mut quarantine = Quarantine::new(dir.path()).unwrap();
+            let mut normalized = 0;
+            for id in &["00000001", "00000002", "00000003", "invalid"] {
+                if quarantine
+                    .normalize("runs", id, &api_run(id))
+                    .unwrap()
+                    .is_some()
+                {
+                    normalized += 1;
+                }
+            }
+            assert_eq!(normalized, 3);
+            let path = dir.path().join("quarantine.jsonl");
+            let result = quarantine.finish(path.to_str().unwrap(), max_error_rate);
+            (result, quarantined(&path))
+        };
+
+        // one of four runs is within a 50% error rate, so the import carries on
+        let (result, records) = quarantine_runs(0.5);
+        assert!(result.is_ok());
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["id"], "invalid");
+        assert_eq!(records[0]["kind"], "invalid-id");
+        assert_eq!(records[0]["record"]["id"], "invalid");
+
+        // but not within 10%, which stops it, after still writing the quarantine
+        let (result, records) = quarantine_runs(0.1);
+        assert!(result.is_err());
+        assert_eq!(records.len(), 1);
+    }
+
+    #[test]
+    fn test_validate_runs() {
+        let dir = TempDir::new().unwrap();
+        let game: Game = serde_json::from_value(json!({
+            "id": 1,
+            "created": null,
+            "slug": "game",
+            "name": "Game",
+            "primary_timing": "RTA",
+        }))
+        .unwrap();
+        let category: Category = serde_json::from_value(json!({
+            "game_id": 1,
+            "id": 2,
+            "slug": "any",
+            "name": "Any%",
+            "per": "PerGame",
+            "rules": "",
+            "players": {"Exactly": 1},
+        }))
+        .unwrap();
+        let database = Database::new(Arc::new(Tables::new(
+            vec![game],
+            vec![category],
+            vec![],
+            vec![],
+            vec![],
+        )));
+        let references = References {
+            database: &database,
+            users: HashSet::new(),
+        };
+
+        let run = |id: u64, category_id: u64, players: serde_json::Value| -> Run {
+            serde_json::from_value(json!({
+                "game_id": 1,
+                "category_id": category_id,
+                "level_id": null,
+                "id": id,
+                "created": null,
+                "date": null,
+                "times_ms": {"igt": null, "rta": 60000, "rta_nl": null},
+                "players": players,
+                "videos": [],
+            }))
+            .unwrap()
+        };
+        let runs = vec![
+            run(3, 2, json!([{"GuestName": "a"}, {"GuestName": "b"}])),
+            run(2, 404, json!([{"GuestName": "a"}])),
+            run(1, 2, json!([{"GuestName": "a"}])),
+        ];
+
+        let mut quarantine = Quarantine::new(dir.path()).unwrap();
+        // as if they'd been normalized
+        quarantine.attempts.insert("runs", runs.len());
+        let mut output = Vec::new();
+        let valid = validate_runs(
+            runs.into_iter().map(Ok),
+            dir.path(),
+            &references,
+            &mut quarantine,
+            &mut output,
+        )
+        .unwrap();
+
+        // the valid runs are written in order of ID, flagging the one with too many
+        // players rather than dropping it, and the other is quarantined
+        assert_eq!(valid, 2);
+        let written: Vec<(u64, bool)> = JsonDeserializer::from_slice(&output)
+            .into_iter::<Run>()
+            .map(|run| run.unwrap())
+            .map(|run| (run.id, run.wrong_player_count))
+            .collect();
+        assert_eq!(written, vec![(1, false), (3, true)]);
+
+        // one of three runs referring to a missing category is well over a 1% error
+        // rate, but since they were all converted, it doesn't stop the import
+        let path = dir.path().join("quarantine.jsonl");
+        quarantine.finish(path.to_str().unwrap(), 0.01).unwrap();
+        let records = quarantined(&path);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["id"], base36(2u64));
+        assert_eq!(records[0]["kind"], "foreign-key-missing");
+        assert_eq!(records[0]["record"]["category_id"], 404);
+    }
+}
@@ -1,2 +1,6 @@
+pub mod diff;
 pub mod download;
+mod fixtures;
 pub mod import;
+pub mod snapshots;
+mod sort;
@@ -0,0 +1,79 @@
+//! Manage the snapshots that `download` takes of its mirror.
+use std::path::Path;
+
+use log::info;
+
+use crate::cli::download::{mirror_files, snapshots::Snapshots};
+
+#[derive(argh::FromArgs, PartialEq, Debug)]
+/// Lists, prunes, or restores the snapshots that `download` takes of the downloaded data
+/// after each sync.
+#[argh(subcommand, name = "snapshots")]
+pub struct Args {
+    #[argh(subcommand)]
+    command: Command,
+}
+
+#[derive(argh::FromArgs, PartialEq, Debug)]
+#[argh(subcommand)]
+enum Command {
+    List(ListArgs),
+    Prune(PruneArgs),
+    Restore(RestoreArgs),
+}
+
+#[derive(argh::FromArgs, PartialEq, Debug)]
+/// Lists every snapshot, oldest first, with its record counts.
+#[argh(subcommand, name = "list")]
+struct ListArgs {}
+
+#[derive(argh::FromArgs, PartialEq, Debug)]
+/// Deletes all but the newest snapshots, and the stored files only they used.
+#[argh(subcommand, name = "prune")]
+struct PruneArgs {
+    /// how many of the newest snapshots to keep.
+    #[argh(option)]
+    keep: usize,
+}
+
+#[derive(argh::FromArgs, PartialEq, Debug)]
+/// Replaces the downloaded data with a snapshot of it. The next `download` continues from
+/// there.
+#[argh(subcommand, name = "restore")]
+struct RestoreArgs {
+    /// the ID of the snapshot to restore, or any unique prefix of it.
+    #[argh(positional)]
+    id: String,
+}
+
+pub fn main(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let snapshots = Snapshots::new("data/api");
+
+    match args.command {
+        Command::List(ListArgs {}) => {
+            for manifest in snapshots.list()? {
+                let counts: Vec<String> = manifest
+                    .resources()
+                    .iter()
+                    .map(|(resource, records)| format!("{} {}", records, resource))
+                    .collect();
+                println!(
+                    "{}  {}  {}",
+                    manifest.id,
+                    manifest.created.to_rfc3339(),
+                    counts.join(", ")
+                );
+            }
+        }
+        Command::Prune(PruneArgs { keep }) => {
+            for manifest in snapshots.prune(keep)? {
+                info!("Deleted snapshot {}.", manifest.id);
+            }
+        }
+        Command::Restore(RestoreArgs { id }) => {
+            snapshots.restore(&id, &mirror_files(Path::new("data/api"))?)?;
+        }
+    }
+
+    Ok(())
+}
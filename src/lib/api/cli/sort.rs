@@ -0,0 +1,148 @@
+//! Sorting more records than we want to hold in memory at once.
+//!
+//! Records are read in chunks, each chunk is sorted and written to a temporary
+//! file, and the chunks are then merged back together, holding only one record
+//! from each in memory.
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs::File,
+    io::{prelude::*, BufReader, BufWriter, SeekFrom},
+    path::Path,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Deserializer as JsonDeserializer, StreamDeserializer};
+
+/// How many records we sort in memory at a time, by default.
+pub const CHUNK_RECORDS: usize = 100_000;
+
+type Chunk<T> = StreamDeserializer<'static, serde_json::de::IoRead<BufReader<File>>, T>;
+
+/// Sorts `records` by `key`, spilling chunks of `chunk_records` records to
+/// temporary files in `dir`. Records with equal keys keep their order.
+pub fn sort_by_key<T, K, F>(
+    records: impl Iterator<Item = Result<T, Box<dyn std::error::Error>>>,
+    dir: &Path,
+    chunk_records: usize,
+    key: F,
+) -> Result<Sorted<T, K, F>, Box<dyn std::error::Error>>
+where
+    T: Serialize + DeserializeOwned,
+    K: Ord,
+    F: Fn(&T) -> K,
+{
+    let chunk_records = chunk_records.max(1);
+    let mut chunks = Vec::new();
+    let mut chunk = Vec::new();
+    let mut records = records.peekable();
+
+    while records.peek().is_some() {
+        chunk.clear();
+        for record in records.by_ref().take(chunk_records) {
+            chunk.push(record?);
+        }
+        chunk.sort_by_key(|record| key(record));
+
+        let mut file = tempfile::tempfile_in(dir)?;
+        {
+            let mut buffer = BufWriter::new(&mut file);
+            for record in chunk.iter() {
+                serde_json::to_writer(&mut buffer, record)?;
+                buffer.write_all(b"\n")?;
+            }
+            buffer.flush()?;
+        }
+        file.seek(SeekFrom::Start(0))?;
+        chunks.push(JsonDeserializer::from_reader(BufReader::new(file)).into_iter());
+    }
+
+    let mut sorted = Sorted {
+        heads: chunks.iter().map(|_| None).collect(),
+        chunks,
+        heap: BinaryHeap::new(),
+        key,
+    };
+    for i in 0..sorted.chunks.len() {
+        sorted.advance(i)?;
+    }
+    Ok(sorted)
+}
+
+/// The records of [sort_by_key], in order.
+pub struct Sorted<T, K, F> {
+    chunks: Vec<Chunk<T>>,
+    /// The next record from each chunk.
+    heads: Vec<Option<T>>,
+    /// The keys of the heads, with their chunks, lowest first.
+    heap: BinaryHeap<Reverse<(K, usize)>>,
+    key: F,
+}
+
+impl<T, K, F> Sorted<T, K, F>
+where
+    T: DeserializeOwned,
+    K: Ord,
+    F: Fn(&T) -> K,
+{
+    /// Reads the next record of chunk `i` into its head.
+    fn advance(&mut self, i: usize) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(record) = self.chunks[i].next() {
+            let record = record?;
+            self.heap.push(Reverse(((self.key)(&record), i)));
+            self.heads[i] = Some(record);
+        }
+        Ok(())
+    }
+}
+
+impl<T, K, F> Iterator for Sorted<T, K, F>
+where
+    T: DeserializeOwned,
+    K: Ord,
+    F: Fn(&T) -> K,
+{
+    type Item = Result<T, Box<dyn std::error::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((_, i)) = self.heap.pop()?;
+        let record = self.heads[i].take().expect("heap entry to have a head");
+        match self.advance(i) {
+            Ok(()) => Some(Ok(record)),
+            Err(error) => Some(Err(error)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sort_by_key() {
+        let dir = TempDir::new().unwrap();
+        let records = [
+            (5, "a"),
+            (3, "b"),
+            (9, "c"),
+            (1, "d"),
+            (3, "e"),
+            (7, "f"),
+            (0, "g"),
+        ];
+
+        let sorted: Vec<(u32, String)> = sort_by_key(
+            records.iter().map(|(n, s)| Ok((*n, s.to_string()))),
+            dir.path(),
+            3,
+            |(n, _)| *n,
+        )
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+
+        let names: Vec<&str> = sorted.iter().map(|(_, s)| s.as_str()).collect();
+        assert_eq!(names, vec!["g", "d", "b", "e", "a", "f", "c"]);
+    }
+}
@@ -0,0 +1,389 @@
+//! Tolerating and reporting changes in the speedrun.com API's schema.
+//!
+//! Our [crate::types] deny unknown fields, so that we notice when the API
+//! changes. That's what we want most of the time, but it means one new field
+//! stops us from importing anything. [DriftReport::parse] instead patches the
+//! raw JSON until it parses, recording what it had to change at each path:
+//! unknown fields are dropped, and known fields with values we can't parse
+//! are nulled out. A record that still doesn't parse is skipped.
+use std::collections::BTreeMap;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value as JsonValue;
+use serde_path_to_error::Segment;
+
+/// How many of the records affected by each kind of drift we list.
+const MAX_EXAMPLES: usize = 5;
+
+/// The most patches we'll make to a single record before skipping it.
+const MAX_PATCHES: usize = 64;
+
+/// How a record didn't match our types.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum DriftKind {
+    /// A field we don't know about, which we dropped.
+    UnknownField,
+    /// An enum value or tag we don't know about.
+    UnknownVariant,
+    /// A field we expected that wasn't there.
+    MissingField,
+    /// A value of a different type than we expected, or otherwise invalid.
+    TypeMismatch,
+}
+
+impl std::fmt::Display for DriftKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                DriftKind::UnknownField => "unknown field",
+                DriftKind::UnknownVariant => "unknown variant",
+                DriftKind::MissingField => "missing field",
+                DriftKind::TypeMismatch => "type mismatch",
+            }
+        )
+    }
+}
+
+/// Every occurrence of one kind of drift at one path in a resource.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Drift {
+    pub resource: String,
+    /// The path to the value, with array indices left out, like
+    /// `players[].rel`.
+    pub path: String,
+    pub kind: DriftKind,
+    /// Whether the path is one that [crate::normalize] reads from, or the
+    /// drift made us skip a record, so that it may have changed what we
+    /// imported.
+    pub normalized: bool,
+    /// How many records it was found in.
+    pub count: usize,
+    /// The parser's complaint, about the first record it was found in.
+    pub message: String,
+    /// The IDs of the first few records it was found in.
+    pub examples: Vec<String>,
+}
+
+/// How many records of a resource we parsed.
+#[derive(Debug, Serialize, Default, Clone, PartialEq)]
+pub struct RecordCounts {
+    /// Records that matched our types.
+    pub parsed: usize,
+    /// Records we had to patch before they parsed.
+    pub patched: usize,
+    /// Records we couldn't parse, even after patching.
+    pub skipped: usize,
+}
+
+/// The drift found across every record we've parsed.
+#[derive(Debug, Serialize, Default, Clone, PartialEq)]
+pub struct DriftReport {
+    pub records: BTreeMap<String, RecordCounts>,
+    pub drift: Vec<Drift>,
+}
+
+impl DriftReport {
+    /// Parses a `resource` record, patching it as necessary and recording
+    /// any drift. Returns `None` if the record couldn't be parsed at all.
+    pub fn parse<T: DeserializeOwned>(
+        &mut self,
+        resource: &str,
+        mut record: JsonValue,
+    ) -> Option<T> {
+        let id = record
+            .get("id")
+            .and_then(JsonValue::as_str)
+            .unwrap_or("(no id)")
+            .to_string();
+        let mut found = Vec::new();
+
+        let parsed = loop {
+            let error = match serde_path_to_error::deserialize::<_, T>(&record) {
+                Ok(parsed) => break Some(parsed),
+                Err(error) => error,
+            };
+            if found.len() >= MAX_PATCHES {
+                break None;
+            }
+
+            let message = error.inner().to_string();
+            let mut segments: Vec<Segment> = error.path().iter().cloned().collect();
+            let kind = drift_kind(&message);
+            // The path to an unknown or missing field usually ends with the
+            // field, but only leads to its parent if it's in an enum that
+            // serde had to buffer.
+            if let Some(field) = quoted_field(&message) {
+                let named = match segments.last() {
+                    Some(Segment::Map { key }) => *key == field,
+                    _ => false,
+                };
+                if (kind == DriftKind::UnknownField || kind == DriftKind::MissingField)
+                    && !named
+                {
+                    segments.push(Segment::Map { key: field });
+                }
+            }
+            let path = path_string(&segments);
+
+            let patched = match kind {
+                DriftKind::UnknownField => remove(&mut record, &segments),
+                _ => nullify(&mut record, &segments),
+            };
+            found.push((path, kind, message));
+            if !patched {
+                break None;
+            }
+        };
+
+        let counts = self.records.entry(resource.to_string()).or_default();
+        match (&parsed, found.is_empty()) {
+            (Some(_), true) => counts.parsed += 1,
+            (Some(_), false) => counts.patched += 1,
+            (None, _) => counts.skipped += 1,
+        }
+
+        found.sort();
+        found.dedup_by(|a, b| (&a.0, a.1) == (&b.0, b.1));
+        for (path, kind, message) in found {
+            self.add(resource, path, kind, message, &id, parsed.is_none());
+        }
+
+        parsed
+    }
+
+    fn add(
+        &mut self,
+        resource: &str,
+        path: String,
+        kind: DriftKind,
+        message: String,
+        id: &str,
+        skipped: bool,
+    ) {
+        let existing = self.drift.iter_mut().find(|drift| {
+            drift.resource == resource && drift.path == path && drift.kind == kind
+        });
+        let drift = match existing {
+            Some(drift) => drift,
+            None => {
+                let normalized =
+                    kind != DriftKind::UnknownField && is_normalized(resource, &path);
+                self.drift.push(Drift {
+                    resource: resource.to_string(),
+                    path,
+                    kind,
+                    normalized,
+                    count: 0,
+                    message,
+                    examples: Vec::new(),
+                });
+                self.drift.last_mut().unwrap()
+            }
+        };
+        drift.count += 1;
+        drift.normalized |= skipped;
+        if drift.examples.len() < MAX_EXAMPLES {
+            drift.examples.push(id.to_string());
+        }
+    }
+
+    /// The drift that may have changed what we imported.
+    pub fn normalized(&self) -> Vec<&Drift> {
+        self.drift.iter().filter(|drift| drift.normalized).collect()
+    }
+}
+
+/// Whether `path` in a `resource` record is read by [crate::normalize].
+fn is_normalized(resource: &str, path: &str) -> bool {
+    crate::normalize::NORMALIZED_FIELDS
+        .iter()
+        .filter(|(normalized_resource, _)| *normalized_resource == resource)
+        .flat_map(|(_, fields)| fields.iter())
+        .any(|field| {
+            path == *field
+                || path.starts_with(&format!("{}.", field))
+                || path.starts_with(&format!("{}[", field))
+                // drift in a parent of a normalized field, such as
+                // replacing an object, reaches it too
+                || field.starts_with(&format!("{}.", path))
+                || path.is_empty()
+        })
+}
+
+fn drift_kind(message: &str) -> DriftKind {
+    if message.starts_with("unknown field") {
+        DriftKind::UnknownField
+    } else if message.starts_with("unknown variant") {
+        DriftKind::UnknownVariant
+    } else if message.starts_with("missing field") {
+        DriftKind::MissingField
+    } else {
+        DriftKind::TypeMismatch
+    }
+}
+
+/// The first `quoted` name in a serde error message.
+fn quoted_field(message: &str) -> Option<String> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"`([^`]*)`").unwrap();
+    }
+    RE.captures(message).map(|captures| captures[1].to_string())
+}
+
+/// Formats a path like `players[].rel`, without array indices, so that drift
+/// in different elements is counted together.
+fn path_string(segments: &[Segment]) -> String {
+    let mut path = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Seq { .. } => path.push_str("[]"),
+            Segment::Map { key } | Segment::Enum { variant: key } => {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(key);
+            }
+            Segment::Unknown => {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push('?');
+            }
+        }
+    }
+    path
+}
+
+/// The value at `segments` in `value`.
+fn lookup<'a>(
+    mut value: &'a mut JsonValue,
+    segments: &[Segment],
+) -> Option<&'a mut JsonValue> {
+    for segment in segments {
+        value = match segment {
+            Segment::Seq { index } => value.get_mut(*index)?,
+            Segment::Map { key } | Segment::Enum { variant: key } => {
+                value.get_mut(key.as_str())?
+            }
+            Segment::Unknown => return None,
+        };
+    }
+    Some(value)
+}
+
+/// Removes the field at `segments`, returning whether there was one.
+fn remove(value: &mut JsonValue, segments: &[Segment]) -> bool {
+    let (last, parent) = match segments.split_last() {
+        Some((Segment::Map { key }, parent)) => (key, parent),
+        _ => return false,
+    };
+    lookup(value, parent)
+        .and_then(JsonValue::as_object_mut)
+        .and_then(|object| object.remove(last))
+        .is_some()
+}
+
+/// Replaces the value at `segments` with null, returning whether it was
+/// anything else. A missing field is added as null.
+fn nullify(value: &mut JsonValue, segments: &[Segment]) -> bool {
+    if let Some((Segment::Map { key }, parent)) = segments.split_last() {
+        if let Some(object) = lookup(value, parent).and_then(JsonValue::as_object_mut) {
+            if !object.contains_key(key) {
+                object.insert(key.clone(), JsonValue::Null);
+                return true;
+            }
+        }
+    }
+    match lookup(value, segments) {
+        Some(value) if !value.is_null() && !segments.is_empty() => {
+            *value = JsonValue::Null;
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn run() -> JsonValue {
+        json!({
+            "id": "abc123",
+            "weblink": null,
+            "game": "g1",
+            "level": null,
+            "category": "c1",
+            "videos": null,
+            "comment": null,
+            "status": {"status": "verified", "examiner": "e1", "verify-date": null},
+            "players": [{"rel": "user", "id": "u1", "uri": "https://example.com"}],
+            "date": null,
+            "submitted": null,
+            "times": {
+                "primary": "PT1M",
+                "primary_t": 60.0,
+                "realtime": "PT1M",
+                "realtime_t": 60.0,
+                "realtime_noloads": null,
+                "realtime_noloads_t": 0.0,
+                "ingame": null,
+                "ingame_t": 0.0,
+            },
+            "system": {"platform": null, "emulated": false, "region": null},
+            "splits": null,
+            "values": {},
+            "links": [],
+        })
+    }
+
+    #[test]
+    fn test_drift_report() {
+        let mut report = DriftReport::default();
+        let parsed: Option<crate::types::Run> = report.parse("runs", run());
+        assert!(parsed.is_some());
+
+        // a new field, which we don't use
+        let mut new_field = run();
+        new_field["players"][0]["pronouns"] = json!("they/them");
+        new_field["system"]["handheld"] = json!(false);
+        let parsed: Option<crate::types::Run> = report.parse("runs", new_field);
+        assert!(parsed.is_some());
+        assert!(report.normalized().is_empty());
+
+        // a new kind of player, which we'd have to skip
+        let mut new_variant = run();
+        new_variant["players"][0]["rel"] = json!("team");
+        let parsed: Option<crate::types::Run> = report.parse("runs", new_variant);
+        assert!(parsed.is_none());
+
+        assert_eq!(
+            report.records["runs"],
+            RecordCounts {
+                parsed: 1,
+                patched: 1,
+                skipped: 1
+            }
+        );
+
+        let found: Vec<(&str, DriftKind, bool)> = report
+            .drift
+            .iter()
+            .map(|drift| (drift.path.as_str(), drift.kind, drift.normalized))
+            .collect();
+        assert!(found.contains(&("players[].pronouns", DriftKind::UnknownField, false)));
+        assert!(found.contains(&("system.handheld", DriftKind::UnknownField, false)));
+        assert!(found
+            .iter()
+            .any(|(path, kind, normalized)| path.starts_with("players[]")
+                && *kind != DriftKind::UnknownField
+                && *normalized));
+        assert_eq!(report.normalized()[0].examples, vec!["abc123".to_string()]);
+    }
+}
@@ -1,5 +1,6 @@
 use derive_more::From;
 use err_derive::Error;
+use itertools::Itertools;
 use lazy_static::lazy_static;
 
 use log::error;
@@ -17,8 +18,91 @@ pub enum Error {
     InvalidId(utils::Base36DecodingError),
     #[error(display = "internal error: invalid object created. {:?}", _0)]
     InternalValidationErrors(validator::ValidationErrors),
+    #[error(display = "a duration was invalid and could not be parsed: {:?}", _0)]
+    #[from(ignore)]
+    InvalidDuration(String),
 }
 
+impl Error {
+    /// A short name for the kind of error, for counting them.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::NoNames => "no-names",
+            Error::InvalidId(_) => "invalid-id",
+            Error::InternalValidationErrors(_) => "validation",
+            Error::InvalidDuration(_) => "invalid-duration",
+        }
+    }
+}
+
+/// The fields of each resource that we read from in normalizing it, as paths in a
+/// [crate::drift::DriftReport]. Keep these up to date with the implementations below, so
+/// that we notice when the API changes something we depend on.
+pub const NORMALIZED_FIELDS: &[(&str, &[&str])] = &[
+    (
+        "games",
+        &[
+            "id",
+            "names",
+            "abbreviation",
+            "created",
+            "ruleset.default-time",
+            "categories",
+            "levels",
+            "variables",
+            "release-date",
+            "released",
+            "romhack",
+            "developers",
+            "publishers",
+            "genres",
+            "engines",
+            "gametypes",
+            "platforms",
+            "assets",
+            "moderators",
+        ],
+    ),
+    (
+        "runs",
+        &[
+            "id",
+            "game",
+            "category",
+            "level",
+            "submitted",
+            "date",
+            "times",
+            "players",
+            "videos",
+            "status",
+            "values",
+            "system",
+            "comment",
+            "splits",
+            "weblink",
+        ],
+    ),
+    (
+        "users",
+        &[
+            "id",
+            "names",
+            "signup",
+            "location",
+            "twitch",
+            "youtube",
+            "twitter",
+            "speedrunslive",
+            "hitbox",
+            "name-style",
+            "role",
+        ],
+    ),
+    ("platforms", &["id", "name", "released"]),
+    ("regions", &["id", "name"]),
+];
+
 pub trait Normalize {
     type Normalized;
     fn normalize(&self) -> Result<Self::Normalized, Error>;
@@ -33,11 +117,36 @@ impl Normalize for crate::types::User {
             .normalize()
             .unwrap_or_else(|_| format!("Corrupt User {}", self.id()));
         let slug = slugify(&name);
+        let link =
+            |uri: &Option<crate::types::Uri>| uri.as_ref().map(|uri| uri.uri().clone());
         let user = User {
             id: u64_from_base36(self.id())?,
             created: *self.signup(),
             name,
             slug,
+            country_code: self
+                .location()
+                .as_ref()
+                .and_then(|location| location.country().code().clone()),
+            region_code: self.location().as_ref().and_then(|location| {
+                location
+                    .region()
+                    .as_ref()
+                    .and_then(|region| region.code().clone())
+            }),
+            links: UserLinks {
+                twitch: link(self.twitch()),
+                youtube: link(self.youtube()),
+                twitter: link(self.twitter()),
+                speedrunslive: link(self.speedrunslive()),
+                hitbox: link(self.hitbox()),
+            },
+            name_style: self
+                .name_style()
+                .as_ref()
+                .map(Normalize::normalize)
+                .transpose()?,
+            role: self.role().normalize()?,
         };
 
         user.validate()?;
@@ -46,6 +155,48 @@ impl Normalize for crate::types::User {
     }
 }
 
+impl Normalize for crate::types::UserNameStyle {
+    type Normalized = UserNameStyle;
+
+    fn normalize(&self) -> Result<Self::Normalized, Error> {
+        fn color(color: &crate::types::UserNameStyleColor) -> UserNameColor {
+            UserNameColor {
+                light: color.light().clone(),
+                dark: color.dark().clone(),
+            }
+        }
+
+        Ok(match self {
+            crate::types::UserNameStyle::Solid { color: solid } => UserNameStyle::Solid {
+                color: color(solid),
+            },
+            crate::types::UserNameStyle::Gradient {
+                color_from,
+                color_to,
+            } => UserNameStyle::Gradient {
+                color_from: color(color_from),
+                color_to: color(color_to),
+            },
+        })
+    }
+}
+
+impl Normalize for crate::types::UserRole {
+    type Normalized = UserRole;
+
+    fn normalize(&self) -> Result<Self::Normalized, Error> {
+        Ok(match self {
+            crate::types::UserRole::Admin => UserRole::Admin,
+            crate::types::UserRole::Banned => UserRole::Banned,
+            crate::types::UserRole::ContentModerator => UserRole::ContentModerator,
+            crate::types::UserRole::Moderator => UserRole::Moderator,
+            crate::types::UserRole::Programmer => UserRole::Programmer,
+            crate::types::UserRole::Trusted => UserRole::Trusted,
+            crate::types::UserRole::User => UserRole::User,
+        })
+    }
+}
+
 impl Normalize for crate::types::Names {
     type Normalized = String;
 
@@ -69,19 +220,85 @@ impl Normalize for crate::types::Names {
     }
 }
 
+/// A game, with the records embedded in it.
+#[derive(Debug, Clone)]
+pub struct NormalizedGame {
+    pub game: Game,
+    pub categories: Vec<Category>,
+    pub levels: Vec<Level>,
+    pub variables: Vec<Variable>,
+    pub developers: Vec<GameTag>,
+    pub publishers: Vec<GameTag>,
+    pub genres: Vec<GameTag>,
+    pub engines: Vec<GameTag>,
+    pub gametypes: Vec<GameTag>,
+    pub platforms: Vec<Platform>,
+    pub moderators: Vec<GameModerator>,
+}
+
 impl Normalize for crate::types::Game {
-    type Normalized = (Game, Vec<Category>, Vec<Level>);
+    type Normalized = NormalizedGame;
 
     fn normalize(&self) -> Result<Self::Normalized, Error> {
+        let developers = normalize_all(self.developers().data())?;
+        let publishers = normalize_all(self.publishers().data())?;
+        let genres = normalize_all(self.genres().data())?;
+        let engines = normalize_all(self.engines().data())?;
+        let gametypes = normalize_all(self.gametypes().data())?;
+        let platforms = normalize_all(self.platforms().data())?;
+
+        let ids = |tags: &[GameTag]| tags.iter().map(|tag| tag.id).collect();
+
         let game = Game {
             id: u64_from_base36(self.id())?,
             name: self.names().normalize()?,
             slug: self.abbreviation().to_string(),
             created: *self.created(),
             primary_timing: self.ruleset().default_time().normalize()?,
+            release_date: Some(*self.release_date()),
+            released: Some(*self.released()),
+            romhack: *self.romhack(),
+            developer_ids: ids(&developers),
+            publisher_ids: ids(&publishers),
+            genre_ids: ids(&genres),
+            engine_ids: ids(&engines),
+            gametype_ids: ids(&gametypes),
+            platform_ids: platforms.iter().map(|platform| platform.id).collect(),
+            assets: self
+                .assets()
+                .iter()
+                .filter_map(|(kind, asset)| {
+                    let asset = asset.as_ref()?;
+                    Some((
+                        kind.clone(),
+                        GameAsset {
+                            uri: asset.uri().to_string(),
+                            width: *asset.width(),
+                            height: *asset.height(),
+                        },
+                    ))
+                })
+                .collect(),
         };
         game.validate()?;
 
+        let moderators = self
+            .moderators()
+            .iter()
+            .map(|(user_id, level)| -> Result<GameModerator, Error> {
+                let moderator = GameModerator {
+                    game_id: game.id,
+                    user_id: u64_from_base36(user_id)?,
+                    level: level.normalize()?,
+                };
+                moderator.validate()?;
+                Ok(moderator)
+            })
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .sorted()
+            .collect();
+
         let categories = self
             .categories()
             .iter()
@@ -93,6 +310,8 @@ impl Normalize for crate::types::Game {
                     name: api_category.name().to_string(),
                     rules: api_category.rules().clone().unwrap_or_else(String::new),
                     per: api_category.type_().normalize()?,
+                    players: Some(api_category.players().normalize()?),
+                    miscellaneous: *api_category.miscellaneous(),
                 };
 
                 category.validate()?;
@@ -119,53 +338,289 @@ impl Normalize for crate::types::Game {
             })
             .collect::<Result<_, _>>()?;
 
-        Ok((game, categories, levels))
+        let variables = self
+            .variables()
+            .iter()
+            .map(|api_variable| normalize_variable(game.id, api_variable))
+            .collect::<Result<_, _>>()?;
+
+        Ok(NormalizedGame {
+            game,
+            categories,
+            levels,
+            variables,
+            developers,
+            publishers,
+            genres,
+            engines,
+            gametypes,
+            platforms,
+            moderators,
+        })
+    }
+}
+
+impl Normalize for crate::types::GameModeratorType {
+    type Normalized = ModeratorLevel;
+
+    fn normalize(&self) -> Result<Self::Normalized, Error> {
+        Ok(match self {
+            crate::types::GameModeratorType::Moderator => ModeratorLevel::Moderator,
+            crate::types::GameModeratorType::SuperModerator => {
+                ModeratorLevel::SuperModerator
+            }
+        })
+    }
+}
+
+/// Normalizes one of a game's variables, which don't know which game they're in.
+fn normalize_variable(
+    game_id: u64,
+    api_variable: &crate::types::Variable,
+) -> Result<Variable, Error> {
+    let variable = Variable {
+        game_id,
+        id: u64_from_base36(api_variable.id())?,
+        category_id: api_variable
+            .category()
+            .as_deref()
+            .map(u64_from_base36)
+            .transpose()?,
+        scope: api_variable.scope().normalize()?,
+        slug: slugify(api_variable.name()),
+        name: api_variable.name().to_string(),
+        is_subcategory: *api_variable.is_subcategory(),
+        mandatory: *api_variable.mandatory(),
+        default_value_id: api_variable
+            .values()
+            .default()
+            .as_deref()
+            .map(u64_from_base36)
+            .transpose()?,
+        values: api_variable
+            .values()
+            .values()
+            .iter()
+            .map(|(id, api_value)| -> Result<VariableValue, Error> {
+                Ok(VariableValue {
+                    id: u64_from_base36(id)?,
+                    slug: slugify(api_value.label()),
+                    label: api_value.label().to_string(),
+                    rules: api_value.rules().clone().unwrap_or_default(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .sorted()
+            .collect(),
+    };
+
+    variable.validate()?;
+
+    Ok(variable)
+}
+
+fn normalize_all<T: Normalize>(items: &[T]) -> Result<Vec<T::Normalized>, Error> {
+    items.iter().map(Normalize::normalize).collect()
+}
+
+fn game_tag(id: &str, name: &str) -> Result<GameTag, Error> {
+    let tag = GameTag {
+        id: u64_from_base36(id)?,
+        slug: slugify(name),
+        name: name.to_string(),
+    };
+    tag.validate()?;
+    Ok(tag)
+}
+
+impl Normalize for crate::types::GameDeveloper {
+    type Normalized = GameTag;
+
+    fn normalize(&self) -> Result<Self::Normalized, Error> {
+        game_tag(self.id(), self.name())
+    }
+}
+
+impl Normalize for crate::types::GamePublisher {
+    type Normalized = GameTag;
+
+    fn normalize(&self) -> Result<Self::Normalized, Error> {
+        game_tag(self.id(), self.name())
+    }
+}
+
+impl Normalize for crate::types::GameGenre {
+    type Normalized = GameTag;
+
+    fn normalize(&self) -> Result<Self::Normalized, Error> {
+        game_tag(self.id(), self.name())
+    }
+}
+
+impl Normalize for crate::types::GameEngine {
+    type Normalized = GameTag;
+
+    fn normalize(&self) -> Result<Self::Normalized, Error> {
+        game_tag(self.id(), self.name())
+    }
+}
+
+impl Normalize for crate::types::GameType {
+    type Normalized = GameTag;
+
+    fn normalize(&self) -> Result<Self::Normalized, Error> {
+        game_tag(self.id(), self.name())
+    }
+}
+
+impl Normalize for crate::types::Platform {
+    type Normalized = Platform;
+
+    fn normalize(&self) -> Result<Self::Normalized, Error> {
+        let platform = Platform {
+            id: u64_from_base36(self.id())?,
+            slug: slugify(self.name()),
+            name: self.name().to_string(),
+            released: *self.released(),
+        };
+        platform.validate()?;
+        Ok(platform)
+    }
+}
+
+impl Normalize for crate::types::Region {
+    type Normalized = Region;
+
+    fn normalize(&self) -> Result<Self::Normalized, Error> {
+        let region = Region {
+            id: u64_from_base36(self.id())?,
+            slug: slugify(self.name()),
+            name: self.name().to_string(),
+        };
+        region.validate()?;
+        Ok(region)
+    }
+}
+
+impl Normalize for crate::types::VariableScope {
+    type Normalized = VariableScope;
+
+    fn normalize(&self) -> Result<Self::Normalized, Error> {
+        Ok(match self {
+            crate::types::VariableScope::Global => VariableScope::Global,
+            crate::types::VariableScope::FullGame => VariableScope::FullGame,
+            crate::types::VariableScope::AllLevels => VariableScope::AllLevels,
+            crate::types::VariableScope::SingleLevel { level } => {
+                VariableScope::SingleLevel {
+                    level_id: u64_from_base36(level)?,
+                }
+            }
+        })
     }
 }
 
 impl Normalize for crate::types::Run {
-    // Option because we drop runs that aren't verified.
-    type Normalized = Option<Run>;
+    type Normalized = Run;
 
     fn normalize(&self) -> Result<Self::Normalized, Error> {
-        match self.status() {
-            crate::types::RunStatus::Verified { .. } => {
-                let run = Run {
-                    game_id: u64_from_base36(self.game())?,
-                    id: u64_from_base36(self.id())?,
-                    created: *self.submitted(),
-                    date: *self.date(),
-                    category_id: u64_from_base36(self.category())?,
-                    level_id: match self.level() {
-                        None => None,
-                        Some(level_id) => Some(u64_from_base36(level_id)?),
-                    },
-                    times_ms: self.times().normalize()?,
-                    players: self
-                        .players()
+        let run = Run {
+            game_id: u64_from_base36(self.game())?,
+            id: u64_from_base36(self.id())?,
+            created: *self.submitted(),
+            date: *self.date(),
+            category_id: u64_from_base36(self.category())?,
+            level_id: match self.level() {
+                None => None,
+                Some(level_id) => Some(u64_from_base36(level_id)?),
+            },
+            times_ms: self.times().normalize()?,
+            players: self
+                .players()
+                .iter()
+                .map(Normalize::normalize)
+                .collect::<Result<_, _>>()?,
+            videos: self
+                .videos()
+                .as_ref()
+                .map(|video| {
+                    video
+                        .links()
+                        .clone()
+                        .unwrap_or_default()
                         .iter()
-                        .map(Normalize::normalize)
-                        .map(Result::unwrap)
-                        .collect(),
-                    videos: self
-                        .videos()
-                        .as_ref()
-                        .map(|video| {
-                            video
-                                .links()
-                                .clone()
-                                .unwrap_or_default()
-                                .iter()
-                                .map(|uri| uri.to_string().parse().unwrap())
-                                .collect()
-                        })
-                        .unwrap_or_default(),
-                };
-                run.validate()?;
-                Ok(Some(run))
-            }
-            _ => Ok(None),
+                        .filter_map(|uri| uri.to_string().parse().ok())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            status: self.status().normalize()?,
+            values: self
+                .values()
+                .iter()
+                .map(|(variable_id, value_id)| {
+                    Ok((u64_from_base36(variable_id)?, u64_from_base36(value_id)?))
+                })
+                .collect::<Result<_, Error>>()?,
+            platform_id: self
+                .system()
+                .platform()
+                .as_deref()
+                .map(u64_from_base36)
+                .transpose()?,
+            region_id: self
+                .system()
+                .region()
+                .as_deref()
+                .map(u64_from_base36)
+                .transpose()?,
+            emulated: *self.system().emulated(),
+            comment: self.comment().clone().filter(|comment| !comment.is_empty()),
+            splits: self.splits().as_ref().and_then(|splits| match splits {
+                crate::types::RunSplitsOrBuggyValue::RunSplits(
+                    crate::types::RunSplits::RunSplitsIo { uri },
+                )
+                | crate::types::RunSplitsOrBuggyValue::BuggyValue(uri) => {
+                    if uri.is_empty() {
+                        None
+                    } else {
+                        uri.parse().ok()
+                    }
+                }
+            }),
+            weblink: self.weblink().clone(),
+            // checked against the run's category when it's imported
+            wrong_player_count: false,
+        };
+        run.validate()?;
+        Ok(run)
+    }
+}
+
+impl Normalize for crate::types::RunStatus {
+    type Normalized = RunStatus;
+
+    fn normalize(&self) -> Result<Self::Normalized, Error> {
+        fn examiner_id(examiner: &Option<String>) -> Result<Option<u64>, Error> {
+            Ok(match examiner {
+                Some(examiner) => Some(u64_from_base36(examiner)?),
+                None => None,
+            })
         }
+
+        Ok(match self {
+            crate::types::RunStatus::New => RunStatus::New,
+            crate::types::RunStatus::Verified {
+                examiner,
+                verify_date,
+            } => RunStatus::Verified {
+                examiner_id: examiner_id(examiner)?,
+                verify_date: *verify_date,
+            },
+            crate::types::RunStatus::Rejected { examiner, reason } => RunStatus::Rejected {
+                examiner_id: examiner_id(examiner)?,
+                reason: reason.clone(),
+            },
+        })
     }
 }
 
@@ -184,6 +639,19 @@ impl Normalize for crate::types::RunPlayer {
     }
 }
 
+impl Normalize for crate::types::CategoryPlayers {
+    type Normalized = CategoryPlayers;
+
+    fn normalize(&self) -> Result<Self::Normalized, Error> {
+        Ok(match self.type_() {
+            crate::types::CategoryPlayersType::Exactly => {
+                CategoryPlayers::Exactly(*self.value())
+            }
+            crate::types::CategoryPlayersType::UpTo => CategoryPlayers::UpTo(*self.value()),
+        })
+    }
+}
+
 impl Normalize for crate::types::CategoryType {
     type Normalized = CategoryType;
 
@@ -211,21 +679,21 @@ impl Normalize for crate::types::RunTimes {
     type Normalized = RunTimesMs;
 
     fn normalize(&self) -> Result<Self::Normalized, Error> {
-        fn u64_or_zero(s: Option<regex::Match<'_>>) -> u64 {
+        fn u64_or_zero(s: Option<regex::Match<'_>>) -> Result<u64, Error> {
             match s {
                 Some(s) => {
                     let s = s.as_str();
                     if s.is_empty() {
-                        0
+                        Ok(0)
                     } else {
-                        s.parse().unwrap()
+                        s.parse().map_err(|_| Error::InvalidDuration(s.to_string()))
                     }
                 }
-                None => 0,
+                None => Ok(0),
             }
         }
 
-        fn parse_duration_ms(s: &str) -> u64 {
+        fn parse_duration_ms(s: &str) -> Result<u64, Error> {
             lazy_static! {
                 static ref RE: Regex = Regex::new(
                     r"(?x)
@@ -244,23 +712,298 @@ impl Normalize for crate::types::RunTimes {
                 .unwrap();
             }
 
-            let captures = RE.captures(s).expect("duration regex to cover all cases");
-            let days = u64_or_zero(captures.get(1));
-            let hours = u64_or_zero(captures.get(2));
-            let minutes = u64_or_zero(captures.get(3));
-            let seconds = u64_or_zero(captures.get(4));
-            let millis = u64_or_zero(captures.get(5));
+            let captures = RE
+                .captures(s)
+                .ok_or_else(|| Error::InvalidDuration(s.to_string()))?;
+            let days = u64_or_zero(captures.get(1))?;
+            let hours = u64_or_zero(captures.get(2))?;
+            let minutes = u64_or_zero(captures.get(3))?;
+            let seconds = u64_or_zero(captures.get(4))?;
+            let millis = u64_or_zero(captures.get(5))?;
 
-            ((((days * 24) + hours) * 60 + minutes) * 60 + seconds) * 1000 + millis
+            Ok(((((days * 24) + hours) * 60 + minutes) * 60 + seconds) * 1000 + millis)
         }
 
         Ok(RunTimesMs {
-            igt: self.ingame().as_ref().map(|s| parse_duration_ms(s)),
-            rta: self.realtime().as_ref().map(|s| parse_duration_ms(s)),
+            igt: self
+                .ingame()
+                .as_deref()
+                .map(parse_duration_ms)
+                .transpose()?,
+            rta: self
+                .realtime()
+                .as_deref()
+                .map(parse_duration_ms)
+                .transpose()?,
             rta_nl: self
                 .realtime_noloads()
-                .as_ref()
-                .map(|s| parse_duration_ms(s)),
+                .as_deref()
+                .map(parse_duration_ms)
+                .transpose()?,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_normalize_user() {
+        let api_user = |location, name_style| -> crate::types::User {
+            serde_json::from_value(json!({
+                "id": "0000000a",
+                "names": {"international": "Runner", "japanese": null, "twitch": null},
+                "weblink": "https://www.speedrun.com/user/Runner",
+                "signup": "2014-08-22T18:45:52Z",
+                "role": "trusted",
+                "location": location,
+                "name-style": name_style,
+                "twitch": {"uri": "https://www.twitch.tv/runner"},
+                "hitbox": null,
+                "youtube": {"uri": "https://www.youtube.com/user/runner"},
+                "twitter": null,
+                "speedrunslive": null,
+                "links": [],
+            }))
+            .unwrap()
+        };
+
+        let user = api_user(
+            json!({
+                "country": {"code": "ca", "names": {"international": "Canada", "japanese": null, "twitch": null}},
+                "region": {"code": "ca/on", "names": {"international": "Ontario, Canada", "japanese": null, "twitch": null}},
+            }),
+            json!({
+                "style": "gradient",
+                "color-from": {"light": "#000000", "dark": "#111111"},
+                "color-to": {"light": "#222222", "dark": "#333333"},
+            }),
+        )
+        .normalize()
+        .unwrap();
+        assert_eq!(user.id, 10);
+        assert_eq!(user.slug, "Runner");
+        assert_eq!(user.country_code.as_deref(), Some("ca"));
+        assert_eq!(user.region_code.as_deref(), Some("ca/on"));
+        assert_eq!(
+            user.links,
+            UserLinks {
+                twitch: Some("https://www.twitch.tv/runner".to_string()),
+                youtube: Some("https://www.youtube.com/user/runner".to_string()),
+                twitter: None,
+                speedrunslive: None,
+                hitbox: None,
+            }
+        );
+        let color = |light: &str, dark: &str| UserNameColor {
+            light: light.to_string(),
+            dark: dark.to_string(),
+        };
+        assert_eq!(
+            user.name_style,
+            Some(UserNameStyle::Gradient {
+                color_from: color("#000000", "#111111"),
+                color_to: color("#222222", "#333333"),
+            })
+        );
+        assert_eq!(user.role, UserRole::Trusted);
+
+        // users don't have to say where they are, or have a styled name
+        let user = api_user(json!(null), json!(null)).normalize().unwrap();
+        assert_eq!(user.country_code, None);
+        assert_eq!(user.region_code, None);
+        assert_eq!(user.name_style, None);
+    }
+
+    #[test]
+    fn test_normalize_game_moderators() {
+        let api_game = |moderators| -> crate::types::Game {
+            serde_json::from_value(json!({
+                "id": "0000000a",
+                "names": {"international": "Game", "japanese": null, "twitch": null},
+                "abbreviation": "game",
+                "weblink": "https://www.speedrun.com/game",
+                "released": 1999,
+                "release-date": "1999-01-01",
+                "ruleset": {
+                    "show-milliseconds": false,
+                    "require-verification": true,
+                    "require-video": false,
+                    "run-times": ["realtime"],
+                    "default-time": "realtime",
+                    "emulators-allowed": false,
+                },
+                "romhack": false,
+                "created": null,
+                "assets": {},
+                "moderators": moderators,
+                "links": [],
+                "categories": {"data": []},
+                "levels": {"data": []},
+                "variables": {"data": []},
+                "developers": {"data": []},
+                "publishers": {"data": []},
+                "genres": {"data": []},
+                "engines": {"data": []},
+                "gametypes": {"data": []},
+                "platforms": {"data": []},
+                "regions": {"data": []},
+            }))
+            .unwrap()
+        };
+
+        let game = api_game(json!({
+            "0000000c": "moderator",
+            "0000000b": "super-moderator",
+        }))
+        .normalize()
+        .unwrap();
+        assert_eq!(
+            game.moderators,
+            vec![
+                GameModerator {
+                    game_id: 10,
+                    user_id: 11,
+                    level: ModeratorLevel::SuperModerator,
+                },
+                GameModerator {
+                    game_id: 10,
+                    user_id: 12,
+                    level: ModeratorLevel::Moderator,
+                },
+            ]
+        );
+
+        // a moderator we can't identify means we can't trust the rest of the game either
+        let invalid = api_game(json!({"not an id": "moderator"})).normalize();
+        assert_eq!(invalid.unwrap_err().kind(), "invalid-id");
+    }
+
+    #[test]
+    fn test_normalize_variable() {
+        let api_variable: crate::types::Variable = serde_json::from_value(json!({
+            "id": "0000000a",
+            "name": "Version",
+            "category": "0000000b",
+            "scope": {"type": "single-level", "level": "0000000c"},
+            "mandatory": true,
+            "user-defined": false,
+            "obsoletes": true,
+            "is-subcategory": true,
+            "links": [],
+            "values": {
+                "_note": "",
+                "choices": {"00000002": "PAL", "00000001": "NTSC (US)"},
+                "values": {
+                    "00000002": {"label": "PAL", "rules": null, "flags": null},
+                    "00000001": {
+                        "label": "NTSC (US)",
+                        "rules": "The original release.",
+                        "flags": {"miscellaneous": false},
+                    },
+                },
+                "default": "00000001",
+            },
+        }))
+        .unwrap();
+
+        let variable = normalize_variable(7, &api_variable).unwrap();
+        assert_eq!(variable.game_id, 7);
+        assert_eq!(variable.id, 10);
+        assert_eq!(variable.category_id, Some(11));
+        assert_eq!(variable.scope, VariableScope::SingleLevel { level_id: 12 });
+        assert_eq!(variable.slug, "Version");
+        assert!(variable.is_subcategory);
+        assert!(variable.mandatory);
+        assert_eq!(variable.default_value_id, Some(1));
+
+        // values are sorted by ID, and slugged by their labels
+        let values: Vec<(u64, &str, &str)> = variable
+            .values
+            .iter()
+            .map(|value| (value.id, value.slug.as_str(), value.rules.as_str()))
+            .collect();
+        assert_eq!(
+            values,
+            vec![(1, "NTSC_US", "The original release."), (2, "PAL", "")]
+        );
+        assert_eq!(variable.value(2).unwrap().label, "PAL");
+        assert!(variable.value(3).is_none());
+
+        let invalid: crate::types::Variable = serde_json::from_value(json!({
+            "id": "not an id",
+            "name": "Version",
+            "category": null,
+            "scope": {"type": "global"},
+            "mandatory": false,
+            "user-defined": false,
+            "obsoletes": true,
+            "is-subcategory": false,
+            "links": [],
+            "values": {"_note": "", "choices": {}, "values": {}, "default": null},
+        }))
+        .unwrap();
+        assert_eq!(
+            normalize_variable(7, &invalid).unwrap_err().kind(),
+            "invalid-id"
+        );
+    }
+
+    #[test]
+    fn test_normalize_run_system() {
+        let api_run = |system: serde_json::Value| -> crate::types::Run {
+            serde_json::from_value(json!({
+                "id": "0000000a",
+                "weblink": null,
+                "game": "00000001",
+                "level": null,
+                "category": "00000002",
+                "videos": null,
+                "comment": null,
+                "status": {"status": "new"},
+                "players": [],
+                "date": null,
+                "submitted": null,
+                "times": {
+                    "primary": "PT1M",
+                    "primary_t": 60.0,
+                    "realtime": "PT1M",
+                    "realtime_t": 60.0,
+                    "realtime_noloads": null,
+                    "realtime_noloads_t": 0.0,
+                    "ingame": null,
+                    "ingame_t": 0.0,
+                },
+                "system": system,
+                "splits": null,
+                "values": {"00000003": "00000004"},
+                "links": [],
+            }))
+            .unwrap()
+        };
+
+        let run = api_run(
+            json!({"platform": "00000005", "emulated": true, "region": "00000006"}),
+        )
+        .normalize()
+        .unwrap();
+        assert_eq!(run.platform_id, Some(5));
+        assert_eq!(run.region_id, Some(6));
+        assert!(run.emulated);
+        assert_eq!(run.values.into_iter().collect::<Vec<_>>(), vec![(3, 4)]);
+
+        let run = api_run(json!({"platform": null, "emulated": false, "region": null}))
+            .normalize()
+            .unwrap();
+        assert_eq!(
+            (run.platform_id, run.region_id, run.emulated),
+            (None, None, false)
+        );
+
+        let invalid =
+            api_run(json!({"platform": "PS2", "emulated": false, "region": null}));
+        assert_eq!(invalid.normalize().unwrap_err().kind(), "invalid-id");
+    }
+}
@@ -0,0 +1,1012 @@
+//! Runs the downloader against a local stand-in for the speedrun.com API, serving
+//! paginated listings built from our fixture data.
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs::File,
+    io::{prelude::*, BufRead, BufReader, BufWriter},
+    net::{TcpListener, TcpStream},
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use flate2::write::GzEncoder;
+use serde::Deserialize;
+use serde_json::{json, Deserializer as JsonDeserializer, Value as JsonValue};
+use tempfile::TempDir;
+
+use speedruns_api::cli::download::{
+    archive::ArchiveMode,
+    mirror_files,
+    retry::RetryPolicy,
+    snapshots::{Manifest, Snapshots},
+    state::{ResourceSyncState, SyncCursor, SyncDirection, SyncState, SYNC_STATE_FILE},
+    store,
+    tombstones::{Tombstones, TOMBSTONES_FILE},
+    Config, Spider,
+};
+use speedruns_api::types::{Platform, Region, Series, Variable};
+use speedruns_utils::base36;
+
+/// The resources we have fixture data for.
+const FIXTURE_RESOURCES: [&str; 3] = ["games", "users", "runs"];
+
+/// Every resource the stand-in lists.
+const RESOURCES: [&str; 6] = ["games", "users", "runs", "platforms", "regions", "series"];
+
+/// The resources that are downloaded incrementally, with a sync state.
+const INCREMENTAL_RESOURCES: [&str; 4] = ["games", "users", "runs", "series"];
+
+/// A request for a page of a resource listing, or for a single record.
+#[derive(Debug, Clone)]
+struct Request {
+    resource: String,
+    /// The record requested, if this isn't a listing request.
+    id: Option<String>,
+    /// Whether the listing was requested oldest-first.
+    ascending: bool,
+    /// The game ID the listing was filtered to, if any.
+    game: Option<String>,
+    offset: usize,
+    max: usize,
+    /// How many requests for this resource preceded this one.
+    count: usize,
+}
+
+/// What the stand-in API should send instead of the page that was requested.
+enum Fault {
+    Status(u16, String),
+    /// A rate-limiting status with a Retry-After header of the given seconds.
+    RetryAfter(u16, u64),
+    Page(Vec<JsonValue>, bool),
+}
+
+type Hook = Box<dyn FnMut(&Request, &mut Listings) -> Option<Fault> + Send>;
+
+/// Every resource listing the stand-in serves, newest first.
+#[derive(Debug, Default, Clone)]
+struct Listings(HashMap<String, Vec<JsonValue>>);
+
+impl Listings {
+    fn ids(&self, resource: &str) -> BTreeSet<String> {
+        self.0[resource].iter().map(id_of).collect()
+    }
+}
+
+struct StandIn {
+    base_url: String,
+    listings: Arc<Mutex<Listings>>,
+}
+
+impl StandIn {
+    fn start(hook: Hook) -> StandIn {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/api/v1/", listener.local_addr().unwrap());
+        let listings = Arc::new(Mutex::new(fixture_listings()));
+
+        let served = listings.clone();
+        let mut hook = hook;
+        let mut counts = HashMap::<String, usize>::new();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let request = match read_request(&stream) {
+                    Some(request) => request,
+                    None => continue,
+                };
+                let count = counts.entry(request.resource.clone()).or_insert(0);
+                let request = Request {
+                    count: *count,
+                    ..request
+                };
+                *count += 1;
+
+                let mut listings = served.lock().unwrap();
+                let mut headers = String::new();
+                let (status, body) = match hook(&request, &mut listings) {
+                    Some(Fault::Status(status, body)) => (status, body),
+                    Some(Fault::RetryAfter(status, seconds)) => {
+                        headers = format!("Retry-After: {}\r\n", seconds);
+                        (status, "{}".to_string())
+                    }
+                    Some(Fault::Page(items, has_next)) => {
+                        (200, page_body(&request, items, has_next))
+                    }
+                    None => match &request.id {
+                        Some(id) => match listings.0[&request.resource]
+                            .iter()
+                            .find(|item| id_of(item) == *id || item["abbreviation"] == *id)
+                        {
+                            Some(item) => (200, json!({ "data": item }).to_string()),
+                            None => (404, r#"{"status":404}"#.to_string()),
+                        },
+                        None => {
+                            let mut listing = listings.0[&request.resource].clone();
+                            if let Some(game) = &request.game {
+                                listing.retain(|item| item["game"] == *game);
+                            }
+                            if request.ascending {
+                                listing.reverse();
+                            }
+                            let start = request.offset.min(listing.len());
+                            let end = (request.offset + request.max).min(listing.len());
+                            let items = listing[start..end].to_vec();
+                            (200, page_body(&request, items, end < listing.len()))
+                        }
+                    },
+                };
+                drop(listings);
+
+                write_response(&mut stream, status, &headers, &body);
+            }
+        });
+
+        StandIn { base_url, listings }
+    }
+
+    fn listings(&self) -> Listings {
+        self.listings.lock().unwrap().clone()
+    }
+}
+
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).ok()?;
+        if header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let target = request_line.split_whitespace().nth(1)?;
+    let mut parts = target.splitn(2, '?');
+    let mut path = parts.next()?.trim_start_matches("/api/v1/").splitn(2, '/');
+    let resource = path.next()?.to_string();
+    let id = path.next().map(str::to_string);
+    let query: HashMap<&str, &str> = parts
+        .next()
+        .unwrap_or("")
+        .split('&')
+        .filter_map(|pair| {
+            let mut pair = pair.splitn(2, '=');
+            Some((pair.next()?, pair.next().unwrap_or("")))
+        })
+        .collect();
+
+    let (offset, max) = match id {
+        Some(_) => (0, 0),
+        None => (
+            query.get("offset")?.parse().ok()?,
+            query.get("max")?.parse().ok()?,
+        ),
+    };
+
+    Some(Request {
+        resource,
+        id,
+        ascending: query.get("direction") == Some(&"asc"),
+        game: query.get("game").map(|game| game.to_string()),
+        offset,
+        max,
+        count: 0,
+    })
+}
+
+fn page_body(request: &Request, items: Vec<JsonValue>, has_next: bool) -> String {
+    let mut links = vec![];
+    if request.offset > 0 {
+        links.push(json!({ "rel": "prev", "uri": "" }));
+    }
+    if has_next {
+        links.push(json!({ "rel": "next", "uri": "" }));
+    }
+    json!({
+        "data": items,
+        "pagination": {
+            "offset": request.offset,
+            "max": request.max,
+            "size": items.len(),
+            "links": links,
+        },
+    })
+    .to_string()
+}
+
+fn write_response(stream: &mut TcpStream, status: u16, headers: &str, body: &str) {
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {} Stand-In\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}",
+        status,
+        body.len(),
+        headers,
+        body
+    );
+}
+
+/// Our fixture records, dressed up as API items with speedrun.com-style IDs and
+/// the API's names for the fields we follow references through.
+fn fixture_listings() -> Listings {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../../data/fixture");
+    let mut listings = Listings::default();
+    for resource in FIXTURE_RESOURCES.iter() {
+        let file = File::open(fixture.join(format!("{}.jsonl", resource))).unwrap();
+        let mut items: Vec<JsonValue> = JsonDeserializer::from_reader(BufReader::new(file))
+            .into_iter::<JsonValue>()
+            .map(Result::unwrap)
+            .map(|mut item| {
+                item["id"] = base36(item["id"].as_u64().unwrap()).into();
+                if let Some(game_id) = item["game_id"].as_u64() {
+                    item["game"] = base36(game_id).into();
+                }
+                if item.get("players").is_some() {
+                    item["players"] = item["players"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|player| match player["UserId"].as_u64() {
+                            Some(user_id) => {
+                                json!({ "rel": "user", "id": base36(user_id) })
+                            }
+                            None => json!({ "rel": "guest", "name": player["GuestName"] }),
+                        })
+                        .collect();
+                }
+                if *resource == "games" {
+                    item["abbreviation"] = item["slug"].clone();
+                }
+                item
+            })
+            .collect();
+        items.sort_by(|a, b| {
+            (b["created"].as_str(), id_of(b)).cmp(&(a["created"].as_str(), id_of(a)))
+        });
+        listings.0.insert(resource.to_string(), items);
+    }
+
+    // We don't have fixtures for these, so we make up a few.
+    let link = |rel: &str| json!({ "rel": rel, "uri": "https://www.speedrun.com/api/v1/" });
+    listings.0.insert(
+        "platforms".to_string(),
+        vec![
+            json!({ "id": "8gej2n93", "name": "PC", "released": 1981, "links": [link("self")] }),
+            json!({ "id": "w89rwelk", "name": "Nintendo 64", "released": 1996, "links": [link("self")] }),
+        ],
+    );
+    listings.0.insert(
+        "regions".to_string(),
+        vec![
+            json!({ "id": "pr184lqn", "name": "USA / NTSC", "links": [link("self")] }),
+            json!({ "id": "e6lxy1dz", "name": "EUR / PAL", "links": [link("self")] }),
+        ],
+    );
+    listings.0.insert(
+        "series".to_string(),
+        vec![json!({
+            "id": "rv7emz49",
+            "names": { "international": "Warcraft", "japanese": null, "twitch": null },
+            "abbreviation": "warcraft",
+            "weblink": "https://www.speedrun.com/series/warcraft",
+            "created": "2016-05-04T19:10:39Z",
+            "assets": { "logo": { "uri": "https://www.speedrun.com/logo.png", "width": 128, "height": 128 } },
+            "moderators": {},
+            "links": [link("self"), link("games")],
+        })],
+    );
+    let game = &mut listings.0.get_mut("games").unwrap()[0];
+    game["variables"] = json!({
+        "data": [{
+            "id": "68km3w4l",
+            "name": "Difficulty",
+            "category": null,
+            "scope": { "type": "full-game" },
+            "mandatory": true,
+            "user-defined": false,
+            "obsoletes": true,
+            "values": {
+                "_note": "`choices` is deprecated, please use `values` instead",
+                "choices": { "4qye4731": "Normal" },
+                "values": { "4qye4731": { "label": "Normal", "rules": null, "flags": { "miscellaneous": false } } },
+                "default": "4qye4731"
+            },
+            "is-subcategory": true,
+            "links": [link("self"), link("game")],
+        }]
+    });
+
+    listings
+}
+
+fn id_of(item: &JsonValue) -> String {
+    item["id"].as_str().unwrap().to_string()
+}
+
+fn config(stand_in: &StandIn, data_dir: &TempDir) -> Config {
+    Config {
+        base_url: stand_in.base_url.clone(),
+        data_dir: data_dir.path().to_path_buf(),
+        concurrency: 4,
+        requests_per_second: 0.0,
+        retry: RetryPolicy {
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(10),
+            ..RetryPolicy::default()
+        },
+        // small enough that every resource is written in several segments
+        flush_records: 50,
+        archive: ArchiveMode::Off,
+    }
+}
+
+fn load_items(data_dir: &TempDir, resource: &str) -> Vec<JsonValue> {
+    store::read(data_dir.path(), resource)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+}
+
+fn saved_ids(data_dir: &TempDir, resource: &str) -> BTreeSet<String> {
+    load_items(data_dir, resource).iter().map(id_of).collect()
+}
+
+/// Saves items the way we did before we had stores, as the downloader should
+/// move them into one.
+fn save_items(data_dir: &TempDir, resource: &str, items: &[JsonValue]) {
+    let file =
+        File::create(data_dir.path().join(format!("{}.jsonl.gz", resource))).unwrap();
+    let mut compressor = GzEncoder::new(BufWriter::new(file), flate2::Compression::fast());
+    for item in items {
+        serde_json::to_writer(&mut compressor, item).unwrap();
+        compressor.write_all(b"\n").unwrap();
+    }
+    compressor.finish().unwrap();
+}
+
+/// Asserts that every resource was downloaded in full, and that the sync
+/// state shows every pass as having finished.
+fn assert_mirrored(stand_in: &StandIn, data_dir: &TempDir) {
+    let listings = stand_in.listings();
+    for resource in RESOURCES.iter() {
+        assert_eq!(
+            listings.ids(resource),
+            saved_ids(data_dir, resource),
+            "{} should be mirrored exactly",
+            resource
+        );
+    }
+
+    let state = SyncState::load(data_dir.path().join(SYNC_STATE_FILE)).unwrap();
+    for resource in INCREMENTAL_RESOURCES.iter() {
+        assert_eq!(state.resources[*resource].cursor, SyncCursor::Complete);
+    }
+}
+
+/// A copy of an item with a new ID, as if it had just been submitted.
+fn new_item(template: &JsonValue, n: u64) -> JsonValue {
+    let mut item = template.clone();
+    item["id"] = format!("new{:05}", n).into();
+    item
+}
+
+#[test]
+fn test_download_from_scratch() {
+    let stand_in = StandIn::start(Box::new(|_, _| None));
+    let data_dir = TempDir::new().unwrap();
+
+    Spider::load_or_create(config(&stand_in, &data_dir))
+        .run()
+        .unwrap();
+
+    assert_mirrored(&stand_in, &data_dir);
+}
+
+#[test]
+fn test_download_retries_error_responses() {
+    let stand_in = StandIn::start(Box::new(|request, _| {
+        if request.resource != "runs" {
+            return None;
+        }
+        match request.count {
+            0 => Some(Fault::Status(500, "{}".to_string())),
+            2 => Some(Fault::Status(503, "<html>busy</html>".to_string())),
+            3 => Some(Fault::Status(200, "not json".to_string())),
+            5 => Some(Fault::Status(420, r#"{"status":420}"#.to_string())),
+            _ => None,
+        }
+    }));
+    let data_dir = TempDir::new().unwrap();
+
+    Spider::load_or_create(config(&stand_in, &data_dir))
+        .run()
+        .unwrap();
+
+    assert_mirrored(&stand_in, &data_dir);
+}
+
+#[test]
+fn test_download_additional_resources() {
+    let stand_in = StandIn::start(Box::new(|_, _| None));
+    let data_dir = TempDir::new().unwrap();
+
+    Spider::load_or_create(config(&stand_in, &data_dir))
+        .run()
+        .unwrap();
+
+    assert_mirrored(&stand_in, &data_dir);
+
+    for platform in load_items(&data_dir, "platforms") {
+        Platform::deserialize(platform).unwrap();
+    }
+    for region in load_items(&data_dir, "regions") {
+        Region::deserialize(region).unwrap();
+    }
+    for series in load_items(&data_dir, "series") {
+        Series::deserialize(series).unwrap();
+    }
+
+    let variables = load_items(&data_dir, "variables");
+    assert_eq!(variables.len(), 1);
+    let variable = Variable::deserialize(variables[0].clone()).unwrap();
+    assert_eq!(variable.name(), "Difficulty");
+}
+
+#[test]
+fn test_download_waits_when_rate_limited() {
+    let stand_in = StandIn::start(Box::new(|request, _| {
+        if request.resource == "users" && request.count < 2 {
+            Some(Fault::RetryAfter(429, 0))
+        } else {
+            None
+        }
+    }));
+    let data_dir = TempDir::new().unwrap();
+
+    let mut spider = Spider::load_or_create(config(&stand_in, &data_dir));
+    spider.run().unwrap();
+
+    assert_mirrored(&stand_in, &data_dir);
+    let stats = &spider.retry_stats()["users"];
+    assert_eq!(stats.rate_limited, 2);
+    assert_eq!(stats.retries, 2);
+    assert_eq!(stats.abandoned, 0);
+    assert_eq!(stats.waited, Duration::from_secs(0));
+}
+
+#[test]
+fn test_download_gives_up_on_permanent_errors() {
+    let stand_in = StandIn::start(Box::new(|request, _| {
+        if request.resource == "users" {
+            Some(Fault::Status(404, r#"{"status":404}"#.to_string()))
+        } else {
+            None
+        }
+    }));
+    let data_dir = TempDir::new().unwrap();
+
+    let mut spider = Spider::load_or_create(config(&stand_in, &data_dir));
+    assert!(spider.run().is_err());
+
+    // every request failed, and none of them were retried
+    let stats = &spider.retry_stats()["users"];
+    assert!(stats.permanent_errors > 0);
+    assert_eq!(stats.permanent_errors, stats.requests);
+    assert_eq!(stats.abandoned, stats.requests);
+    assert_eq!(stats.retries, 0);
+
+    // the resources we'd already finished are still saved
+    assert_eq!(
+        saved_ids(&data_dir, "games").len(),
+        stand_in.listings.lock().unwrap().0["games"].len()
+    );
+}
+
+#[test]
+fn test_download_gives_up_after_max_attempts() {
+    let stand_in = StandIn::start(Box::new(|request, _| {
+        if request.resource == "runs" && request.count > 0 {
+            Some(Fault::Status(500, "{}".to_string()))
+        } else {
+            None
+        }
+    }));
+    let data_dir = TempDir::new().unwrap();
+
+    // one request at a time, so that the first page is the one that succeeds
+    let mut config = config(&stand_in, &data_dir);
+    config.concurrency = 1;
+    config.retry.max_attempts = 3;
+    let mut spider = Spider::load_or_create(config.clone());
+    assert!(spider.run().is_err());
+
+    let stats = &spider.retry_stats()["runs"];
+    assert_eq!(stats.server_errors, 3);
+    assert_eq!(stats.retries, 2);
+    assert_eq!(stats.abandoned, 1);
+
+    // we checkpointed the first page before giving up, so we'll resume from it
+    let state = SyncState::load(data_dir.path().join(SYNC_STATE_FILE)).unwrap();
+    let progress = &state.resources["runs"];
+    assert_ne!(progress.cursor, SyncCursor::Complete);
+    assert!(!progress.last_seen_ids.is_empty());
+}
+
+#[test]
+fn test_download_stays_within_request_budget() {
+    let stand_in = StandIn::start(Box::new(|_, _| None));
+    let data_dir = TempDir::new().unwrap();
+
+    let mut config = config(&stand_in, &data_dir);
+    config.requests_per_second = 100.0;
+    let mut spider = Spider::load_or_create(config);
+    let start = Instant::now();
+    spider.run().unwrap();
+    let elapsed = start.elapsed();
+
+    assert_mirrored(&stand_in, &data_dir);
+    let requests: u64 = spider
+        .retry_stats()
+        .values()
+        .map(|stats| stats.requests)
+        .sum();
+    assert!(
+        elapsed >= Duration::from_millis(10 * (requests - 1)),
+        "{} requests in {:?}",
+        requests,
+        elapsed
+    );
+}
+
+#[test]
+fn test_download_short_pages() {
+    let stand_in = StandIn::start(Box::new(|request, listings| {
+        let listing = &listings.0[&request.resource];
+        let start = request.offset.min(listing.len());
+        let end = (request.offset + request.max / 3).min(listing.len());
+        Some(Fault::Page(
+            listing[start..end].to_vec(),
+            end < listing.len(),
+        ))
+    }));
+    let data_dir = TempDir::new().unwrap();
+
+    Spider::load_or_create(config(&stand_in, &data_dir))
+        .run()
+        .unwrap();
+
+    assert_mirrored(&stand_in, &data_dir);
+}
+
+#[test]
+fn test_download_duplicate_pages() {
+    let mut stale = HashSet::new();
+    let stand_in = StandIn::start(Box::new(move |request, listings| {
+        if request.resource != "runs"
+            || request.offset == 0
+            || !stale.insert(request.offset)
+        {
+            return None;
+        }
+        // the first time each page is requested, serve the previous page again instead
+        let listing = &listings.0[&request.resource];
+        let end = request.offset.min(listing.len());
+        let start = request.offset.saturating_sub(request.max).min(end);
+        Some(Fault::Page(listing[start..end].to_vec(), true))
+    }));
+    let data_dir = TempDir::new().unwrap();
+
+    Spider::load_or_create(config(&stand_in, &data_dir))
+        .run()
+        .unwrap();
+
+    assert_mirrored(&stand_in, &data_dir);
+}
+
+#[test]
+fn test_download_new_items_mid_crawl() {
+    let stand_in = StandIn::start(Box::new(|request, listings| {
+        if request.resource == "runs" && request.count == 2 {
+            let runs = listings.0.get_mut("runs").unwrap();
+            let new: Vec<JsonValue> = (0..7).map(|n| new_item(&runs[0], n)).collect();
+            runs.splice(0..0, new);
+        }
+        None
+    }));
+    let data_dir = TempDir::new().unwrap();
+
+    Spider::load_or_create(config(&stand_in, &data_dir))
+        .run()
+        .unwrap();
+
+    assert_mirrored(&stand_in, &data_dir);
+    assert!(saved_ids(&data_dir, "runs").contains("new00006"));
+}
+
+#[test]
+fn test_download_resumes_interrupted_sync() {
+    let stand_in = StandIn::start(Box::new(|_, _| None));
+    let data_dir = TempDir::new().unwrap();
+
+    // As if we'd previously had the oldest runs, and were interrupted after
+    // storing the first two pages of newer ones...
+    let runs = stand_in.listings().0["runs"].clone();
+    let mut stored: Vec<JsonValue> = runs[..400].to_vec();
+    stored.extend_from_slice(&runs[runs.len() - 100..]);
+    save_items(&data_dir, "runs", &stored);
+
+    let mut state = SyncState::default();
+    state.resources.insert(
+        "runs".to_string(),
+        ResourceSyncState {
+            offset: 400,
+            last_seen_ids: runs[200..400].iter().map(id_of).collect(),
+            covered: 500,
+            ..ResourceSyncState::new(SyncDirection::Desc)
+        },
+    );
+    state.save(data_dir.path().join(SYNC_STATE_FILE)).unwrap();
+
+    // ...and then more runs were submitted before we started again.
+    {
+        let mut listings = stand_in.listings.lock().unwrap();
+        let runs = listings.0.get_mut("runs").unwrap();
+        let new: Vec<JsonValue> = (0..3).map(|n| new_item(&runs[0], n)).collect();
+        runs.splice(0..0, new);
+    }
+
+    Spider::load_or_create(config(&stand_in, &data_dir))
+        .run()
+        .unwrap();
+
+    assert_mirrored(&stand_in, &data_dir);
+    // the runs we had were moved into the store
+    assert!(!store::legacy_path(data_dir.path(), "runs").exists());
+}
+
+#[test]
+fn test_reconcile_marks_deleted_records() {
+    let stand_in = StandIn::start(Box::new(|_, _| None));
+    let data_dir = TempDir::new().unwrap();
+
+    let mut spider = Spider::load_or_create(config(&stand_in, &data_dir));
+    spider.run().unwrap();
+
+    let (deleted, changed) = {
+        let mut listings = stand_in.listings.lock().unwrap();
+        let runs = listings.0.get_mut("runs").unwrap();
+        let deleted = runs.remove(runs.len() / 2);
+        runs[0]["comment"] = "edited".into();
+        (id_of(&deleted), id_of(&runs[0]))
+    };
+
+    let results = spider.reconcile().unwrap();
+    assert_eq!(results["runs"].deleted, 1);
+    assert_eq!(results["runs"].changed, 1);
+    assert_eq!(results["runs"].added, 0);
+    assert_eq!(results["games"].deleted, 0);
+
+    // the deleted run is kept, but marked
+    assert!(saved_ids(&data_dir, "runs").contains(&deleted));
+    let tombstones = Tombstones::load(data_dir.path().join(TOMBSTONES_FILE)).unwrap();
+    assert!(tombstones.contains("runs", &deleted));
+    assert_eq!(tombstones.resources["runs"].len(), 1);
+
+    let saved = load_items(&data_dir, "runs");
+    let edited = saved.iter().find(|item| id_of(item) == changed).unwrap();
+    assert_eq!(edited["comment"], "edited");
+}
+
+#[test]
+fn test_reconcile_confirms_records_skipped_by_the_sweep() {
+    // deleting a record we've already swept past shifts the first record of
+    // the next page back into the page we've already fetched, so we never see
+    // it in the listing. Pages are requested concurrently, so we wait until
+    // the first one has been served.
+    let mut swept_first_page = false;
+    let mut deleted = false;
+    let stand_in = StandIn::start(Box::new(move |request, listings| {
+        if request.resource == "runs" && request.ascending && request.id.is_none() {
+            if request.offset == 0 {
+                swept_first_page = true;
+            } else if swept_first_page && !deleted {
+                let runs = listings.0.get_mut("runs").unwrap();
+                let oldest = runs.len() - 1;
+                runs.remove(oldest);
+                deleted = true;
+            }
+        }
+        None
+    }));
+    let data_dir = TempDir::new().unwrap();
+
+    let mut spider = Spider::load_or_create(config(&stand_in, &data_dir));
+    spider.run().unwrap();
+    let results = spider.reconcile().unwrap();
+
+    assert_eq!(results["runs"].seen, stand_in.listings().ids("runs").len());
+    assert_eq!(results["runs"].deleted, 0);
+    assert!(!spider.tombstones().resources.contains_key("runs"));
+}
+
+#[test]
+fn test_download_restores_reappearing_records() {
+    let stand_in = StandIn::start(Box::new(|_, _| None));
+    let data_dir = TempDir::new().unwrap();
+
+    let mut spider = Spider::load_or_create(config(&stand_in, &data_dir));
+    spider.run().unwrap();
+
+    let removed = stand_in
+        .listings
+        .lock()
+        .unwrap()
+        .0
+        .get_mut("users")
+        .unwrap()
+        .remove(0);
+    spider.reconcile().unwrap();
+    assert!(spider.tombstones().contains("users", &id_of(&removed)));
+
+    stand_in
+        .listings
+        .lock()
+        .unwrap()
+        .0
+        .get_mut("users")
+        .unwrap()
+        .insert(0, removed.clone());
+    let mut spider = Spider::load_or_create(config(&stand_in, &data_dir));
+    spider.run().unwrap();
+
+    assert!(!spider.tombstones().contains("users", &id_of(&removed)));
+    let tombstones = Tombstones::load(data_dir.path().join(TOMBSTONES_FILE)).unwrap();
+    assert!(!tombstones.contains("users", &id_of(&removed)));
+}
+
+#[test]
+fn test_download_single_games() {
+    let stand_in = StandIn::start(Box::new(|_, _| None));
+    let data_dir = TempDir::new().unwrap();
+    let listings = stand_in.listings();
+
+    // an unrelated record we already have, which shouldn't be clobbered
+    let unrelated = json!({ "id": "unrelated", "name": "someone else" });
+    save_items(&data_dir, "users", &[unrelated]);
+
+    let mut spider = Spider::load_or_create(config(&stand_in, &data_dir));
+    spider
+        .run_games(&["wc2".to_string(), "bpr".to_string()])
+        .unwrap();
+
+    let game_ids: BTreeSet<String> = listings.0["games"]
+        .iter()
+        .filter(|game| game["abbreviation"] == "wc2" || game["abbreviation"] == "bpr")
+        .map(id_of)
+        .collect();
+    assert_eq!(saved_ids(&data_dir, "games"), game_ids);
+
+    let runs: Vec<&JsonValue> = listings.0["runs"]
+        .iter()
+        .filter(|run| game_ids.contains(run["game"].as_str().unwrap()))
+        .collect();
+    assert!(!runs.is_empty());
+    assert_eq!(
+        saved_ids(&data_dir, "runs"),
+        runs.iter().map(|run| id_of(run)).collect()
+    );
+
+    let mut user_ids: BTreeSet<String> = runs
+        .iter()
+        .flat_map(|run| run["players"].as_array().unwrap())
+        .filter_map(|player| player["id"].as_str())
+        .map(str::to_string)
+        .collect();
+    assert!(user_ids.len() < listings.0["users"].len());
+    user_ids.insert("unrelated".to_string());
+    assert_eq!(saved_ids(&data_dir, "users"), user_ids);
+}
+
+#[test]
+fn test_download_single_games_then_everything() {
+    let stand_in = StandIn::start(Box::new(|_, _| None));
+    let data_dir = TempDir::new().unwrap();
+
+    // a full page of the newest runs are from one game, and so are some of the
+    // oldest, with runs of other games in between
+    {
+        let mut listings = stand_in.listings.lock().unwrap();
+        let bpr = id_of(
+            listings.0["games"]
+                .iter()
+                .find(|game| game["abbreviation"] == "bpr")
+                .unwrap(),
+        );
+        let runs = listings.0.get_mut("runs").unwrap();
+        let (mut game_runs, other_runs): (Vec<JsonValue>, Vec<JsonValue>) =
+            runs.drain(..).partition(|run| run["game"] == *bpr);
+        assert!(game_runs.len() > 200);
+        let oldest_game_runs = game_runs.split_off(200);
+        runs.extend(game_runs);
+        runs.extend(other_runs);
+        runs.extend(oldest_game_runs);
+    }
+
+    // that game's runs and users shouldn't count as part of the listings that
+    // a full sync has covered, or it would skip as many of the others
+    let mut spider = Spider::load_or_create(config(&stand_in, &data_dir));
+    spider.run_games(&["bpr".to_string()]).unwrap();
+
+    Spider::load_or_create(config(&stand_in, &data_dir))
+        .run()
+        .unwrap();
+
+    assert_mirrored(&stand_in, &data_dir);
+    let state = SyncState::load(data_dir.path().join(SYNC_STATE_FILE)).unwrap();
+    let listings = stand_in.listings();
+    for resource in INCREMENTAL_RESOURCES.iter() {
+        assert_eq!(
+            state.resources[*resource].covered,
+            listings.0[*resource].len(),
+            "{}",
+            resource
+        );
+    }
+}
+
+#[test]
+fn test_download_replays_archived_responses() {
+    let stand_in = StandIn::start(Box::new(|request, _| {
+        if request.resource == "users" && request.count < 1 {
+            Some(Fault::RetryAfter(429, 0))
+        } else {
+            None
+        }
+    }));
+    let responses = TempDir::new().unwrap();
+    let recorded_dir = TempDir::new().unwrap();
+    let mut recorded = config(&stand_in, &recorded_dir);
+    recorded.archive = ArchiveMode::Record(responses.path().to_path_buf());
+    let mut recorder = Spider::load_or_create(recorded);
+    recorder.run().unwrap();
+    assert_mirrored(&stand_in, &recorded_dir);
+
+    // nothing is listening here, so everything has to come from the archive
+    let replayed_dir = TempDir::new().unwrap();
+    let replayed = Config {
+        base_url: "http://127.0.0.1:9/".to_string(),
+        archive: ArchiveMode::Replay(responses.path().to_path_buf()),
+        ..config(&stand_in, &replayed_dir)
+    };
+    let mut spider = Spider::load_or_create(replayed);
+    spider.run().unwrap();
+
+    for resource in RESOURCES.iter().chain(&["variables"]) {
+        assert_eq!(
+            load_items(&replayed_dir, resource),
+            load_items(&recorded_dir, resource),
+            "{} should be replayed exactly",
+            resource
+        );
+    }
+    // the rate-limited response is replayed before the one that succeeded
+    assert_eq!(spider.retry_stats()["users"].rate_limited, 1);
+    assert_eq!(
+        spider.retry_stats()["users"].requests,
+        recorder.retry_stats()["users"].requests
+    );
+
+    // anything that wasn't recorded can't be replayed
+    let empty = TempDir::new().unwrap();
+    let mut spider = Spider::load_or_create(Config {
+        archive: ArchiveMode::Replay(empty.path().to_path_buf()),
+        ..config(&stand_in, &TempDir::new().unwrap())
+    });
+    assert!(spider.run().is_err());
+    let stats = &spider.retry_stats()["games"];
+    assert_eq!(stats.abandoned, stats.requests);
+    assert_eq!(stats.retries, 0);
+}
+
+#[test]
+fn test_download_unknown_game() {
+    let stand_in = StandIn::start(Box::new(|_, _| None));
+    let data_dir = TempDir::new().unwrap();
+
+    let mut spider = Spider::load_or_create(config(&stand_in, &data_dir));
+    assert!(spider.run_games(&["nonexistent".to_string()]).is_err());
+}
+
+#[test]
+fn test_snapshots_are_content_addressed() {
+    let stand_in = StandIn::start(Box::new(|_, _| None));
+    let data_dir = TempDir::new().unwrap();
+
+    let mut spider = Spider::load_or_create(config(&stand_in, &data_dir));
+    spider.run().unwrap();
+    let first = spider.snapshot().unwrap();
+    for resource in RESOURCES.iter() {
+        assert_eq!(
+            first.resources()[*resource],
+            stand_in.listings().ids(resource).len()
+        );
+    }
+    assert_eq!(first.files[SYNC_STATE_FILE].records, None);
+
+    // nothing has changed, so there's nothing new to snapshot
+    assert_eq!(spider.snapshot().unwrap(), first);
+
+    {
+        let mut listings = stand_in.listings.lock().unwrap();
+        let runs = listings.0.get_mut("runs").unwrap();
+        let new = new_item(&runs[0], 0);
+        runs.insert(0, new);
+    }
+    spider.run().unwrap();
+    let second = spider.snapshot().unwrap();
+
+    assert_ne!(second.id, first.id);
+    assert_eq!(second.resources()["runs"], first.resources()["runs"] + 1);
+    // unchanged files are shared between snapshots
+    let games = |manifest: &Manifest| -> Vec<(String, String)> {
+        manifest
+            .files
+            .iter()
+            .filter(|(name, _)| name.starts_with("games/"))
+            .map(|(name, file)| (name.clone(), file.hash.clone()))
+            .collect()
+    };
+    assert!(!games(&first).is_empty());
+    assert_eq!(games(&second), games(&first));
+
+    let snapshots = Snapshots::new(data_dir.path());
+    assert_eq!(snapshots.list().unwrap(), vec![first, second]);
+}
+
+#[test]
+fn test_snapshots_restore_and_prune() {
+    let stand_in = StandIn::start(Box::new(|_, _| None));
+    let data_dir = TempDir::new().unwrap();
+    let snapshots = Snapshots::new(data_dir.path());
+
+    let mut spider = Spider::load_or_create(config(&stand_in, &data_dir));
+    spider.run().unwrap();
+    let first = spider.snapshot().unwrap();
+    let first_runs = saved_ids(&data_dir, "runs");
+
+    {
+        let mut listings = stand_in.listings.lock().unwrap();
+        let runs = listings.0.get_mut("runs").unwrap();
+        runs.remove(runs.len() / 2);
+    }
+    spider.reconcile().unwrap();
+    let second = spider.snapshot().unwrap();
+    let tombstones = || Tombstones::load(data_dir.path().join(TOMBSTONES_FILE)).unwrap();
+    assert_eq!(tombstones().resources["runs"].len(), 1);
+
+    // restoring the first snapshot brings back the files from before we
+    // found the deleted run
+    snapshots
+        .restore(
+            &first.id[..first.id.len() - 2],
+            &mirror_files(data_dir.path()).unwrap(),
+        )
+        .unwrap();
+    assert_eq!(saved_ids(&data_dir, "runs"), first_runs);
+    assert!(!tombstones().resources.contains_key("runs"));
+
+    let pruned = snapshots.prune(1).unwrap();
+    assert_eq!(pruned, vec![first.clone()]);
+    assert_eq!(snapshots.list().unwrap(), vec![second.clone()]);
+    assert!(snapshots
+        .restore(&first.id, &mirror_files(data_dir.path()).unwrap())
+        .is_err());
+
+    // the files the remaining snapshot uses are still there
+    snapshots
+        .restore(&second.id, &mirror_files(data_dir.path()).unwrap())
+        .unwrap();
+    assert_eq!(tombstones().resources["runs"].len(), 1);
+    assert_eq!(saved_ids(&data_dir, "runs").len(), first_runs.len());
+}
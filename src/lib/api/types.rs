@@ -324,6 +324,20 @@ pub struct RunVideos {
     text: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Getters)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+#[get = "pub"]
+pub struct Series {
+    abbreviation: String,
+    assets: HashMap<String, Option<GameAsset>>,
+    created: Option<DateTime<Utc>>,
+    id: String,
+    links: Vec<Link>,
+    moderators: HashMap<String, GameModeratorType>,
+    names: Names,
+    weblink: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Getters, Deref)]
 #[serde(deny_unknown_fields, rename_all = "kebab-case")]
 #[get = "pub"]
@@ -339,7 +353,7 @@ pub struct User {
     id: String,
     links: Vec<Link>,
     location: Option<UserLocation>,
-    name_style: UserNameStyle,
+    name_style: Option<UserNameStyle>,
     names: Names,
     role: UserRole,
     signup: Option<DateTime<Utc>>,
@@ -9,7 +9,7 @@ use std::error::Error;
 
 use log::warn;
 
-use speedruns_api::cli::{download, import};
+use speedruns_api::cli::{diff, download, import, snapshots};
 use speedruns_juniper::cli as juniper_cli;
 
 #[derive(argh::FromArgs, PartialEq, Debug)]
@@ -31,19 +31,13 @@ pub struct Args {
 #[derive(argh::FromArgs, PartialEq, Debug)]
 #[argh(subcommand)]
 pub enum Subcommand {
-    Download(DownloadArgs),
+    Download(download::Args),
     Import(import::Args),
+    Snapshots(snapshots::Args),
     Serve(juniper_cli::Args),
+    Diff(diff::Args),
 }
 
-#[derive(argh::FromArgs, PartialEq, Debug)]
-/// Fetches/updates a local mirror of speedrun.com API content. This just stores the JSON
-/// representation of each item as-is, it doesn't make any assumptions about their structure
-/// beyond the existence of  a string "id" value. This stores everything in-memory, it's not
-/// memory-efficient.
-#[argh(subcommand, name = "download")]
-pub struct DownloadArgs {}
-
 pub async fn main() -> Result<(), Box<dyn Error>> {
     let args: Args = argh::from_env();
 
@@ -58,15 +52,21 @@ pub async fn main() -> Result<(), Box<dyn Error>> {
     pretty_env_logger::init();
 
     match args.subcommand {
-        Subcommand::Download(_args) => {
-            download::main()?;
+        Subcommand::Download(args) => {
+            download::main(args)?;
         }
         Subcommand::Import(args) => {
             import::main(args)?;
         }
+        Subcommand::Snapshots(args) => {
+            snapshots::main(args)?;
+        }
         Subcommand::Serve(args) => {
             juniper_cli::main(args).await?;
         }
+        Subcommand::Diff(args) => {
+            diff::main(args)?;
+        }
     }
 
     Ok(())
@@ -9,13 +9,19 @@ use serde::{Deserialize, Serialize};
 
 use log::error;
 
-use speedruns_models::{Category, CategoryType, Game, Level, Run, User};
+use speedruns_models::{
+    Category, CategoryType, Game, GameModerator, GameTag, Level, Platform, Region, Run,
+    User, Variable,
+};
 
 #[macro_use]
 extern crate rental;
 
 mod integrity;
-pub use integrity::{validate, IntegrityError, IntegrityErrors};
+pub use integrity::{
+    has_wrong_player_count, user_precedence, validate, validate_game_moderator,
+    validate_run, IntegrityError, IntegrityErrors, RunReferences,
+};
 
 #[derive(Debug, Clone)]
 pub struct Database(rentals::Database);
@@ -39,6 +45,25 @@ pub struct Tables {
     runs: HashMap<u64, Run>,
     users: HashMap<u64, User>,
     levels: HashMap<u64, Level>,
+    #[serde(default)]
+    variables: HashMap<u64, Variable>,
+    #[serde(default)]
+    platforms: HashMap<u64, Platform>,
+    #[serde(default)]
+    regions: HashMap<u64, Region>,
+    #[serde(default)]
+    developers: HashMap<u64, GameTag>,
+    #[serde(default)]
+    publishers: HashMap<u64, GameTag>,
+    #[serde(default)]
+    genres: HashMap<u64, GameTag>,
+    #[serde(default)]
+    engines: HashMap<u64, GameTag>,
+    #[serde(default)]
+    gametypes: HashMap<u64, GameTag>,
+    /// Moderators don't have IDs of their own, so they aren't hash-indexed.
+    #[serde(default)]
+    game_moderators: Vec<GameModerator>,
 }
 
 #[derive(Debug, Clone, Getters)]
@@ -52,8 +77,20 @@ pub struct Indicies<'tables> {
     per_level_categories_by_game_id_and_slug:
         SortedMap<(u64, &'tables str), &'tables Category>,
     levels_by_game_id_and_slug: SortedMap<(u64, &'tables str), &'tables Level>,
+    variables_by_game_id: SortedMap<u64, Vec<&'tables Variable>>,
+    platforms_by_slug: SortedMap<&'tables str, &'tables Platform>,
+    regions_by_slug: SortedMap<&'tables str, &'tables Region>,
+    games_by_developer_id: SortedMap<u64, Vec<&'tables Game>>,
+    games_by_publisher_id: SortedMap<u64, Vec<&'tables Game>>,
+    games_by_genre_id: SortedMap<u64, Vec<&'tables Game>>,
+    games_by_engine_id: SortedMap<u64, Vec<&'tables Game>>,
+    games_by_gametype_id: SortedMap<u64, Vec<&'tables Game>>,
+    games_by_platform_id: SortedMap<u64, Vec<&'tables Game>>,
+    games_by_release_year: SortedMap<u32, Vec<&'tables Game>>,
     runs_by_game_id_and_category_id_and_level_id:
         SortedMap<(u64, u64, Option<u64>), Vec<&'tables Run>>,
+    game_moderators_by_game_id: SortedMap<u64, Vec<&'tables GameModerator>>,
+    game_moderators_by_user_id: SortedMap<u64, Vec<&'tables GameModerator>>,
 }
 
 impl Tables {
@@ -70,8 +107,72 @@ impl Tables {
             runs: runs.into_iter().map(|x| (*x.id(), x)).collect(),
             users: users.into_iter().map(|x| (*x.id(), x)).collect(),
             levels: levels.into_iter().map(|x| (*x.id(), x)).collect(),
+            ..Tables::default()
         }
     }
+
+    pub fn with_variables(
+        mut self,
+        variables: impl IntoIterator<Item = Variable>,
+    ) -> Tables {
+        self.variables = variables.into_iter().map(|x| (*x.id(), x)).collect();
+        self
+    }
+
+    pub fn with_platforms(
+        mut self,
+        platforms: impl IntoIterator<Item = Platform>,
+    ) -> Tables {
+        self.platforms = platforms.into_iter().map(|x| (*x.id(), x)).collect();
+        self
+    }
+
+    pub fn with_regions(mut self, regions: impl IntoIterator<Item = Region>) -> Tables {
+        self.regions = regions.into_iter().map(|x| (*x.id(), x)).collect();
+        self
+    }
+
+    pub fn with_developers(
+        mut self,
+        developers: impl IntoIterator<Item = GameTag>,
+    ) -> Tables {
+        self.developers = developers.into_iter().map(|x| (*x.id(), x)).collect();
+        self
+    }
+
+    pub fn with_publishers(
+        mut self,
+        publishers: impl IntoIterator<Item = GameTag>,
+    ) -> Tables {
+        self.publishers = publishers.into_iter().map(|x| (*x.id(), x)).collect();
+        self
+    }
+
+    pub fn with_genres(mut self, genres: impl IntoIterator<Item = GameTag>) -> Tables {
+        self.genres = genres.into_iter().map(|x| (*x.id(), x)).collect();
+        self
+    }
+
+    pub fn with_engines(mut self, engines: impl IntoIterator<Item = GameTag>) -> Tables {
+        self.engines = engines.into_iter().map(|x| (*x.id(), x)).collect();
+        self
+    }
+
+    pub fn with_gametypes(
+        mut self,
+        gametypes: impl IntoIterator<Item = GameTag>,
+    ) -> Tables {
+        self.gametypes = gametypes.into_iter().map(|x| (*x.id(), x)).collect();
+        self
+    }
+
+    pub fn with_game_moderators(
+        mut self,
+        game_moderators: impl IntoIterator<Item = GameModerator>,
+    ) -> Tables {
+        self.game_moderators = game_moderators.into_iter().sorted().collect();
+        self
+    }
 }
 
 impl Database {
@@ -81,6 +182,7 @@ impl Database {
     /// validation-failing rows filtered out.
     pub fn new(tables: Arc<Tables>) -> Database {
         let mut tables = tables;
+        integrity::flag_player_counts(&mut tables);
         loop {
             match Self::try_new(tables.clone()) {
                 Ok(self_) => return self_,
@@ -88,16 +190,20 @@ impl Database {
                     let mut invalid_games = HashSet::<Game>::new();
                     let mut invalid_categories = HashSet::<Category>::new();
                     let mut invalid_levels = HashSet::<Level>::new();
+                    let mut invalid_variables = HashSet::<Variable>::new();
                     let mut invalid_runs = HashSet::<Run>::new();
                     let mut invalid_users = HashSet::<User>::new();
+                    let mut invalid_game_moderators = HashSet::<GameModerator>::new();
 
                     for error in errors.errors {
                         let invalid_rows = error.invalid_rows();
                         invalid_games.extend(invalid_rows.games);
                         invalid_categories.extend(invalid_rows.categories);
                         invalid_levels.extend(invalid_rows.levels);
+                        invalid_variables.extend(invalid_rows.variables);
                         invalid_runs.extend(invalid_rows.runs);
                         invalid_users.extend(invalid_rows.users);
+                        invalid_game_moderators.extend(invalid_rows.game_moderators);
                     }
 
                     fn filter_invalid<T: Hash + Eq + Clone>(
@@ -136,11 +242,38 @@ impl Database {
                         invalid_levels.len(),
                         (invalid_levels.len() * 100) / tables.levels().len().max(1)
                     );
+                    error!(
+                        "{:6} ({:3}%) invalid variables",
+                        invalid_variables.len(),
+                        (invalid_variables.len() * 100) / tables.variables().len().max(1)
+                    );
+                    error!(
+                        "{:6} ({:3}%) invalid game moderators",
+                        invalid_game_moderators.len(),
+                        (invalid_game_moderators.len() * 100)
+                            / tables.game_moderators().len().max(1)
+                    );
 
                     tables = Arc::new(Tables {
                         games: filter_invalid(&tables.games, invalid_games),
                         categories: filter_invalid(&tables.categories, invalid_categories),
                         levels: filter_invalid(&tables.levels, invalid_levels),
+                        variables: filter_invalid(&tables.variables, invalid_variables),
+                        platforms: tables.platforms.clone(),
+                        regions: tables.regions.clone(),
+                        developers: tables.developers.clone(),
+                        publishers: tables.publishers.clone(),
+                        genres: tables.genres.clone(),
+                        engines: tables.engines.clone(),
+                        gametypes: tables.gametypes.clone(),
+                        game_moderators: tables
+                            .game_moderators
+                            .iter()
+                            .filter(|moderator| {
+                                !invalid_game_moderators.contains(moderator)
+                            })
+                            .cloned()
+                            .collect(),
                         runs: filter_invalid(&tables.runs, invalid_runs),
                         users: filter_invalid(&tables.users, invalid_users),
                     })
@@ -152,8 +285,11 @@ impl Database {
     /// Attempt to initialize a Database from table data.
     ///
     /// If any data fails validation, this will return an Err of
-    /// IntegrityErrors indicating the records that caused the failure.
+    /// IntegrityErrors indicating the records that caused the failure. Runs are
+    /// flagged if their category doesn't allow their number of players.
     pub fn try_new(tables: Arc<Tables>) -> Result<Database, IntegrityErrors> {
+        let mut tables = tables;
+        integrity::flag_player_counts(&mut tables);
         let self_ = Self::new_unvalidated(tables);
         validate(&self_).map(move |()| self_)
     }
@@ -188,13 +324,92 @@ impl Database {
         self.tables().levels()
     }
 
+    pub fn variables(&self) -> &HashMap<u64, Variable> {
+        self.tables().variables()
+    }
+
+    pub fn platforms(&self) -> &HashMap<u64, Platform> {
+        self.tables().platforms()
+    }
+
+    pub fn regions(&self) -> &HashMap<u64, Region> {
+        self.tables().regions()
+    }
+
+    pub fn developers(&self) -> &HashMap<u64, GameTag> {
+        self.tables().developers()
+    }
+
+    pub fn publishers(&self) -> &HashMap<u64, GameTag> {
+        self.tables().publishers()
+    }
+
+    pub fn genres(&self) -> &HashMap<u64, GameTag> {
+        self.tables().genres()
+    }
+
+    pub fn engines(&self) -> &HashMap<u64, GameTag> {
+        self.tables().engines()
+    }
+
+    pub fn gametypes(&self) -> &HashMap<u64, GameTag> {
+        self.tables().gametypes()
+    }
+
     pub fn runs(&self) -> &HashMap<u64, Run> {
         self.tables().runs()
     }
 
+    pub fn game_moderators(&self) -> &Vec<GameModerator> {
+        self.tables().game_moderators()
+    }
+
     pub fn users(&self) -> &HashMap<u64, User> {
         self.tables().users()
     }
+
+    /// The games by the developer with this ID, in order of ID.
+    pub fn games_by_developer(&self, id: u64) -> &[&Game] {
+        games_in(self.indicies().games_by_developer_id(), &id)
+    }
+
+    /// The games by the publisher with this ID, in order of ID.
+    pub fn games_by_publisher(&self, id: u64) -> &[&Game] {
+        games_in(self.indicies().games_by_publisher_id(), &id)
+    }
+
+    /// The games in the genre with this ID, in order of ID.
+    pub fn games_by_genre(&self, id: u64) -> &[&Game] {
+        games_in(self.indicies().games_by_genre_id(), &id)
+    }
+
+    /// The games made with the engine with this ID, in order of ID.
+    pub fn games_by_engine(&self, id: u64) -> &[&Game] {
+        games_in(self.indicies().games_by_engine_id(), &id)
+    }
+
+    /// The games of the game type with this ID, in order of ID.
+    pub fn games_by_gametype(&self, id: u64) -> &[&Game] {
+        games_in(self.indicies().games_by_gametype_id(), &id)
+    }
+
+    /// The games on the platform with this ID, in order of ID.
+    pub fn games_by_platform(&self, id: u64) -> &[&Game] {
+        games_in(self.indicies().games_by_platform_id(), &id)
+    }
+
+    /// The games released in `year`, in order of ID.
+    pub fn games_by_release_year(&self, year: u32) -> &[&Game] {
+        games_in(self.indicies().games_by_release_year(), &year)
+    }
+}
+
+/// The games under `key` in one of the games_by_* indicies, if any.
+fn games_in<'a, Key: Ord>(
+    index: &'a SortedMap<Key, Vec<&'a Game>>,
+    key: &Key,
+) -> &'a [&'a Game] {
+    index.get(key).map(Vec::as_slice).unwrap_or_default()
 }
 
 impl<'tables> Indicies<'tables> {
@@ -225,10 +440,25 @@ impl<'tables> Indicies<'tables> {
                 .collect()
         }
 
+        /// Index games under each of the IDs in one of their fields.
+        fn games_by<'tables>(
+            games: &'tables HashMap<u64, Game>,
+            ids: fn(&'tables Game) -> &'tables Vec<u64>,
+        ) -> SortedMap<u64, Vec<&'tables Game>> {
+            let mut index = SortedMap::<u64, Vec<&'tables Game>>::new();
+            for game in games.values().sorted_by_key(|game| game.id()) {
+                for id in ids(game) {
+                    index.entry(*id).or_default().push(game);
+                }
+            }
+            index
+        }
+
         Indicies {
             last_updated: tables
                 .runs()
                 .values()
+                .filter(|run| run.is_verified())
                 .map(|run| run.created)
                 .flatten()
                 .max()
@@ -241,10 +471,47 @@ impl<'tables> Indicies<'tables> {
 
             users_by_slug: index(tables.users(), |user| user.slug().as_ref()),
 
+            platforms_by_slug: index(tables.platforms(), |platform| {
+                platform.slug().as_ref()
+            }),
+
+            regions_by_slug: index(tables.regions(), |region| region.slug().as_ref()),
+
+            games_by_developer_id: games_by(tables.games(), |game| game.developer_ids()),
+
+            games_by_publisher_id: games_by(tables.games(), |game| game.publisher_ids()),
+
+            games_by_genre_id: games_by(tables.games(), |game| game.genre_ids()),
+
+            games_by_engine_id: games_by(tables.games(), |game| game.engine_ids()),
+
+            games_by_gametype_id: games_by(tables.games(), |game| game.gametype_ids()),
+
+            games_by_platform_id: games_by(tables.games(), |game| game.platform_ids()),
+
+            games_by_release_year: tables
+                .games()
+                .values()
+                .filter_map(|game| game.released().map(|year| (year, game)))
+                .sorted_by_key(|(year, game)| (*year, *game.id()))
+                .group_by(|(year, _)| *year)
+                .into_iter()
+                .map(|(year, games)| (year, games.map(|(_, game)| game).collect()))
+                .collect(),
+
             levels_by_game_id_and_slug: index(tables.levels(), |level| {
                 (*level.game_id(), level.slug().as_ref())
             }),
 
+            variables_by_game_id: tables
+                .variables()
+                .values()
+                .sorted_by_key(|variable| (*variable.game_id(), *variable.id()))
+                .group_by(|variable| *variable.game_id())
+                .into_iter()
+                .map(|(game_id, variables)| (game_id, variables.collect()))
+                .collect(),
+
             per_game_categories_by_game_id_and_slug: index_where(
                 tables.categories(),
                 |category| (*category.game_id(), category.slug().as_ref()),
@@ -264,6 +531,28 @@ impl<'tables> Indicies<'tables> {
                 .into_iter()
                 .map(|(key, runs)| (key, runs.collect()))
                 .collect(),
+
+            game_moderators_by_game_id: tables.game_moderators().iter().fold(
+                SortedMap::new(),
+                |mut index, moderator| {
+                    index
+                        .entry(*moderator.game_id())
+                        .or_insert_with(Vec::new)
+                        .push(moderator);
+                    index
+                },
+            ),
+
+            game_moderators_by_user_id: tables.game_moderators().iter().fold(
+                SortedMap::new(),
+                |mut index, moderator| {
+                    index
+                        .entry(*moderator.user_id())
+                        .or_insert_with(Vec::new)
+                        .push(moderator);
+                    index
+                },
+            ),
         }
     }
 }
@@ -273,3 +562,115 @@ impl<Row> TableUtils for HashMap<u64, Row> {}
 pub trait IndexUtils {}
 
 impl<Key: Ord + Eq, RowRef> IndexUtils for BTreeMap<Key, RowRef> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn game(id: u64, released: u32, genre_ids: &[u64], platform_ids: &[u64]) -> Game {
+        serde_json::from_value(json!({
+            "id": id,
+            "created": null,
+            "slug": format!("game{}", id),
+            "name": format!("Game {}", id),
+            "primary_timing": "RTA",
+            "released": released,
+            "genre_ids": genre_ids,
+            "platform_ids": platform_ids,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_game_moderators() {
+        let user = |id: u64| -> User {
+            serde_json::from_value(json!({
+                "id": id,
+                "created": null,
+                "slug": format!("user{}", id),
+                "name": format!("User {}", id),
+            }))
+            .unwrap()
+        };
+        let moderator = |game_id: u64, user_id: u64| -> GameModerator {
+            serde_json::from_value(json!({
+                "game_id": game_id,
+                "user_id": user_id,
+                "level": "Moderator",
+            }))
+            .unwrap()
+        };
+        let tables = Tables::new(
+            vec![game(1, 1991, &[], &[]), game(2, 1992, &[], &[])],
+            vec![],
+            vec![],
+            vec![],
+            vec![user(10), user(11)],
+        )
+        .with_game_moderators(vec![
+            moderator(1, 10),
+            moderator(2, 10),
+            moderator(2, 11),
+            // a user we don't have
+            moderator(2, 12),
+        ]);
+
+        let errors = Database::try_new(Arc::new(tables.clone())).err().unwrap();
+        assert_eq!(errors.errors.len(), 1);
+        assert_eq!(errors.errors[0].kind(), "foreign-key-missing");
+        assert_eq!(
+            errors.errors[0].invalid_rows().game_moderators,
+            vec![moderator(2, 12)].into_iter().collect()
+        );
+
+        // which is left out of the database and its indicies
+        let database = Database::new(Arc::new(tables));
+        assert_eq!(database.game_moderators().len(), 3);
+        let users = |moderators: Option<&Vec<&GameModerator>>| -> Vec<u64> {
+            moderators
+                .into_iter()
+                .flatten()
+                .map(|moderator| moderator.user_id)
+                .collect()
+        };
+        let games = |moderators: Option<&Vec<&GameModerator>>| -> Vec<u64> {
+            moderators
+                .into_iter()
+                .flatten()
+                .map(|moderator| moderator.game_id)
+                .collect()
+        };
+        let by_game = database.indicies().game_moderators_by_game_id();
+        assert_eq!(users(by_game.get(&1)), vec![10]);
+        assert_eq!(users(by_game.get(&2)), vec![10, 11]);
+        let by_user = database.indicies().game_moderators_by_user_id();
+        assert_eq!(games(by_user.get(&10)), vec![1, 2]);
+        assert_eq!(games(by_user.get(&11)), vec![2]);
+        assert_eq!(games(by_user.get(&12)), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn test_games_by() {
+        let database = Database::new(Arc::new(Tables::new(
+            vec![
+                game(3, 1998, &[10], &[20, 21]),
+                game(1, 1991, &[10, 11], &[20]),
+                game(2, 1998, &[], &[21]),
+            ],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+        )));
+        let ids =
+            |games: &[&Game]| -> Vec<u64> { games.iter().map(|game| game.id).collect() };
+
+        assert_eq!(ids(database.games_by_genre(10)), vec![1, 3]);
+        assert_eq!(ids(database.games_by_genre(11)), vec![1]);
+        assert_eq!(ids(database.games_by_genre(12)), Vec::<u64>::new());
+        assert_eq!(ids(database.games_by_platform(21)), vec![2, 3]);
+        assert_eq!(ids(database.games_by_release_year(1998)), vec![2, 3]);
+        assert_eq!(ids(database.games_by_developer(10)), Vec::<u64>::new());
+    }
+}
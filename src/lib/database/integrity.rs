@@ -3,6 +3,7 @@ use std::{
     collections::HashMap,
     default::Default,
     fmt::{Debug, Display},
+    sync::Arc,
 };
 
 use derive_more::From;
@@ -14,7 +15,7 @@ use validator::{Validate, ValidationErrors};
 
 use speedruns_models::{
     any::{AnyModel, AnyModelVec},
-    Category, Game, Level, Run, RunPlayer, User,
+    Category, Game, GameModerator, Level, Run, RunPlayer, User, Variable, VariableScope,
 };
 use speedruns_utils::slugify;
 
@@ -126,6 +127,23 @@ pub fn validate(database: &super::Database) -> Result<(), IntegrityErrors> {
         }
     }
 
+    trace!("Validating {} variables.", database.variables().len());
+    for variable in database.variables().values() {
+        if let Err(mut error) = validate_variable(database, variable) {
+            errors.append(&mut error.errors);
+        }
+    }
+
+    trace!(
+        "Validating {} game moderators.",
+        database.game_moderators().len()
+    );
+    for moderator in database.game_moderators() {
+        if let Err(mut error) = validate_game_moderator(database, moderator) {
+            errors.append(&mut error.errors);
+        }
+    }
+
     IntegrityErrors::try_from(errors)
 }
 
@@ -192,10 +210,138 @@ fn validate_level(
     IntegrityErrors::try_from(errors)
 }
 
-fn validate_run(database: &super::Database, run: &Run) -> Result<(), IntegrityErrors> {
+fn validate_variable(
+    database: &super::Database,
+    variable: &Variable,
+) -> Result<(), IntegrityErrors> {
     let mut errors = Vec::new();
 
-    match database.games().get(&run.game_id) {
+    if let Err(validation_errors) = variable.validate() {
+        errors.push(IntegrityError::CheckFailed {
+            errors: validation_errors,
+            source: variable.clone().into(),
+        });
+    }
+
+    if database.games().get(&variable.game_id).is_none() {
+        errors.push(IntegrityError::ForeignKeyMissing {
+            target_type: "game",
+            target_id: variable.game_id,
+            foreign_key_field: "game_id",
+            source: variable.clone().into(),
+        });
+    }
+
+    if let Some(category_id) = variable.category_id {
+        if database.categories().get(&category_id).is_none() {
+            errors.push(IntegrityError::ForeignKeyMissing {
+                target_type: "category",
+                target_id: category_id,
+                foreign_key_field: "category_id",
+                source: variable.clone().into(),
+            });
+        }
+    }
+
+    if let VariableScope::SingleLevel { level_id } = variable.scope {
+        if database.levels().get(&level_id).is_none() {
+            errors.push(IntegrityError::ForeignKeyMissing {
+                target_type: "level",
+                target_id: level_id,
+                foreign_key_field: "scope.level_id",
+                source: variable.clone().into(),
+            });
+        }
+    }
+
+    IntegrityErrors::try_from(errors)
+}
+
+/// The rows that a run or game moderator can refer to, which it's validated
+/// against. This lets them be validated against something more compact than a
+/// whole Database.
+pub trait RunReferences {
+    fn game(&self, id: u64) -> Option<&Game>;
+    fn category(&self, id: u64) -> Option<&Category>;
+    fn has_level(&self, id: u64) -> bool;
+    fn has_user(&self, id: u64) -> bool;
+    fn variable(&self, id: u64) -> Option<&Variable>;
+    fn has_platform(&self, id: u64) -> bool;
+    fn has_region(&self, id: u64) -> bool;
+}
+
+impl RunReferences for super::Database {
+    fn game(&self, id: u64) -> Option<&Game> {
+        self.games().get(&id)
+    }
+
+    fn variable(&self, id: u64) -> Option<&Variable> {
+        self.variables().get(&id)
+    }
+
+    fn category(&self, id: u64) -> Option<&Category> {
+        self.categories().get(&id)
+    }
+
+    fn has_level(&self, id: u64) -> bool {
+        self.levels().contains_key(&id)
+    }
+
+    fn has_user(&self, id: u64) -> bool {
+        self.users().contains_key(&id)
+    }
+
+    fn has_platform(&self, id: u64) -> bool {
+        self.platforms().contains_key(&id)
+    }
+
+    fn has_region(&self, id: u64) -> bool {
+        self.regions().contains_key(&id)
+    }
+}
+
+/// Whether a run has a number of players its category doesn't allow. These runs
+/// aren't invalid, but they're kept off leaderboards.
+// Option::is_some_and would need a newer rustc than we support.
+#[allow(clippy::unnecessary_map_or)]
+pub fn has_wrong_player_count(category: Option<&Category>, run: &Run) -> bool {
+    category
+        .and_then(|category| category.players.as_ref())
+        .map_or(false, |players| !players.allows(run.players.len()))
+}
+
+/// Sets [Run::wrong_player_count] on each run in `tables` according to its
+/// category, copying the tables only if that changes any of them.
+pub(crate) fn flag_player_counts(tables: &mut Arc<super::Tables>) {
+    let changed: Vec<u64> = tables
+        .runs
+        .values()
+        .filter(|run| {
+            run.wrong_player_count
+                != has_wrong_player_count(tables.categories.get(&run.category_id), run)
+        })
+        .map(|run| run.id)
+        .collect();
+    if changed.is_empty() {
+        return;
+    }
+
+    trace!("Flagging player counts of {} runs.", changed.len());
+    let tables = Arc::make_mut(tables);
+    for id in changed {
+        let run = tables.runs.get_mut(&id).expect("run to exist");
+        run.wrong_player_count = !run.wrong_player_count;
+    }
+}
+
+/// Validates a run against the rows it refers to.
+pub fn validate_run(
+    references: &impl RunReferences,
+    run: &Run,
+) -> Result<(), IntegrityErrors> {
+    let mut errors = Vec::new();
+
+    match references.game(run.game_id) {
         Some(game) => {
             let primary_timing = game.primary_timing();
             let times = run.times_ms();
@@ -213,7 +359,7 @@ fn validate_run(database: &super::Database, run: &Run) -> Result<(), IntegrityEr
         }
     }
 
-    if database.categories().get(&run.category_id()).is_none() {
+    if references.category(*run.category_id()).is_none() {
         errors.push(IntegrityError::ForeignKeyMissing {
             target_type: "category",
             target_id: run.category_id,
@@ -223,7 +369,7 @@ fn validate_run(database: &super::Database, run: &Run) -> Result<(), IntegrityEr
     }
 
     if let Some(level_id) = run.level_id {
-        if database.levels().get(&level_id).is_none() {
+        if !references.has_level(level_id) {
             errors.push(IntegrityError::ForeignKeyMissing {
                 target_type: "level",
                 target_id: level_id,
@@ -235,7 +381,7 @@ fn validate_run(database: &super::Database, run: &Run) -> Result<(), IntegrityEr
 
     for player in run.players() {
         if let RunPlayer::UserId(user_id) = player {
-            if database.users().get(&user_id).is_none() {
+            if !references.has_user(*user_id) {
                 errors.push(IntegrityError::ForeignKeyMissing {
                     target_type: "user",
                     target_id: *user_id,
@@ -246,6 +392,54 @@ fn validate_run(database: &super::Database, run: &Run) -> Result<(), IntegrityEr
         }
     }
 
+    if let Some(platform_id) = run.platform_id {
+        if !references.has_platform(platform_id) {
+            errors.push(IntegrityError::ForeignKeyMissing {
+                target_type: "platform",
+                target_id: platform_id,
+                foreign_key_field: "platform_id",
+                source: run.clone().into(),
+            });
+        }
+    }
+
+    if let Some(region_id) = run.region_id {
+        if !references.has_region(region_id) {
+            errors.push(IntegrityError::ForeignKeyMissing {
+                target_type: "region",
+                target_id: region_id,
+                foreign_key_field: "region_id",
+                source: run.clone().into(),
+            });
+        }
+    }
+
+    for (variable_id, value_id) in run.values() {
+        let variable = references
+            .variable(*variable_id)
+            .filter(|variable| variable.game_id == run.game_id);
+        match variable {
+            Some(variable) => {
+                if variable.value(*value_id).is_none() {
+                    errors.push(IntegrityError::ForeignKeyMissing {
+                        target_type: "variable value",
+                        target_id: *value_id,
+                        foreign_key_field: "values[…]",
+                        source: run.clone().into(),
+                    });
+                }
+            }
+            None => {
+                errors.push(IntegrityError::ForeignKeyMissing {
+                    target_type: "variable",
+                    target_id: *variable_id,
+                    foreign_key_field: "values",
+                    source: run.clone().into(),
+                });
+            }
+        }
+    }
+
     if let Err(validation_errors) = run.validate() {
         errors.push(IntegrityError::CheckFailed {
             errors: validation_errors,
@@ -256,6 +450,34 @@ fn validate_run(database: &super::Database, run: &Run) -> Result<(), IntegrityEr
     IntegrityErrors::try_from(errors)
 }
 
+/// Validates a game moderator against the game and user it refers to.
+pub fn validate_game_moderator(
+    references: &impl RunReferences,
+    moderator: &GameModerator,
+) -> Result<(), IntegrityErrors> {
+    let mut errors = Vec::new();
+
+    if references.game(moderator.game_id).is_none() {
+        errors.push(IntegrityError::ForeignKeyMissing {
+            target_type: "game",
+            target_id: moderator.game_id,
+            foreign_key_field: "game_id",
+            source: moderator.clone().into(),
+        });
+    }
+
+    if !references.has_user(moderator.user_id) {
+        errors.push(IntegrityError::ForeignKeyMissing {
+            target_type: "user",
+            target_id: moderator.user_id,
+            foreign_key_field: "user_id",
+            source: moderator.clone().into(),
+        });
+    }
+
+    IntegrityErrors::try_from(errors)
+}
+
 fn validate_user(_database: &super::Database, user: &User) -> Result<(), IntegrityErrors> {
     let mut errors = Vec::new();
 
@@ -269,6 +491,12 @@ fn validate_user(_database: &super::Database, user: &User) -> Result<(), Integri
     Ok(())
 }
 
+/// The order in which users with the same slug take precedence: only the
+/// first is kept.
+pub fn user_precedence(user: &User) -> impl Ord + '_ {
+    (user.created(), user.name().len(), user.name(), user.id())
+}
+
 impl Display for IntegrityErrors {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         writeln!(f, "{} IntegrityErrors:", self.errors.len())?;
@@ -319,9 +547,22 @@ pub struct Rows {
     pub levels: HashSet<Level>,
     pub runs: HashSet<Run>,
     pub users: HashSet<User>,
+    pub variables: HashSet<Variable>,
+    pub game_moderators: HashSet<GameModerator>,
 }
 
 impl IntegrityError {
+    /// A short name for this kind of error, to count them by.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            IntegrityError::IndexingError => "indexing",
+            IntegrityError::ForeignKeyMissing { .. } => "foreign-key-missing",
+            IntegrityError::CheckFailed { .. } => "check-failed",
+            IntegrityError::NonUniqueSlug { .. } => "non-unique-slug",
+            IntegrityError::MissingPrimaryTiming(_) => "missing-primary-timing",
+        }
+    }
+
     pub fn invalid_rows<'tables>(&self) -> Rows {
         let mut invalids = Rows::default();
 
@@ -337,6 +578,10 @@ impl IntegrityError {
                     Level(level) => invalids.levels.insert(level.clone()),
                     Run(run) => invalids.runs.insert(run.clone()),
                     User(user) => invalids.users.insert(user.clone()),
+                    Variable(variable) => invalids.variables.insert(variable.clone()),
+                    GameModerator(moderator) => {
+                        invalids.game_moderators.insert(moderator.clone())
+                    }
                 };
             }
             IntegrityError::CheckFailed { .. } => {
@@ -370,6 +615,7 @@ impl IntegrityError {
                         }
                     }
                     Runs(_) => unreachable!("runs don't have slugs?!"),
+                    Variables(_) => unreachable!("variable slugs aren't unique"),
                     Games(games) => {
                         let dead_dupes = games
                             .iter()
@@ -391,10 +637,7 @@ impl IntegrityError {
                     Users(users) => {
                         let dead_dupes = users
                             .iter()
-                            .sorted_by_key(|user| {
-                                (user.created(), user.name().len(), user.name(), user.id())
-                                    .clone()
-                            })
+                            .sorted_by_key(|user| user_precedence(user))
                             .skip(1);
                         for dupe in dead_dupes {
                             invalids.users.insert(dupe.clone());
@@ -425,3 +668,91 @@ impl IntegrityErrors {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Database, Tables};
+    use serde_json::{json, Value};
+
+    fn category(id: u64, players: Value) -> Category {
+        serde_json::from_value(json!({
+            "game_id": 1,
+            "id": id,
+            "slug": format!("category{}", id),
+            "name": format!("Category {}", id),
+            "per": "PerGame",
+            "rules": "",
+            "players": players,
+        }))
+        .unwrap()
+    }
+
+    fn run(id: u64, category_id: u64, players: usize) -> Run {
+        let players: Vec<_> = (0..players)
+            .map(|i| json!({ "GuestName": format!("player{}", i) }))
+            .collect();
+        serde_json::from_value(json!({
+            "game_id": 1,
+            "category_id": category_id,
+            "level_id": null,
+            "id": id,
+            "created": null,
+            "date": null,
+            "times_ms": {"igt": null, "rta": 60000, "rta_nl": null},
+            "players": players,
+            "videos": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_has_wrong_player_count() {
+        let exactly = category(2, json!({"Exactly": 2}));
+        let up_to = category(3, json!({"UpTo": 2}));
+        let unknown = category(4, json!(null));
+
+        assert!(has_wrong_player_count(Some(&exactly), &run(1, 2, 1)));
+        assert!(!has_wrong_player_count(Some(&exactly), &run(1, 2, 2)));
+        assert!(!has_wrong_player_count(Some(&up_to), &run(1, 3, 1)));
+        assert!(has_wrong_player_count(Some(&up_to), &run(1, 3, 3)));
+        // without a rule or a category, any number of players is allowed
+        assert!(!has_wrong_player_count(Some(&unknown), &run(1, 4, 3)));
+        assert!(!has_wrong_player_count(None, &run(1, 5, 3)));
+    }
+
+    #[test]
+    fn test_flag_player_counts() {
+        let game: Game = serde_json::from_value(json!({
+            "id": 1,
+            "created": null,
+            "slug": "game",
+            "name": "Game",
+            "primary_timing": "RTA",
+        }))
+        .unwrap();
+        let mut stale = run(2, 2, 1);
+        stale.wrong_player_count = true;
+        let tables = Arc::new(Tables::new(
+            vec![game],
+            vec![category(2, json!({"Exactly": 1}))],
+            vec![],
+            vec![run(1, 2, 2), stale],
+            vec![],
+        ));
+        let flagged = |database: &Database| {
+            let mut flagged: Vec<_> = database
+                .runs()
+                .values()
+                .map(|run| (run.id, run.wrong_player_count))
+                .collect();
+            flagged.sort();
+            flagged
+        };
+
+        // runs are flagged however the database is built, and stale flags cleared
+        let expected = vec![(1, true), (2, false)];
+        assert_eq!(flagged(&Database::new(tables.clone())), expected);
+        assert_eq!(flagged(&Database::try_new(tables).unwrap()), expected);
+    }
+}
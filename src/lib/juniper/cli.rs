@@ -140,10 +140,43 @@ fn unpack_tables(no_data: bool) -> Tables {
     info!("{} categories.", categories.len());
     let levels = read_table("data/imported/levels.jsonl").expect("level data corrupt");
     info!("{} levels.", levels.len());
+    let variables =
+        read_table("data/imported/variables.jsonl").expect("variable data corrupt");
+    info!("{} variables.", variables.len());
+    let platforms =
+        read_table("data/imported/platforms.jsonl").expect("platform data corrupt");
+    info!("{} platforms.", platforms.len());
+    let regions = read_table("data/imported/regions.jsonl").expect("region data corrupt");
+    info!("{} regions.", regions.len());
+    let developers =
+        read_table("data/imported/developers.jsonl").expect("developer data corrupt");
+    info!("{} developers.", developers.len());
+    let publishers =
+        read_table("data/imported/publishers.jsonl").expect("publisher data corrupt");
+    info!("{} publishers.", publishers.len());
+    let genres = read_table("data/imported/genres.jsonl").expect("genre data corrupt");
+    info!("{} genres.", genres.len());
+    let engines = read_table("data/imported/engines.jsonl").expect("engine data corrupt");
+    info!("{} engines.", engines.len());
+    let gametypes =
+        read_table("data/imported/gametypes.jsonl").expect("gametype data corrupt");
+    info!("{} gametypes.", gametypes.len());
+    let game_moderators = read_table("data/imported/game_moderators.jsonl")
+        .expect("game moderator data corrupt");
+    info!("{} game moderators.", game_moderators.len());
 
     runs.extend(supplemental.into_iter());
 
     Tables::new(games, categories, levels, runs, users)
+        .with_variables(variables)
+        .with_platforms(platforms)
+        .with_regions(regions)
+        .with_developers(developers)
+        .with_publishers(publishers)
+        .with_genres(genres)
+        .with_engines(engines)
+        .with_gametypes(gametypes)
+        .with_game_moderators(game_moderators)
 }
 
 pub fn read_table<T: DeserializeOwned>(
@@ -15,7 +15,10 @@ use juniper_from_schema::graphql_schema_from_file;
 use speedruns_database::Database;
 use speedruns_models::{
     self as models,
-    aggregation::{leaderboard::leaderboard, progression::progression},
+    aggregation::{
+        leaderboard::{leaderboard, subcategory_leaderboards, RunFilter, VariableValues},
+        progression::progression,
+    },
 };
 use speedruns_utils::{base36, slugify, u64_from_base36};
 
@@ -59,6 +62,24 @@ pub struct Run(models::Run);
 #[derive(Debug, Deref, From, Into)]
 pub struct User(models::User);
 
+#[derive(Debug, Deref, From, Into)]
+pub struct CategoryPlayers(models::CategoryPlayers);
+
+#[derive(Debug, Deref, From, Into)]
+pub struct UserNameColor(models::UserNameColor);
+
+#[derive(Debug, Deref, From, Into)]
+pub struct RunVideo(models::RunVideo);
+
+#[derive(Debug, Deref, From, Into)]
+pub struct Platform(models::Platform);
+
+#[derive(Debug, Deref, From, Into)]
+pub struct Region(models::Region);
+
+#[derive(Debug, Deref, From, Into)]
+pub struct GameModerator(models::GameModerator);
+
 #[derive(Debug, Deref, From, Into)]
 pub struct LeaderboardRun(models::aggregation::leaderboard::LeaderboardRun);
 
@@ -72,12 +93,149 @@ pub struct CategoryLevel {
     level: Level,
 }
 
+#[derive(Debug, Getters)]
+#[get = "pub"]
+pub struct SubcategoryLeaderboard {
+    values: Vec<VariableValue>,
+    leaderboard: Vec<models::aggregation::leaderboard::LeaderboardRun>,
+}
+
+#[derive(Debug, Clone, Getters)]
+#[get = "pub"]
+pub struct VariableValue {
+    variable: String,
+    value: String,
+    label: String,
+}
+
 #[derive(Debug)]
 pub enum Player {
     User(User),
     Guest(String),
 }
 
+/// Whether a run belongs in a query's results, given which runs other than
+/// verified ones it has asked for.
+fn include_run(run: &models::Run, include_pending: bool, include_rejected: bool) -> bool {
+    match run.status() {
+        models::RunStatus::New => include_pending,
+        models::RunStatus::Verified { .. } => true,
+        models::RunStatus::Rejected { .. } => include_rejected,
+    }
+}
+
+/// The filter for the runs of a category and level's leaderboard, given the
+/// leaderboard's arguments. Variables and their values are looked up by slug
+/// among the variables that apply to the category and level, and platforms
+/// and regions by slug. Returns None if any of them doesn't exist.
+fn run_filter(
+    database: &Database,
+    category: &models::Category,
+    level_id: Option<u64>,
+    variables: Option<Vec<VariableFilter>>,
+    platform_slug: Option<String>,
+    region_slug: Option<String>,
+    emulated: Option<bool>,
+) -> Option<RunFilter> {
+    let game_variables = database
+        .indicies()
+        .variables_by_game_id()
+        .get(category.game_id())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    let values: VariableValues = variables
+        .unwrap_or_default()
+        .iter()
+        .map(|filter| {
+            let variable = game_variables.iter().find(|variable| {
+                *variable.slug() == filter.variable
+                    && variable.applies_to(*category.id(), level_id)
+            })?;
+            let value = variable
+                .values()
+                .iter()
+                .find(|value| *value.slug() == filter.value)?;
+            Some((*variable.id(), *value.id()))
+        })
+        .collect::<Option<_>>()?;
+    // runs without a value for a variable are ranked as if they had its default
+    let defaults = game_variables
+        .iter()
+        .filter(|variable| values.contains_key(variable.id()))
+        .filter_map(|variable| Some((*variable.id(), (*variable.default_value_id())?)))
+        .collect();
+
+    let platform_id = match platform_slug {
+        Some(slug) => Some(*database.indicies().platforms_by_slug().get(&slug[..])?.id()),
+        None => None,
+    };
+    let region_id = match region_slug {
+        Some(slug) => Some(*database.indicies().regions_by_slug().get(&slug[..])?.id()),
+        None => None,
+    };
+
+    Some(RunFilter {
+        values,
+        defaults,
+        platform_id,
+        region_id,
+        emulated,
+    })
+}
+
+/// Ranks the runs of a category and level on a leaderboard for each combination
+/// of values of the subcategory variables that apply to them, truncating each
+/// to `limit` runs.
+fn subcategory_leaderboards_of(
+    database: &Database,
+    category: &models::Category,
+    level_id: Option<u64>,
+    runs: &[models::Run],
+    include_obsolete: bool,
+    limit: Option<i32>,
+    filter: &RunFilter,
+) -> Vec<SubcategoryLeaderboard> {
+    let game = &database.games()[category.game_id()];
+    let subcategories: Vec<&models::Variable> = database
+        .indicies()
+        .variables_by_game_id()
+        .get(category.game_id())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .copied()
+        .filter(|variable| {
+            *variable.is_subcategory() && variable.applies_to(*category.id(), level_id)
+        })
+        .collect();
+
+    subcategory_leaderboards(game, &subcategories, runs.iter(), include_obsolete, filter)
+        .into_iter()
+        .map(|(values, mut ranked)| {
+            if let Some(limit) = limit {
+                ranked.truncate(limit.try_into().unwrap_or(0));
+            }
+
+            SubcategoryLeaderboard {
+                values: values
+                    .iter()
+                    .filter_map(|(variable_id, value_id)| {
+                        let variable = database.variables().get(variable_id)?;
+                        let value = variable.value(*value_id)?;
+                        Some(VariableValue {
+                            variable: variable.slug().clone(),
+                            value: value.slug().clone(),
+                            label: value.label().clone(),
+                        })
+                    })
+                    .collect(),
+                leaderboard: ranked,
+            }
+        })
+        .collect()
+}
+
 impl StatsFields for Stats {
     fn field_last_updated(&self, executor: &Executor<'_, Context>) -> f64 {
         executor
@@ -190,6 +348,34 @@ impl SpeedrunsFields for Speedruns {
         }
     }
 
+    fn field_platforms(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, Platform, Walked>,
+    ) -> Vec<Platform> {
+        executor
+            .context()
+            .indicies()
+            .platforms_by_slug()
+            .values()
+            .map(|platform| (*platform).clone().into())
+            .collect()
+    }
+
+    fn field_regions(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, Region, Walked>,
+    ) -> Vec<Region> {
+        executor
+            .context()
+            .indicies()
+            .regions_by_slug()
+            .values()
+            .map(|region| (*region).clone().into())
+            .collect()
+    }
+
     fn field_seed(&self, _executor: &Executor<'_, Context>) -> i32 {
         rand::Rng::gen(&mut rand::thread_rng())
     }
@@ -224,6 +410,8 @@ impl GameFields for Game {
         &self,
         executor: &Executor<'_, Context>,
         _trail: &QueryTrail<'_, Run, Walked>,
+        include_pending: bool,
+        include_rejected: bool,
     ) -> Vec<Run> {
         executor
             .context()
@@ -232,6 +420,7 @@ impl GameFields for Game {
             .range((*self.id(), 0, None)..(*self.id() + 1, 0, None))
             .map(|(_key, value)| value)
             .flatten()
+            .filter(|run| include_run(run, include_pending, include_rejected))
             .map(|run| (*run).clone().into())
             .collect()
     }
@@ -254,6 +443,7 @@ impl GameFields for Game {
         &self,
         executor: &Executor<'_, Context>,
         _trail: &QueryTrail<'_, Category, Walked>,
+        include_miscellaneous: bool,
     ) -> Vec<Category> {
         executor
             .context()
@@ -261,6 +451,7 @@ impl GameFields for Game {
             .per_game_categories_by_game_id_and_slug()
             .range((*self.id(), "")..(*self.id() + 1, ""))
             .map(|(_key, value)| value)
+            .filter(|category| include_miscellaneous || !category.miscellaneous)
             .sorted_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)))
             .map(|c| Category((*c).clone().into()))
             .collect()
@@ -270,6 +461,7 @@ impl GameFields for Game {
         &self,
         executor: &Executor<'_, Context>,
         _trail: &QueryTrail<'_, Category, Walked>,
+        include_miscellaneous: bool,
     ) -> Vec<Category> {
         executor
             .context()
@@ -277,10 +469,27 @@ impl GameFields for Game {
             .per_level_categories_by_game_id_and_slug()
             .range((*self.id(), "")..(*self.id() + 1, ""))
             .map(|(_key, value)| value)
+            .filter(|category| include_miscellaneous || !category.miscellaneous)
             .sorted_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)))
             .map(|c| Category((*c).clone().into()))
             .collect()
     }
+
+    fn field_moderators(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, GameModerator, Walked>,
+    ) -> Vec<GameModerator> {
+        executor
+            .context()
+            .indicies()
+            .game_moderators_by_game_id()
+            .get(self.id())
+            .into_iter()
+            .flatten()
+            .map(|moderator| (*moderator).clone().into())
+            .collect()
+    }
 }
 
 impl RunFields for Run {
@@ -348,8 +557,91 @@ impl RunFields for Run {
             .collect()
     }
 
-    fn field_videos(&self, _executor: &Executor<'_, Context>) -> Vec<String> {
-        self.videos().iter().map(|v| v.to_string()).collect()
+    fn field_videos(
+        &self,
+        _executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, RunVideo, Walked>,
+    ) -> Vec<RunVideo> {
+        self.videos().iter().map(|v| v.clone().into()).collect()
+    }
+
+    fn field_platform(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, Platform, Walked>,
+    ) -> Option<Platform> {
+        self.platform_id()
+            .and_then(|platform_id| executor.context().platforms().get(&platform_id))
+            .map(|platform| platform.clone().into())
+    }
+
+    fn field_region(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, Region, Walked>,
+    ) -> Option<Region> {
+        self.region_id()
+            .and_then(|region_id| executor.context().regions().get(&region_id))
+            .map(|region| region.clone().into())
+    }
+
+    fn field_emulated(&self, _executor: &Executor<'_, Context>) -> bool {
+        *self.emulated()
+    }
+
+    fn field_comment(&self, _executor: &Executor<'_, Context>) -> Option<String> {
+        self.comment().clone()
+    }
+
+    fn field_splits_url(&self, _executor: &Executor<'_, Context>) -> Option<String> {
+        self.splits().as_ref().map(|splits| splits.to_string())
+    }
+
+    fn field_splits_io_id(&self, _executor: &Executor<'_, Context>) -> Option<String> {
+        match self.splits() {
+            Some(models::RunSplits::SplitsIo { id }) => Some(id.clone()),
+            _ => None,
+        }
+    }
+
+    fn field_weblink(&self, _executor: &Executor<'_, Context>) -> Option<String> {
+        self.weblink().clone()
+    }
+
+    fn field_status(&self, _executor: &Executor<'_, Context>) -> RunStatus {
+        match self.status() {
+            models::RunStatus::New => RunStatus::New,
+            models::RunStatus::Verified { .. } => RunStatus::Verified,
+            models::RunStatus::Rejected { .. } => RunStatus::Rejected,
+        }
+    }
+
+    fn field_examiner(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, User, Walked>,
+    ) -> Option<User> {
+        // examiners aren't checked for integrity, so they may be missing
+        self.status()
+            .examiner_id()
+            .and_then(|user_id| executor.context().database.users().get(&user_id))
+            .map(|user| user.clone().into())
+    }
+
+    fn field_verify_date(&self, _executor: &Executor<'_, Context>) -> Option<f64> {
+        match self.status() {
+            models::RunStatus::Verified { verify_date, .. } => {
+                verify_date.map(|date| date.timestamp() as f64)
+            }
+            _ => None,
+        }
+    }
+
+    fn field_rejection_reason(&self, _executor: &Executor<'_, Context>) -> Option<String> {
+        match self.status() {
+            models::RunStatus::Rejected { reason, .. } => reason.clone(),
+            _ => None,
+        }
     }
 }
 
@@ -416,6 +708,18 @@ impl CategoryFields for Category {
         slugify(&*self.name())
     }
 
+    fn field_miscellaneous(&self, _executor: &Executor<'_, Context>) -> bool {
+        *self.miscellaneous()
+    }
+
+    fn field_players(
+        &self,
+        _executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, CategoryPlayers, Walked>,
+    ) -> Option<CategoryPlayers> {
+        self.players().clone().map(CategoryPlayers::from)
+    }
+
     fn field_leaderboard(
         &self,
         executor: &Executor<'_, Context>,
@@ -423,6 +727,12 @@ impl CategoryFields for Category {
         level_slug: Option<String>,
         include_obsolete: bool,
         limit: Option<i32>,
+        include_pending: bool,
+        include_rejected: bool,
+        variables: Option<Vec<VariableFilter>>,
+        platform_slug: Option<String>,
+        region_slug: Option<String>,
+        emulated: Option<bool>,
     ) -> Vec<LeaderboardRun> {
         let game = &executor.context().games()[self.game_id()];
         let level_id;
@@ -442,6 +752,20 @@ impl CategoryFields for Category {
             level_id = None;
         }
 
+        let filter = match run_filter(
+            executor.context(),
+            self,
+            level_id,
+            variables,
+            platform_slug,
+            region_slug,
+            emulated,
+        ) {
+            Some(filter) => filter,
+            // variable, platform or region specified but not found
+            None => return vec![],
+        };
+
         let runs = executor
             .context()
             .indicies()
@@ -449,9 +773,13 @@ impl CategoryFields for Category {
             .get(&(*self.game_id(), *self.id(), level_id));
 
         if let Some(runs) = runs {
-            let runs: Vec<_> = runs.iter().map(|run| (*run).clone()).collect();
+            let runs: Vec<_> = runs
+                .iter()
+                .filter(|run| include_run(run, include_pending, include_rejected))
+                .map(|run| (*run).clone())
+                .collect();
 
-            let mut ranked = leaderboard(&game, runs.iter(), include_obsolete);
+            let mut ranked = leaderboard(&game, runs.iter(), include_obsolete, &filter);
 
             if let Some(limit) = limit {
                 ranked.truncate(limit.try_into().unwrap_or(0));
@@ -463,6 +791,74 @@ impl CategoryFields for Category {
         }
     }
 
+    fn field_subcategory_leaderboards(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, SubcategoryLeaderboard, Walked>,
+        level_slug: Option<String>,
+        include_obsolete: bool,
+        limit: Option<i32>,
+        include_pending: bool,
+        include_rejected: bool,
+        variables: Option<Vec<VariableFilter>>,
+        platform_slug: Option<String>,
+        region_slug: Option<String>,
+        emulated: Option<bool>,
+    ) -> Vec<SubcategoryLeaderboard> {
+        let level_id;
+        if let Some(level_slug) = level_slug {
+            let level = executor
+                .context()
+                .indicies()
+                .levels_by_game_id_and_slug()
+                .get(&(*self.game_id(), &level_slug));
+            if let Some(level) = level {
+                level_id = Some(level.id);
+            } else {
+                // level specified but not found
+                return vec![];
+            }
+        } else {
+            level_id = None;
+        }
+
+        let filter = match run_filter(
+            executor.context(),
+            self,
+            level_id,
+            variables,
+            platform_slug,
+            region_slug,
+            emulated,
+        ) {
+            Some(filter) => filter,
+            // variable, platform or region specified but not found
+            None => return vec![],
+        };
+
+        let runs: Vec<models::Run> = executor
+            .context()
+            .indicies()
+            .runs_by_game_id_and_category_id_and_level_id()
+            .get(&(*self.game_id(), *self.id(), level_id))
+            .map(Clone::clone)
+            .unwrap_or_else(Default::default)
+            .iter()
+            .filter(|run| include_run(run, include_pending, include_rejected))
+            .map(|run| models::Run::clone(run))
+            .collect();
+
+        subcategory_leaderboards_of(
+            executor.context(),
+            self,
+            level_id,
+            &runs,
+            include_obsolete,
+            limit,
+            &filter,
+        )
+    }
+
     fn field_progression(
         &self,
         executor: &Executor<'_, Context>,
@@ -495,7 +891,11 @@ impl CategoryFields for Category {
                 .runs_by_game_id_and_category_id_and_level_id()
                 .get(&(*self.game_id(), *self.id(), Some(level_id)))
             {
-                Some(runs) => runs.iter().map(|run| (*run).clone()).collect(),
+                Some(runs) => runs
+                    .iter()
+                    .filter(|run| run.is_verified())
+                    .map(|run| (*run).clone())
+                    .collect(),
                 None => Vec::new(),
             },
             None => executor
@@ -509,6 +909,7 @@ impl CategoryFields for Category {
                 .map(|(_key, value)| value)
                 .map(|x| x.iter())
                 .flatten()
+                .filter(|run| run.is_verified())
                 .map(|run| (*run).clone().into())
                 .collect(),
         };
@@ -535,6 +936,105 @@ impl CategoryFields for Category {
     }
 }
 
+impl SubcategoryLeaderboardFields for SubcategoryLeaderboard {
+    fn field_values(
+        &self,
+        _executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, VariableValue, Walked>,
+    ) -> Vec<VariableValue> {
+        self.values().clone()
+    }
+
+    fn field_leaderboard(
+        &self,
+        _executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, LeaderboardRun, Walked>,
+    ) -> Vec<LeaderboardRun> {
+        self.leaderboard()
+            .iter()
+            .map(|r| LeaderboardRun(r.clone()))
+            .collect()
+    }
+}
+
+impl VariableValueFields for VariableValue {
+    fn field_variable(&self, _executor: &Executor<'_, Context>) -> &String {
+        self.variable()
+    }
+
+    fn field_value(&self, _executor: &Executor<'_, Context>) -> &String {
+        self.value()
+    }
+
+    fn field_label(&self, _executor: &Executor<'_, Context>) -> &String {
+        self.label()
+    }
+}
+
+impl PlatformFields for Platform {
+    fn field_src_id(&self, _executor: &Executor<'_, Context>) -> String {
+        base36(*self.id())
+    }
+
+    fn field_slug(&self, _executor: &Executor<'_, Context>) -> &String {
+        &*self.slug()
+    }
+
+    fn field_name(&self, _executor: &Executor<'_, Context>) -> &String {
+        &*self.name()
+    }
+
+    fn field_released(&self, _executor: &Executor<'_, Context>) -> i32 {
+        i32::try_from(*self.released()).expect("impossibly distant release")
+    }
+}
+
+impl RunVideoFields for RunVideo {
+    fn field_kind(&self, _executor: &Executor<'_, Context>) -> RunVideoKind {
+        match **self {
+            models::RunVideo::YouTube { .. } => RunVideoKind::Youtube,
+            models::RunVideo::TwitchVod { .. } => RunVideoKind::TwitchVod,
+            models::RunVideo::TwitchClip { .. } => RunVideoKind::TwitchClip,
+            models::RunVideo::Bilibili { .. } => RunVideoKind::Bilibili,
+            models::RunVideo::NicoNico { .. } => RunVideoKind::NicoNico,
+            models::RunVideo::Link { .. } => RunVideoKind::Link,
+        }
+    }
+
+    fn field_url(&self, _executor: &Executor<'_, Context>) -> String {
+        self.to_string()
+    }
+
+    fn field_video_id(&self, _executor: &Executor<'_, Context>) -> Option<String> {
+        match &**self {
+            models::RunVideo::YouTube { id, .. }
+            | models::RunVideo::TwitchVod { id, .. }
+            | models::RunVideo::Bilibili { id, .. }
+            | models::RunVideo::NicoNico { id, .. } => Some(id.clone()),
+            models::RunVideo::TwitchClip { slug } => Some(slug.clone()),
+            models::RunVideo::Link { .. } => None,
+        }
+    }
+
+    fn field_start(&self, _executor: &Executor<'_, Context>) -> Option<i32> {
+        self.start()
+    }
+}
+
+impl RegionFields for Region {
+    fn field_src_id(&self, _executor: &Executor<'_, Context>) -> String {
+        base36(*self.id())
+    }
+
+    fn field_slug(&self, _executor: &Executor<'_, Context>) -> &String {
+        &*self.slug()
+    }
+
+    fn field_name(&self, _executor: &Executor<'_, Context>) -> &String {
+        &*self.name()
+    }
+}
+
 impl UserFields for User {
     fn field_id(&self, _executor: &Executor<'_, Context>) -> ID {
         global_id(*self.id(), NodeType::User)
@@ -547,6 +1047,139 @@ impl UserFields for User {
     fn field_slug(&self, _executor: &Executor<'_, Context>) -> String {
         slugify(&*self.name())
     }
+
+    fn field_name(&self, _executor: &Executor<'_, Context>) -> &String {
+        &*self.name()
+    }
+
+    fn field_country_code(&self, _executor: &Executor<'_, Context>) -> Option<String> {
+        self.country_code().clone()
+    }
+
+    fn field_region_code(&self, _executor: &Executor<'_, Context>) -> Option<String> {
+        self.region_code().clone()
+    }
+
+    fn field_twitch(&self, _executor: &Executor<'_, Context>) -> Option<String> {
+        self.links().twitch().clone()
+    }
+
+    fn field_youtube(&self, _executor: &Executor<'_, Context>) -> Option<String> {
+        self.links().youtube().clone()
+    }
+
+    fn field_twitter(&self, _executor: &Executor<'_, Context>) -> Option<String> {
+        self.links().twitter().clone()
+    }
+
+    fn field_speedrunslive(&self, _executor: &Executor<'_, Context>) -> Option<String> {
+        self.links().speedrunslive().clone()
+    }
+
+    fn field_hitbox(&self, _executor: &Executor<'_, Context>) -> Option<String> {
+        self.links().hitbox().clone()
+    }
+
+    fn field_name_color(
+        &self,
+        _executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, UserNameColor, Walked>,
+    ) -> Option<UserNameColor> {
+        self.name_style().as_ref().map(|style| match style {
+            models::UserNameStyle::Solid { color } => color.clone().into(),
+            models::UserNameStyle::Gradient { color_from, .. } => color_from.clone().into(),
+        })
+    }
+
+    fn field_name_color_to(
+        &self,
+        _executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, UserNameColor, Walked>,
+    ) -> Option<UserNameColor> {
+        match self.name_style() {
+            Some(models::UserNameStyle::Gradient { color_to, .. }) => {
+                Some(color_to.clone().into())
+            }
+            _ => None,
+        }
+    }
+
+    fn field_role(&self, _executor: &Executor<'_, Context>) -> UserRole {
+        match self.role() {
+            models::UserRole::Banned => UserRole::Banned,
+            models::UserRole::User => UserRole::User,
+            models::UserRole::Trusted => UserRole::Trusted,
+            models::UserRole::Moderator => UserRole::Moderator,
+            models::UserRole::ContentModerator => UserRole::ContentModerator,
+            models::UserRole::Programmer => UserRole::Programmer,
+            models::UserRole::Admin => UserRole::Admin,
+        }
+    }
+
+    fn field_moderated_games(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, GameModerator, Walked>,
+    ) -> Vec<GameModerator> {
+        executor
+            .context()
+            .indicies()
+            .game_moderators_by_user_id()
+            .get(self.id())
+            .into_iter()
+            .flatten()
+            .map(|moderator| (*moderator).clone().into())
+            .collect()
+    }
+}
+
+impl GameModeratorFields for GameModerator {
+    fn field_game(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, Game, Walked>,
+    ) -> Game {
+        executor.context().games()[self.game_id()].clone().into()
+    }
+
+    fn field_user(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, User, Walked>,
+    ) -> User {
+        executor.context().users()[self.user_id()].clone().into()
+    }
+
+    fn field_level(&self, _executor: &Executor<'_, Context>) -> ModeratorLevel {
+        match self.level() {
+            models::ModeratorLevel::Moderator => ModeratorLevel::Moderator,
+            models::ModeratorLevel::SuperModerator => ModeratorLevel::SuperModerator,
+        }
+    }
+}
+
+impl CategoryPlayersFields for CategoryPlayers {
+    fn field_count(&self, _executor: &Executor<'_, Context>) -> i32 {
+        let count = match **self {
+            models::CategoryPlayers::Exactly(count)
+            | models::CategoryPlayers::UpTo(count) => count,
+        };
+        i32::try_from(count).expect("impossibly many players")
+    }
+
+    fn field_exact(&self, _executor: &Executor<'_, Context>) -> bool {
+        matches!(**self, models::CategoryPlayers::Exactly(_))
+    }
+}
+
+impl UserNameColorFields for UserNameColor {
+    fn field_light(&self, _executor: &Executor<'_, Context>) -> &String {
+        &*self.light()
+    }
+
+    fn field_dark(&self, _executor: &Executor<'_, Context>) -> &String {
+        &*self.dark()
+    }
 }
 
 impl PlayerFields for Player {
@@ -617,8 +1250,27 @@ impl CategoryLevelFields for CategoryLevel {
         _trail: &QueryTrail<'_, LeaderboardRun, Walked>,
         include_obsolete: bool,
         limit: Option<i32>,
+        include_pending: bool,
+        include_rejected: bool,
+        variables: Option<Vec<VariableFilter>>,
+        platform_slug: Option<String>,
+        region_slug: Option<String>,
+        emulated: Option<bool>,
     ) -> Vec<LeaderboardRun> {
         let game = &executor.context().games()[self.category().game_id()];
+        let filter = match run_filter(
+            executor.context(),
+            self.category(),
+            Some(*self.level().id()),
+            variables,
+            platform_slug,
+            region_slug,
+            emulated,
+        ) {
+            Some(filter) => filter,
+            // variable, platform or region specified but not found
+            None => return vec![],
+        };
 
         let runs: Vec<models::Run> = executor
             .context()
@@ -632,10 +1284,11 @@ impl CategoryLevelFields for CategoryLevel {
             .map(Clone::clone)
             .unwrap_or_else(Default::default)
             .iter()
+            .filter(|run| include_run(run, include_pending, include_rejected))
             .map(|run| models::Run::clone(run))
             .collect();
 
-        let mut ranked = leaderboard(game, runs.iter(), include_obsolete);
+        let mut ranked = leaderboard(game, runs.iter(), include_obsolete, &filter);
 
         if let Some(limit) = limit {
             ranked.truncate(limit.try_into().unwrap_or(0));
@@ -644,6 +1297,57 @@ impl CategoryLevelFields for CategoryLevel {
         ranked.iter().map(|r| LeaderboardRun(r.clone())).collect()
     }
 
+    fn field_subcategory_leaderboards(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, SubcategoryLeaderboard, Walked>,
+        include_obsolete: bool,
+        limit: Option<i32>,
+        include_pending: bool,
+        include_rejected: bool,
+        variables: Option<Vec<VariableFilter>>,
+        platform_slug: Option<String>,
+        region_slug: Option<String>,
+        emulated: Option<bool>,
+    ) -> Vec<SubcategoryLeaderboard> {
+        let level_id = Some(*self.level().id());
+        let filter = match run_filter(
+            executor.context(),
+            self.category(),
+            level_id,
+            variables,
+            platform_slug,
+            region_slug,
+            emulated,
+        ) {
+            Some(filter) => filter,
+            // variable, platform or region specified but not found
+            None => return vec![],
+        };
+
+        let runs: Vec<models::Run> = executor
+            .context()
+            .indicies()
+            .runs_by_game_id_and_category_id_and_level_id()
+            .get(&(*self.category().game_id(), *self.category().id(), level_id))
+            .map(Clone::clone)
+            .unwrap_or_else(Default::default)
+            .iter()
+            .filter(|run| include_run(run, include_pending, include_rejected))
+            .map(|run| models::Run::clone(run))
+            .collect();
+
+        subcategory_leaderboards_of(
+            executor.context(),
+            self.category(),
+            level_id,
+            &runs,
+            include_obsolete,
+            limit,
+            &filter,
+        )
+    }
+
     fn field_progression(
         &self,
         executor: &Executor<'_, Context>,
@@ -664,6 +1368,7 @@ impl CategoryLevelFields for CategoryLevel {
             .map(Clone::clone)
             .unwrap_or_else(Default::default)
             .iter()
+            .filter(|run| run.is_verified())
             .map(|run| models::Run::clone(run))
             .collect();
 
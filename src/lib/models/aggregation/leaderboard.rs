@@ -1,9 +1,41 @@
-use std::{collections::HashSet, convert::TryFrom};
+use std::{
+    collections::{BTreeMap, HashSet},
+    convert::TryFrom,
+};
 
 use getset::Getters;
 use serde::Serialize;
 
-use crate::{Game, Run, RunPlayer};
+use crate::{Game, Run, RunPlayer, Variable};
+
+/// Values for some of a game's variables, as value IDs by variable ID.
+pub type VariableValues = BTreeMap<u64, u64>;
+
+/// Which runs a leaderboard ranks: only those matching everything that's set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunFilter {
+    pub values: VariableValues,
+    /// The default values of the variables in `values`, which runs without a
+    /// value for one of them are treated as having, as in
+    /// [subcategory_leaderboards].
+    pub defaults: VariableValues,
+    pub platform_id: Option<u64>,
+    pub region_id: Option<u64>,
+    pub emulated: Option<bool>,
+}
+
+impl RunFilter {
+    pub fn matches(&self, run: &Run) -> bool {
+        self.values.iter().all(|(variable_id, value_id)| {
+            run.values
+                .get(variable_id)
+                .or_else(|| self.defaults.get(variable_id))
+                == Some(value_id)
+        }) && (self.platform_id.is_none() || self.platform_id == run.platform_id)
+            && (self.region_id.is_none() || self.region_id == run.region_id)
+            && (self.emulated.is_none() || self.emulated == Some(run.emulated))
+    }
+}
 
 #[derive(Debug, Clone, Getters, Serialize)]
 #[get = "pub"]
@@ -18,13 +50,17 @@ pub struct LeaderboardRun {
 /// Ranks a set of runs (all for the same game/category/level) using the
 /// timing specified for the game rules, then by run date, then by
 /// submission datetime, discarding lower-ranked runs by the same runner
-/// unless rank_obsoletes is true.
+/// unless rank_obsoletes is true. Only runs matching the filter are ranked,
+/// and never runs with a number of players their category doesn't allow.
 pub fn leaderboard<'runs>(
     game: &'_ Game,
     runs: impl Iterator<Item = &'runs Run>,
     rank_obsoletes: bool,
+    filter: &RunFilter,
 ) -> Vec<LeaderboardRun> {
-    let mut runs: Vec<&Run> = runs.collect();
+    let mut runs: Vec<&Run> = runs
+        .filter(|run| !run.wrong_player_count && filter.matches(run))
+        .collect();
 
     if runs.is_empty() {
         return vec![];
@@ -91,3 +127,265 @@ pub fn leaderboard<'runs>(
 
     leaderboard
 }
+
+/// Ranks a set of runs (all for the same game/category/level) on a separate
+/// leaderboard for each combination of values they have for the given
+/// subcategory variables, keyed by those values. A run without a value for
+/// one of the variables is ranked with its default value, or left out if it
+/// doesn't have one. Only runs matching the filter are ranked, as with
+/// [leaderboard].
+pub fn subcategory_leaderboards<'runs>(
+    game: &'_ Game,
+    subcategories: &[&Variable],
+    runs: impl Iterator<Item = &'runs Run>,
+    rank_obsoletes: bool,
+    filter: &RunFilter,
+) -> BTreeMap<VariableValues, Vec<LeaderboardRun>> {
+    let mut runs_by_values = BTreeMap::<VariableValues, Vec<&Run>>::new();
+    for run in runs.filter(|run| !run.wrong_player_count && filter.matches(run)) {
+        let values = subcategories
+            .iter()
+            .map(|variable| {
+                let value_id = run
+                    .values
+                    .get(&variable.id)
+                    .or(variable.default_value_id.as_ref())?;
+                Some((variable.id, *value_id))
+            })
+            .collect::<Option<_>>();
+        if let Some(values) = values {
+            runs_by_values.entry(values).or_default().push(run);
+        }
+    }
+
+    runs_by_values
+        .into_iter()
+        .map(|(values, runs)| {
+            let ranked = leaderboard(
+                game,
+                runs.into_iter(),
+                rank_obsoletes,
+                &RunFilter::default(),
+            );
+            (values, ranked)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn game() -> Game {
+        serde_json::from_value(json!({
+            "id": 1,
+            "created": null,
+            "slug": "game",
+            "name": "Game",
+            "primary_timing": "RTA",
+        }))
+        .unwrap()
+    }
+
+    fn run(id: u64, user_id: u64, seconds: u64, values: &[(u64, u64)]) -> Run {
+        let mut run: Run = serde_json::from_value(json!({
+            "game_id": 1,
+            "category_id": 2,
+            "level_id": null,
+            "id": id,
+            "created": null,
+            "date": null,
+            "times_ms": {"igt": null, "rta": seconds * 1000, "rta_nl": null},
+            "players": [{"UserId": user_id}],
+            "videos": [],
+        }))
+        .unwrap();
+        run.values = values.iter().copied().collect();
+        run
+    }
+
+    fn variable(id: u64, default_value_id: Option<u64>) -> Variable {
+        serde_json::from_value(json!({
+            "game_id": 1,
+            "id": id,
+            "category_id": null,
+            "scope": "Global",
+            "slug": "variable",
+            "name": "Variable",
+            "is_subcategory": true,
+            "mandatory": true,
+            "default_value_id": default_value_id,
+            "values": [],
+        }))
+        .unwrap()
+    }
+
+    fn ids(leaderboard: &[LeaderboardRun]) -> Vec<u64> {
+        leaderboard.iter().map(|ranked| ranked.run.id).collect()
+    }
+
+    #[test]
+    fn test_run_filter() {
+        let mut run = run(1, 1, 60, &[(10, 100), (11, 110)]);
+        run.platform_id = Some(5);
+        run.region_id = None;
+        run.emulated = true;
+
+        assert!(RunFilter::default().matches(&run));
+
+        let values = |values: &[(u64, u64)]| RunFilter {
+            values: values.iter().copied().collect(),
+            ..RunFilter::default()
+        };
+        assert!(values(&[(10, 100)]).matches(&run));
+        assert!(values(&[(10, 100), (11, 110)]).matches(&run));
+        assert!(!values(&[(10, 101)]).matches(&run));
+        assert!(!values(&[(10, 100), (12, 120)]).matches(&run));
+
+        // a run without a value for a variable only matches its default value
+        let with_default = |value_id| RunFilter {
+            values: vec![(12, value_id)].into_iter().collect(),
+            defaults: vec![(12, 120)].into_iter().collect(),
+            ..RunFilter::default()
+        };
+        assert!(with_default(120).matches(&run));
+        assert!(!with_default(121).matches(&run));
+        assert!(!values(&[(12, 120)]).matches(&run));
+
+        let platform = |platform_id| RunFilter {
+            platform_id: Some(platform_id),
+            ..RunFilter::default()
+        };
+        assert!(platform(5).matches(&run));
+        assert!(!platform(6).matches(&run));
+
+        let region = RunFilter {
+            region_id: Some(3),
+            ..RunFilter::default()
+        };
+        assert!(!region.matches(&run));
+
+        let emulated = |emulated| RunFilter {
+            emulated: Some(emulated),
+            ..RunFilter::default()
+        };
+        assert!(emulated(true).matches(&run));
+        assert!(!emulated(false).matches(&run));
+
+        let everything = RunFilter {
+            values: vec![(11, 110)].into_iter().collect(),
+            defaults: vec![(11, 111)].into_iter().collect(),
+            platform_id: Some(5),
+            region_id: None,
+            emulated: Some(true),
+        };
+        assert!(everything.matches(&run));
+    }
+
+    #[test]
+    fn test_leaderboard_filter() {
+        let mut console = run(1, 1, 60, &[]);
+        console.platform_id = Some(5);
+        let mut emulator = run(2, 2, 50, &[]);
+        emulator.platform_id = Some(5);
+        emulator.emulated = true;
+        let mut other = run(3, 3, 40, &[]);
+        other.platform_id = Some(6);
+        let runs = [console, emulator, other];
+
+        let all = leaderboard(&game(), runs.iter(), false, &RunFilter::default());
+        assert_eq!(ids(&all), vec![3, 2, 1]);
+
+        let filter = RunFilter {
+            platform_id: Some(5),
+            emulated: Some(false),
+            ..RunFilter::default()
+        };
+        let filtered = leaderboard(&game(), runs.iter(), false, &filter);
+        assert_eq!(ids(&filtered), vec![1]);
+        assert_eq!(*filtered[0].rank(), 1);
+    }
+
+    #[test]
+    fn test_leaderboard_wrong_player_count() {
+        let mut wrong = run(1, 1, 50, &[(10, 100)]);
+        wrong.wrong_player_count = true;
+        let runs = [wrong, run(2, 2, 60, &[(10, 100)])];
+
+        // runs with a number of players their category doesn't allow aren't ranked
+        let ranked = leaderboard(&game(), runs.iter(), false, &RunFilter::default());
+        assert_eq!(ids(&ranked), vec![2]);
+        assert_eq!(*ranked[0].rank(), 1);
+
+        let boards = subcategory_leaderboards(
+            &game(),
+            &[&variable(10, None)],
+            runs.iter(),
+            false,
+            &RunFilter::default(),
+        );
+        assert_eq!(
+            ids(&boards[&vec![(10, 100)].into_iter().collect()]),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn test_subcategory_leaderboards() {
+        let (difficulty, version) = (variable(10, None), variable(11, Some(111)));
+        let runs = [
+            run(1, 1, 90, &[(10, 100), (11, 110)]),
+            run(2, 2, 80, &[(10, 100), (11, 110)]),
+            run(3, 3, 70, &[(10, 101), (11, 110)]),
+            // ranked with the version's default value
+            run(4, 4, 60, &[(10, 100)]),
+            // left out, since the difficulty has no default value
+            run(5, 5, 50, &[(11, 110)]),
+        ];
+
+        let boards = subcategory_leaderboards(
+            &game(),
+            &[&difficulty, &version],
+            runs.iter(),
+            false,
+            &RunFilter::default(),
+        );
+        let boards: Vec<_> = boards
+            .iter()
+            .map(|(values, ranked)| {
+                (values.clone().into_iter().collect::<Vec<_>>(), ids(ranked))
+            })
+            .collect();
+        assert_eq!(
+            boards,
+            vec![
+                (vec![(10, 100), (11, 110)], vec![2, 1]),
+                (vec![(10, 100), (11, 111)], vec![4]),
+                (vec![(10, 101), (11, 110)], vec![3]),
+            ]
+        );
+
+        // the filter applies before runs are split up
+        let filter = RunFilter {
+            values: vec![(10, 101)].into_iter().collect(),
+            ..RunFilter::default()
+        };
+        let boards =
+            subcategory_leaderboards(&game(), &[&difficulty], runs.iter(), false, &filter);
+        assert_eq!(boards.len(), 1);
+        assert_eq!(
+            ids(&boards[&vec![(10, 101)].into_iter().collect()]),
+            vec![3]
+        );
+
+        // filtering on a default value ranks the same runs as its board
+        let filter = RunFilter {
+            values: vec![(10, 100), (11, 111)].into_iter().collect(),
+            defaults: vec![(11, 111)].into_iter().collect(),
+            ..RunFilter::default()
+        };
+        let ranked = leaderboard(&game(), runs.iter(), false, &filter);
+        assert_eq!(ids(&ranked), vec![4]);
+    }
+}
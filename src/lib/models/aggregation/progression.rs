@@ -5,7 +5,7 @@ use itertools::Itertools;
 use serde::Serialize;
 
 use crate::{
-    aggregation::leaderboard::{leaderboard, LeaderboardRun},
+    aggregation::leaderboard::{leaderboard, LeaderboardRun, RunFilter},
     Game, Run,
 };
 
@@ -52,7 +52,9 @@ pub fn progression<'runs>(
         let mut best_ms: Option<u64> = None;
 
         let mut leaderboard_runs_by_id: HashMap<u64, LeaderboardRun> = HashMap::new();
-        for leaderboard_run in leaderboard(game, runs.iter().cloned(), false) {
+        for leaderboard_run in
+            leaderboard(game, runs.iter().cloned(), false, &RunFilter::default())
+        {
             let id = *leaderboard_run.run().id();
             leaderboard_runs_by_id.insert(id, leaderboard_run);
         }
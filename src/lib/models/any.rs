@@ -46,6 +46,8 @@ pub enum AnyModel {
     Game(Game),
     Category(Category),
     Level(Level),
+    Variable(Variable),
+    GameModerator(GameModerator),
 }
 
 /// A reference to a homogenous Vec of any Model type.
@@ -68,6 +70,7 @@ pub enum AnyModelVec {
     Games(Vec<Game>),
     Categories(Vec<Category>),
     Levels(Vec<Level>),
+    Variables(Vec<Variable>),
 }
 
 impl Model for AnyModel {
@@ -78,6 +81,8 @@ impl Model for AnyModel {
             AnyModel::Game(game) => Model::id(game),
             AnyModel::Category(category) => Model::id(category),
             AnyModel::Level(level) => Model::id(level),
+            AnyModel::Variable(variable) => Model::id(variable),
+            AnyModel::GameModerator(moderator) => Model::id(moderator),
         }
     }
 
@@ -88,6 +93,8 @@ impl Model for AnyModel {
             AnyModel::Game(game) => Model::created(game),
             AnyModel::Category(category) => Model::created(category),
             AnyModel::Level(level) => Model::created(level),
+            AnyModel::Variable(variable) => Model::created(variable),
+            AnyModel::GameModerator(moderator) => Model::created(moderator),
         }
     }
 }
@@ -141,3 +148,24 @@ impl Model for Level {
         None
     }
 }
+
+impl Model for Variable {
+    fn id(&self) -> u64 {
+        *Variable::id(self)
+    }
+
+    fn created(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+}
+
+/// Moderators don't have IDs of their own, so they're identified by their users.
+impl Model for GameModerator {
+    fn id(&self) -> u64 {
+        *GameModerator::user_id(self)
+    }
+
+    fn created(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+}
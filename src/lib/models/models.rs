@@ -1,6 +1,7 @@
 #![allow(missing_docs)]
 use std::{
     cmp::{Eq, Ord},
+    collections::BTreeMap,
     convert::From,
 };
 
@@ -15,6 +16,9 @@ use speedruns_utils::base36;
 
 pub mod aggregation;
 pub mod any;
+mod video;
+
+pub use video::RunVideo;
 
 // We currently represent all ids as u64s for efficiency.
 // You can use [crate::utils] to convert to and from speedrun.com's
@@ -53,6 +57,13 @@ pub struct Category {
     pub id: u64,
     pub per: CategoryType,
     pub rules: String,
+    /// How many players a run must have, if we know.
+    #[serde(default)]
+    pub players: Option<CategoryPlayers>,
+    /// Whether this is a miscellaneous category, not one of the game's main
+    /// leaderboards.
+    #[serde(default)]
+    pub miscellaneous: bool,
 }
 
 impl Category {
@@ -69,6 +80,24 @@ pub enum CategoryType {
     PerLevel,
 }
 
+/// How many players a category's runs must have.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Hash, PartialOrd, Ord, Eq)]
+#[serde(deny_unknown_fields)]
+pub enum CategoryPlayers {
+    Exactly(u32),
+    UpTo(u32),
+}
+
+impl CategoryPlayers {
+    /// Whether a run with this many players is allowed.
+    pub fn allows(&self, players: usize) -> bool {
+        match *self {
+            CategoryPlayers::Exactly(count) => players == count as usize,
+            CategoryPlayers::UpTo(count) => (1..=count as usize).contains(&players),
+        }
+    }
+}
+
 #[derive(
     Debug,
     Serialize,
@@ -91,6 +120,19 @@ pub struct User {
     #[validate(length(min = 1))]
     pub name: String,
     pub id: u64,
+    /// The code of the country the user says they're in, like "ca".
+    #[serde(default)]
+    pub country_code: Option<String>,
+    /// The code of the region of that country, like "ca/on".
+    #[serde(default)]
+    pub region_code: Option<String>,
+    #[serde(default)]
+    pub links: UserLinks,
+    /// How the user's name is coloured on speedrun.com.
+    #[serde(default)]
+    pub name_style: Option<UserNameStyle>,
+    #[serde(default)]
+    pub role: UserRole,
 }
 
 impl User {
@@ -100,6 +142,74 @@ impl User {
     }
 }
 
+/// A user's profiles on other sites.
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    Clone,
+    Default,
+    PartialEq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Eq,
+    Getters,
+)]
+#[serde(deny_unknown_fields)]
+#[get = "pub"]
+pub struct UserLinks {
+    pub twitch: Option<String>,
+    pub youtube: Option<String>,
+    pub twitter: Option<String>,
+    pub speedrunslive: Option<String>,
+    pub hitbox: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Hash, PartialOrd, Ord, Eq)]
+#[serde(deny_unknown_fields)]
+pub enum UserNameStyle {
+    Solid {
+        color: UserNameColor,
+    },
+    Gradient {
+        color_from: UserNameColor,
+        color_to: UserNameColor,
+    },
+}
+
+/// A colour for a user's name, as hex codes for light and dark backgrounds.
+#[derive(
+    Debug, Serialize, Deserialize, Clone, PartialEq, Hash, PartialOrd, Ord, Eq, Getters,
+)]
+#[serde(deny_unknown_fields)]
+#[get = "pub"]
+pub struct UserNameColor {
+    pub light: String,
+    pub dark: String,
+}
+
+/// What a user is allowed to do on speedrun.com.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Hash, PartialOrd, Ord, Eq)]
+#[serde(deny_unknown_fields)]
+pub enum UserRole {
+    Banned,
+    User,
+    Trusted,
+    Moderator,
+    ContentModerator,
+    Programmer,
+    Admin,
+}
+
+impl Default for UserRole {
+    /// An ordinary user, which is what we assume for users imported before we
+    /// kept their roles.
+    fn default() -> Self {
+        UserRole::User
+    }
+}
+
 #[derive(
     Debug,
     Serialize,
@@ -123,6 +233,30 @@ pub struct Game {
     #[validate(length(min = 1))]
     pub name: String,
     pub primary_timing: TimingMethod,
+    #[serde(default)]
+    pub release_date: Option<NaiveDate>,
+    /// The year the game was released.
+    #[serde(default)]
+    pub released: Option<u32>,
+    /// Whether this is a romhack of another game.
+    #[serde(default)]
+    pub romhack: bool,
+    #[serde(default)]
+    pub developer_ids: Vec<u64>,
+    #[serde(default)]
+    pub publisher_ids: Vec<u64>,
+    #[serde(default)]
+    pub genre_ids: Vec<u64>,
+    #[serde(default)]
+    pub engine_ids: Vec<u64>,
+    #[serde(default)]
+    pub gametype_ids: Vec<u64>,
+    #[serde(default)]
+    pub platform_ids: Vec<u64>,
+    /// The game's images on speedrun.com, by kind, like "logo" or
+    /// "cover-large".
+    #[serde(default)]
+    pub assets: BTreeMap<String, GameAsset>,
 }
 
 impl Game {
@@ -132,6 +266,82 @@ impl Game {
     }
 }
 
+/// An image of a game.
+#[derive(
+    Debug, Serialize, Deserialize, Clone, PartialEq, Hash, PartialOrd, Ord, Eq, Getters,
+)]
+#[serde(deny_unknown_fields)]
+#[get = "pub"]
+pub struct GameAsset {
+    pub uri: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+/// A developer, publisher, genre, engine or type of game, which games are
+/// tagged with. Each kind is kept in a table of its own.
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    Clone,
+    PartialEq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Eq,
+    Getters,
+    Validate,
+)]
+#[serde(deny_unknown_fields)]
+#[get = "pub"]
+pub struct GameTag {
+    pub id: u64,
+    #[validate(length(min = 1))]
+    pub slug: String,
+    #[validate(length(min = 1))]
+    pub name: String,
+}
+
+impl GameTag {
+    /// This item's ID as it would be formatted for speedrun.com.
+    pub fn src_id(&self) -> String {
+        base36(*self.id())
+    }
+}
+
+/// A user who moderates a game's leaderboards.
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    Clone,
+    PartialEq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Eq,
+    Getters,
+    Validate,
+)]
+#[serde(deny_unknown_fields)]
+#[get = "pub"]
+pub struct GameModerator {
+    pub game_id: u64,
+    pub user_id: u64,
+    pub level: ModeratorLevel,
+}
+
+/// What a moderator is allowed to do with a game.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Hash, PartialOrd, Ord, Eq)]
+#[serde(deny_unknown_fields)]
+pub enum ModeratorLevel {
+    /// Can verify and reject runs.
+    Moderator,
+    /// Can also change the game's settings, and its other moderators.
+    SuperModerator,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Hash, PartialOrd, Ord, Eq)]
 #[serde(deny_unknown_fields)]
 #[allow(non_camel_case_types)]
@@ -173,6 +383,176 @@ impl Level {
     }
 }
 
+/// A console or other system that games are played on.
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    Clone,
+    PartialEq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Eq,
+    Getters,
+    Validate,
+)]
+#[serde(deny_unknown_fields)]
+#[get = "pub"]
+pub struct Platform {
+    pub id: u64,
+    #[validate(length(min = 1))]
+    pub slug: String,
+    #[validate(length(min = 1))]
+    pub name: String,
+    /// The year the platform was released.
+    pub released: u32,
+}
+
+impl Platform {
+    /// This item's ID as it would be formatted for speedrun.com.
+    pub fn src_id(&self) -> String {
+        base36(*self.id())
+    }
+}
+
+/// A region that a game's releases are sold in, such as "USA / NTSC".
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    Clone,
+    PartialEq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Eq,
+    Getters,
+    Validate,
+)]
+#[serde(deny_unknown_fields)]
+#[get = "pub"]
+pub struct Region {
+    pub id: u64,
+    #[validate(length(min = 1))]
+    pub slug: String,
+    #[validate(length(min = 1))]
+    pub name: String,
+}
+
+impl Region {
+    /// This item's ID as it would be formatted for speedrun.com.
+    pub fn src_id(&self) -> String {
+        base36(*self.id())
+    }
+}
+
+/// A detail of a game's runs, such as the version they were played on, that
+/// runs have one of a fixed set of values for. A subcategory variable splits
+/// its categories' leaderboards by value; any other variable is just shown.
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    Clone,
+    PartialEq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Eq,
+    Getters,
+    Validate,
+)]
+#[serde(deny_unknown_fields)]
+#[get = "pub"]
+pub struct Variable {
+    pub game_id: u64,
+    pub id: u64,
+    /// The only category the variable applies to, if not all of them.
+    pub category_id: Option<u64>,
+    pub scope: VariableScope,
+    #[validate(length(min = 1))]
+    pub slug: String,
+    #[validate(length(min = 1))]
+    pub name: String,
+    pub is_subcategory: bool,
+    pub mandatory: bool,
+    pub default_value_id: Option<u64>,
+    #[validate]
+    pub values: Vec<VariableValue>,
+}
+
+impl Variable {
+    /// This item's ID as it would be formatted for speedrun.com.
+    pub fn src_id(&self) -> String {
+        base36(*self.id())
+    }
+
+    /// The value of this variable with the given ID.
+    pub fn value(&self, id: u64) -> Option<&VariableValue> {
+        self.values.iter().find(|value| value.id == id)
+    }
+
+    /// Whether this variable applies to runs of the given category and level.
+    pub fn applies_to(&self, category_id: u64, level_id: Option<u64>) -> bool {
+        if let Some(variable_category_id) = self.category_id {
+            if variable_category_id != category_id {
+                return false;
+            }
+        }
+        match (&self.scope, level_id) {
+            (VariableScope::Global, _) => true,
+            (VariableScope::FullGame, None) => true,
+            (VariableScope::AllLevels, Some(_)) => true,
+            (VariableScope::SingleLevel { level_id }, Some(run_level_id)) => {
+                *level_id == run_level_id
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Which of a game's runs a variable applies to.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Hash, PartialOrd, Ord, Eq)]
+#[serde(deny_unknown_fields)]
+pub enum VariableScope {
+    Global,
+    FullGame,
+    AllLevels,
+    SingleLevel { level_id: u64 },
+}
+
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    Clone,
+    PartialEq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Eq,
+    Getters,
+    Validate,
+)]
+#[serde(deny_unknown_fields)]
+#[get = "pub"]
+pub struct VariableValue {
+    pub id: u64,
+    #[validate(length(min = 1))]
+    pub slug: String,
+    #[validate(length(min = 1))]
+    pub label: String,
+    pub rules: String,
+}
+
+impl VariableValue {
+    /// This item's ID as it would be formatted for speedrun.com.
+    pub fn src_id(&self) -> String {
+        base36(*self.id())
+    }
+}
+
 #[derive(
     Debug,
     Serialize,
@@ -199,6 +579,32 @@ pub struct Run {
     #[validate]
     pub players: Vec<RunPlayer>,
     pub videos: Vec<RunVideo>,
+    #[serde(default)]
+    pub status: RunStatus,
+    /// The ID of the value the run has for each of its game's variables, by
+    /// variable ID.
+    #[serde(default)]
+    pub values: BTreeMap<u64, u64>,
+    #[serde(default)]
+    pub platform_id: Option<u64>,
+    #[serde(default)]
+    pub region_id: Option<u64>,
+    /// Whether the run was played on an emulator, rather than the platform
+    /// itself.
+    #[serde(default)]
+    pub emulated: bool,
+    /// The runner's comment on the run.
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub splits: Option<RunSplits>,
+    /// The run's page on speedrun.com.
+    #[serde(default)]
+    pub weblink: Option<String>,
+    /// Whether the run has a number of players its category doesn't allow,
+    /// which keeps it off leaderboards.
+    #[serde(default)]
+    pub wrong_player_count: bool,
 }
 
 impl Run {
@@ -206,33 +612,98 @@ impl Run {
     pub fn src_id(&self) -> String {
         base36(*self.id())
     }
+
+    /// Whether the run has been verified by a moderator. Only verified runs
+    /// count towards leaderboards unless a query asks for others.
+    pub fn is_verified(&self) -> bool {
+        matches!(self.status, RunStatus::Verified { .. })
+    }
 }
 
+/// Where a run is in the verification queue.
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone, PartialOrd, Eq, Ord, Hash)]
 #[serde(deny_unknown_fields)]
-pub enum RunVideo {
-    YouTube { id: String, start: Option<i32> },
-    Link { url: String },
+pub enum RunStatus {
+    /// Submitted, but not yet examined.
+    New,
+    Verified {
+        examiner_id: Option<u64>,
+        verify_date: Option<DateTime<Utc>>,
+    },
+    Rejected {
+        examiner_id: Option<u64>,
+        reason: Option<String>,
+    },
+}
+
+impl Default for RunStatus {
+    /// Verified by an unknown examiner at an unknown time, which is what we
+    /// assume for runs imported before we kept unverified runs.
+    fn default() -> Self {
+        RunStatus::Verified {
+            examiner_id: None,
+            verify_date: None,
+        }
+    }
+}
+
+impl RunStatus {
+    /// The ID of the user who verified or rejected the run, if known.
+    pub fn examiner_id(&self) -> Option<u64> {
+        match self {
+            RunStatus::New => None,
+            RunStatus::Verified { examiner_id, .. }
+            | RunStatus::Rejected { examiner_id, .. } => *examiner_id,
+        }
+    }
 }
 
-impl std::fmt::Display for RunVideo {
+/// Where a run's splits were uploaded.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, PartialOrd, Eq, Ord, Hash)]
+#[serde(deny_unknown_fields)]
+pub enum RunSplits {
+    /// A run on splits.io, by its ID there.
+    SplitsIo {
+        id: String,
+    },
+    Link {
+        url: String,
+    },
+}
+
+impl std::fmt::Display for RunSplits {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        use RunVideo::*;
+        use RunSplits::*;
         match self {
-            YouTube { id, start } => {
-                write!(f, "https://youtu.be/{}?t={}", id, start.unwrap_or(0))
-            }
+            SplitsIo { id } => write!(f, "https://splits.io/{}", id),
             Link { url } => write!(f, "{}", url),
         }
     }
 }
 
-impl std::str::FromStr for RunVideo {
+impl std::str::FromStr for RunSplits {
     type Err = std::convert::Infallible;
 
+    /// Parses a link to a run's splits, recognizing both splits.io's pages
+    /// (`https://splits.io/1bx`) and the API links speedrun.com gives
+    /// (`https://splits.io/api/v4/runs/1bx`).
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // TODO: parse all youtube URL variants
-        Ok(RunVideo::Link { url: s.to_string() })
+        let path = s
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_start_matches("www.")
+            .strip_prefix("splits.io/");
+        if let Some(path) = path {
+            let id = path
+                .trim_start_matches("api/v4/runs/")
+                .split(&['/', '?', '#'][..])
+                .next()
+                .unwrap_or_default();
+            if !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Ok(RunSplits::SplitsIo { id: id.to_string() });
+            }
+        }
+        Ok(RunSplits::Link { url: s.to_string() })
     }
 }
 
@@ -287,3 +758,59 @@ impl Validate for RunPlayer {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_category_players_allows() {
+        let exactly = CategoryPlayers::Exactly(2);
+        assert!(!exactly.allows(0));
+        assert!(!exactly.allows(1));
+        assert!(exactly.allows(2));
+        assert!(!exactly.allows(3));
+
+        let up_to = CategoryPlayers::UpTo(2);
+        assert!(!up_to.allows(0));
+        assert!(up_to.allows(1));
+        assert!(up_to.allows(2));
+        assert!(!up_to.allows(3));
+    }
+
+    #[test]
+    fn test_run_splits() {
+        let splits_io = RunSplits::SplitsIo {
+            id: "1bx".to_string(),
+        };
+        for url in &[
+            "https://splits.io/1bx",
+            "http://splits.io/1bx",
+            "https://www.splits.io/1bx",
+            "https://splits.io/api/v4/runs/1bx",
+            "https://splits.io/1bx?",
+            "https://splits.io/1bx#",
+            "https://splits.io/1bx?timing=real",
+            "https://splits.io/1bx/",
+        ] {
+            assert_eq!(url.parse::<RunSplits>().unwrap(), splits_io, "{}", url);
+        }
+        assert_eq!(splits_io.to_string(), "https://splits.io/1bx");
+
+        for url in &[
+            "https://example.com/splits/1bx",
+            "https://splits.io/",
+            "https://splits.io/api/v4/runs/",
+            "not a url",
+        ] {
+            assert_eq!(
+                url.parse::<RunSplits>().unwrap(),
+                RunSplits::Link {
+                    url: url.to_string()
+                },
+                "{}",
+                url
+            );
+        }
+    }
+}
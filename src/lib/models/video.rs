@@ -0,0 +1,420 @@
+//! Recognizing the video links that runs are submitted with.
+use std::fmt::{self, Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+/// A video of a run, by its ID if it's on a site we recognize, or by its URL
+/// otherwise. Start offsets are in seconds, and Bilibili videos may be split
+/// into parts, numbered from 1.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, PartialOrd, Eq, Ord, Hash)]
+#[serde(deny_unknown_fields)]
+pub enum RunVideo {
+    YouTube {
+        id: String,
+        start: Option<i32>,
+    },
+    TwitchVod {
+        id: String,
+        start: Option<i32>,
+    },
+    TwitchClip {
+        slug: String,
+    },
+    Bilibili {
+        id: String,
+        #[serde(default)]
+        part: Option<u32>,
+        start: Option<i32>,
+    },
+    NicoNico {
+        id: String,
+        start: Option<i32>,
+    },
+    Link {
+        url: String,
+    },
+}
+
+impl RunVideo {
+    /// The offset into the video that the run starts at, in seconds, if any.
+    pub fn start(&self) -> Option<i32> {
+        use RunVideo::*;
+        match self {
+            YouTube { start, .. }
+            | TwitchVod { start, .. }
+            | Bilibili { start, .. }
+            | NicoNico { start, .. } => *start,
+            TwitchClip { .. } | Link { .. } => None,
+        }
+    }
+}
+
+impl Display for RunVideo {
+    /// Writes the canonical URL of the video.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use RunVideo::*;
+        match self {
+            YouTube { id, start } => {
+                write!(f, "https://youtu.be/{}", id)?;
+                if let Some(start) = start {
+                    write!(f, "?t={}", start)?;
+                }
+            }
+            TwitchVod { id, start } => {
+                write!(f, "https://www.twitch.tv/videos/{}", id)?;
+                if let Some(start) = start {
+                    write!(f, "?t={}h{}m{}s", start / 3600, start / 60 % 60, start % 60)?;
+                }
+            }
+            TwitchClip { slug } => write!(f, "https://clips.twitch.tv/{}", slug)?,
+            Bilibili { id, part, start } => {
+                write!(f, "https://www.bilibili.com/video/{}", id)?;
+                let mut separator = '?';
+                if let Some(part) = part {
+                    write!(f, "{}p={}", separator, part)?;
+                    separator = '&';
+                }
+                if let Some(start) = start {
+                    write!(f, "{}t={}", separator, start)?;
+                }
+            }
+            NicoNico { id, start } => {
+                write!(f, "https://www.nicovideo.jp/watch/{}", id)?;
+                if let Some(start) = start {
+                    write!(f, "?from={}", start)?;
+                }
+            }
+            Link { url } => write!(f, "{}", url)?,
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for RunVideo {
+    type Err = std::convert::Infallible;
+
+    /// Parses a video URL, falling back to a [RunVideo::Link] for anything we
+    /// don't recognize.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Url::parse(s.trim())
+            .and_then(|url| url.video())
+            .unwrap_or_else(|| RunVideo::Link { url: s.to_string() }))
+    }
+}
+
+/// The parts of a URL that we need to recognize videos.
+struct Url<'a> {
+    /// The host, lowercased, without any `www.` or `m.` prefix.
+    host: String,
+    /// The non-empty segments of the path.
+    path: Vec<&'a str>,
+    query: Vec<(&'a str, &'a str)>,
+    fragment: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Url<'a> {
+    fn parse(s: &'a str) -> Option<Url<'a>> {
+        let s = s
+            .strip_prefix("https://")
+            .or_else(|| s.strip_prefix("http://"))
+            .or_else(|| s.strip_prefix("//"))
+            .unwrap_or(s);
+        let (s, fragment) = s.split_once('#').unwrap_or((s, ""));
+        let (s, query) = s.split_once('?').unwrap_or((s, ""));
+        let (host, path) = s.split_once('/').unwrap_or((s, ""));
+
+        let host = host.to_lowercase();
+        let host = host
+            .strip_prefix("www.")
+            .or_else(|| host.strip_prefix("m."))
+            .unwrap_or(&host)
+            .to_string();
+        if host.is_empty() || !host.contains('.') {
+            return None;
+        }
+
+        Some(Url {
+            host,
+            path: path
+                .split('/')
+                .filter(|segment| !segment.is_empty())
+                .collect(),
+            query: parameters(query),
+            fragment: parameters(fragment),
+        })
+    }
+
+    /// The value of a query or fragment parameter.
+    fn parameter(&self, name: &str) -> Option<&'a str> {
+        self.query
+            .iter()
+            .chain(self.fragment.iter())
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| *value)
+    }
+
+    /// The start offset given by the first of these parameters that's set.
+    fn start(&self, names: &[&str]) -> Option<i32> {
+        names
+            .iter()
+            .find_map(|name| self.parameter(name))
+            .and_then(parse_offset)
+    }
+
+    fn video(&self) -> Option<RunVideo> {
+        let path = self.path.as_slice();
+        match self.host.as_str() {
+            "youtu.be" => {
+                let id = valid_id(path.first()?)?;
+                Some(self.youtube(id))
+            }
+            "youtube.com" | "music.youtube.com" | "youtube-nocookie.com" => {
+                let id = match path {
+                    ["watch"] => self.parameter("v")?,
+                    ["shorts", id] | ["embed", id] | ["v", id] | ["live", id] => id,
+                    _ => return None,
+                };
+                Some(self.youtube(valid_id(id)?))
+            }
+            "twitch.tv" | "go.twitch.tv" | "player.twitch.tv" | "clips.twitch.tv" => {
+                self.twitch()
+            }
+            "bilibili.com" => match path {
+                ["video", id] if id.starts_with("BV") || id.starts_with("av") => {
+                    Some(RunVideo::Bilibili {
+                        id: valid_id(id)?.to_string(),
+                        part: self.parameter("p").and_then(|part| part.parse().ok()),
+                        start: self.start(&["t"]),
+                    })
+                }
+                _ => None,
+            },
+            "nicovideo.jp" | "sp.nicovideo.jp" | "nico.ms" => {
+                let id = match path {
+                    ["watch", id] => id,
+                    [id] if self.host == "nico.ms" => id,
+                    _ => return None,
+                };
+                Some(RunVideo::NicoNico {
+                    id: valid_id(id)?.to_string(),
+                    start: self.start(&["from"]),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn youtube(&self, id: &str) -> RunVideo {
+        RunVideo::YouTube {
+            id: id.to_string(),
+            start: self.start(&["t", "start"]),
+        }
+    }
+
+    fn twitch(&self) -> Option<RunVideo> {
+        let path = self.path.as_slice();
+        let vod = |id: &str| {
+            let id = id.trim_start_matches('v');
+            if !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()) {
+                Some(RunVideo::TwitchVod {
+                    id: id.to_string(),
+                    start: self.start(&["t"]),
+                })
+            } else {
+                None
+            }
+        };
+        let clip = |slug: &str| {
+            Some(RunVideo::TwitchClip {
+                slug: valid_id(slug)?.to_string(),
+            })
+        };
+
+        if self.host == "clips.twitch.tv" {
+            return match path {
+                ["embed"] => clip(self.parameter("clip")?),
+                [slug] => clip(slug),
+                _ => None,
+            };
+        }
+        if let Some(id) = self.parameter("video") {
+            return vod(id);
+        }
+        if let Some(slug) = self.parameter("clip") {
+            return clip(slug);
+        }
+        match path {
+            ["videos", id] | [_, "v", id] | [_, "video", id] | [_, "videos", id] => vod(id),
+            [_, "clip", slug] => clip(slug),
+            _ => None,
+        }
+    }
+}
+
+fn parameters(s: &str) -> Vec<(&str, &str)> {
+    s.split('&')
+        .filter(|parameter| !parameter.is_empty())
+        .map(|parameter| parameter.split_once('=').unwrap_or((parameter, "")))
+        .collect()
+}
+
+/// The ID if it looks like one, rather than something else in its place.
+fn valid_id(id: &str) -> Option<&str> {
+    if !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Some(id)
+    } else {
+        None
+    }
+}
+
+/// Parses an offset into a video, as seconds (`3723` or `3723s`) or hours,
+/// minutes and seconds (`1h2m3s`).
+fn parse_offset(s: &str) -> Option<i32> {
+    if s.is_empty() {
+        return None;
+    }
+    let mut total: i32 = 0;
+    let mut number: Option<i32> = None;
+    for c in s.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            number = Some(
+                number
+                    .unwrap_or(0)
+                    .checked_mul(10)?
+                    .checked_add(digit as i32)?,
+            );
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        total = total.checked_add(number.take()?.checked_mul(unit)?)?;
+    }
+    if let Some(number) = number {
+        total = total.checked_add(number)?;
+    }
+    Some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(url: &str) -> RunVideo {
+        url.parse().unwrap()
+    }
+
+    #[test]
+    fn test_youtube() {
+        let video = |start| RunVideo::YouTube {
+            id: "dQw4w9WgXcQ".to_string(),
+            start,
+        };
+        for url in &[
+            "https://youtu.be/dQw4w9WgXcQ",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            "http://m.youtube.com/watch?feature=share&v=dQw4w9WgXcQ",
+            "youtube.com/shorts/dQw4w9WgXcQ",
+            "https://www.youtube.com/embed/dQw4w9WgXcQ",
+            "https://www.youtube.com/live/dQw4w9WgXcQ?feature=share",
+        ] {
+            assert_eq!(parse(url), video(None), "{}", url);
+        }
+        for url in &[
+            "https://youtu.be/dQw4w9WgXcQ?t=3723",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=1h2m3s",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ#t=62m3s",
+            "https://www.youtube.com/embed/dQw4w9WgXcQ?start=3723",
+        ] {
+            assert_eq!(parse(url), video(Some(3723)), "{}", url);
+        }
+        assert_eq!(
+            video(Some(3723)).to_string(),
+            "https://youtu.be/dQw4w9WgXcQ?t=3723"
+        );
+    }
+
+    #[test]
+    fn test_other_sites() {
+        let vod = RunVideo::TwitchVod {
+            id: "123456789".to_string(),
+            start: Some(3723),
+        };
+        for url in &[
+            "https://www.twitch.tv/videos/123456789?t=01h02m03s",
+            "https://twitch.tv/runner/v/123456789?t=1h2m3s",
+            "https://player.twitch.tv/?video=v123456789&t=1h2m3s",
+        ] {
+            assert_eq!(parse(url), vod, "{}", url);
+        }
+        assert_eq!(
+            vod.to_string(),
+            "https://www.twitch.tv/videos/123456789?t=1h2m3s"
+        );
+
+        let clip = RunVideo::TwitchClip {
+            slug: "FunnyClipName".to_string(),
+        };
+        assert_eq!(parse("https://clips.twitch.tv/FunnyClipName"), clip);
+        assert_eq!(
+            parse("https://www.twitch.tv/runner/clip/FunnyClipName"),
+            clip
+        );
+
+        let bilibili = |part, start| RunVideo::Bilibili {
+            id: "BV1xx411c7mD".to_string(),
+            part,
+            start,
+        };
+        assert_eq!(
+            parse("https://www.bilibili.com/video/BV1xx411c7mD?p=2&t=90"),
+            bilibili(Some(2), Some(90))
+        );
+        assert_eq!(
+            bilibili(Some(2), Some(90)).to_string(),
+            "https://www.bilibili.com/video/BV1xx411c7mD?p=2&t=90"
+        );
+        assert_eq!(
+            bilibili(Some(2), None).to_string(),
+            "https://www.bilibili.com/video/BV1xx411c7mD?p=2"
+        );
+        assert_eq!(
+            bilibili(None, Some(90)).to_string(),
+            "https://www.bilibili.com/video/BV1xx411c7mD?t=90"
+        );
+        assert_eq!(
+            parse("https://nico.ms/sm9"),
+            RunVideo::NicoNico {
+                id: "sm9".to_string(),
+                start: None,
+            }
+        );
+        assert_eq!(
+            parse("https://www.nicovideo.jp/watch/sm9?from=90").to_string(),
+            "https://www.nicovideo.jp/watch/sm9?from=90"
+        );
+
+        for url in &[
+            "https://www.twitch.tv/runner",
+            "https://www.youtube.com/channel/UC123",
+            "https://example.com/watch?v=dQw4w9WgXcQ",
+            "not a url",
+        ] {
+            assert_eq!(
+                parse(url),
+                RunVideo::Link {
+                    url: url.to_string()
+                },
+                "{}",
+                url
+            );
+        }
+    }
+}
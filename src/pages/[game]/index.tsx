@@ -121,7 +121,12 @@ const GameRun = gql`
     id
     srcId
     timeMs
-    videos
+    videos {
+      kind
+      url
+      videoId
+      start
+    }
     category {
       id
       srcId
@@ -157,7 +162,12 @@ const GameLeaderboardRun = gql`
       id
       srcId
       timeMs
-      videos
+      videos {
+        kind
+        url
+        videoId
+        start
+      }
       category {
         id
         srcId
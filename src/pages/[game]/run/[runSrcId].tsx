@@ -75,12 +75,15 @@ const RunPage: NextPage = () => {
       </p>
 
       {run.videos
-        .filter(video => /youtu/.test(video))
+        .filter(video => video.kind === schema.RunVideoKind.YOUTUBE)
         .map(video => (
-          <div key={video}>
+          <div key={video.url}>
             <YouTube
-              videoId={video.split(/=|\.be\//)[1].split(/[?&]/)[0]}
-              opts={{ width: "100%" }}
+              videoId={video.videoId ?? undefined}
+              opts={{
+                width: "100%",
+                playerVars: { start: video.start ?? undefined },
+              }}
             />
           </div>
         ))}
@@ -104,7 +107,12 @@ const GetRunPage = gql`
       id
       srcId
       timeMs
-      videos
+      videos {
+        kind
+        url
+        videoId
+        start
+      }
       category {
         id
         srcId